@@ -0,0 +1,336 @@
+use rust_decimal::Decimal;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::str::FromStr;
+
+/// Unit a bitcoin quantity is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denomination {
+    Btc,
+    MilliBtc,
+    /// One millionth of a bitcoin (100 sats).
+    Bits,
+    Sat,
+}
+
+impl Denomination {
+    /// Decimal places below this unit down to one satoshi.
+    fn precision(self) -> u32 {
+        match self {
+            Denomination::Btc => 8,
+            Denomination::MilliBtc => 5,
+            Denomination::Bits => 2,
+            Denomination::Sat => 0,
+        }
+    }
+}
+
+impl fmt::Display for Denomination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Denomination::Btc => "BTC",
+            Denomination::MilliBtc => "mBTC",
+            Denomination::Bits => "bits",
+            Denomination::Sat => "sat",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "btc" => Ok(Denomination::Btc),
+            "mbtc" => Ok(Denomination::MilliBtc),
+            "bits" | "bit" => Ok(Denomination::Bits),
+            "sat" | "sats" | "satoshi" | "satoshis" => Ok(Denomination::Sat),
+            _ => Err(AmountError::UnknownDenomination(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    Invalid(String),
+    Negative,
+    /// Finer than one satoshi.
+    TooPrecise,
+    TooLarge,
+    UnknownDenomination(String),
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Invalid(value) => write!(f, "'{}' is not an amount", value),
+            AmountError::Negative => write!(f, "amount is negative"),
+            AmountError::TooPrecise => write!(f, "amount has a fraction of a satoshi"),
+            AmountError::TooLarge => write!(f, "amount is too large"),
+            AmountError::UnknownDenomination(unit) => write!(f, "unknown denomination '{}'", unit),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// A non-negative bitcoin quantity, held as a whole number of satoshis.
+///
+/// Parses from and formats to BTC by default; other units go through
+/// [`Denomination`]. `+` and `-` panic on overflow or underflow; use the `checked_`
+/// forms for values that are not already known to be in range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const ONE_SAT: Amount = Amount(1);
+    pub const ONE_BTC: Amount = Amount(100_000_000);
+    /// Total bitcoin supply; no valid amount exceeds it.
+    pub const MAX_MONEY: Amount = Amount(21_000_000 * 100_000_000);
+
+    pub const fn from_sat(sats: u64) -> Amount {
+        Amount(sats)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn from_btc(btc: Decimal) -> Result<Amount, AmountError> {
+        Self::from_decimal_in(btc, Denomination::Btc)
+    }
+
+    /// The amount in BTC, for multiplying by a fiat rate.
+    pub fn to_btc(self) -> Decimal {
+        self.to_decimal_in(Denomination::Btc)
+    }
+
+    pub fn from_decimal_in(value: Decimal, denomination: Denomination) -> Result<Amount, AmountError> {
+        if value.is_sign_negative() && !value.is_zero() {
+            return Err(AmountError::Negative);
+        }
+        let sats = value
+            .checked_mul(Decimal::from(10u64.pow(denomination.precision())))
+            .ok_or(AmountError::TooLarge)?;
+        if !sats.fract().is_zero() {
+            return Err(AmountError::TooPrecise);
+        }
+        u64::try_from(sats).map(Amount).map_err(|_| AmountError::TooLarge)
+    }
+
+    pub fn to_decimal_in(self, denomination: Denomination) -> Decimal {
+        Decimal::from_i128_with_scale(i128::from(self.0), denomination.precision()).normalize()
+    }
+
+    /// Parses a bare number in `denomination`.
+    pub fn from_str_in(s: &str, denomination: Denomination) -> Result<Amount, AmountError> {
+        let value = Decimal::from_str(s.trim()).map_err(|_| AmountError::Invalid(s.to_string()))?;
+        Self::from_decimal_in(value, denomination)
+    }
+
+    /// Formats as a bare number in `denomination`, without trailing zeros.
+    pub fn to_string_in(self, denomination: Denomination) -> String {
+        self.to_decimal_in(denomination).to_string()
+    }
+
+    /// Formats as a number followed by the unit, e.g. `1500 sat`.
+    pub fn to_string_with_denomination(self, denomination: Denomination) -> String {
+        format!("{} {}", self.to_string_in(denomination), denomination)
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Sums `amounts`, or `None` on overflow.
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, Amount::checked_add)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_in(Denomination::Btc))
+    }
+}
+
+/// Accepts a bare BTC number or a number followed by a unit: `0.5`, `0.5 BTC`,
+/// `500 mBTC`, `500000 bits`, `50000000 sat`.
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let denomination = if unit.is_empty() { Denomination::Btc } else { unit.parse()? };
+        Self::from_str_in(number, denomination)
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        self.checked_add(other).expect("amount addition overflowed")
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        self.checked_sub(other).expect("amount subtraction underflowed")
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, other: Amount) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Amount> for Amount {
+    fn sum<I: Iterator<Item = &'a Amount>>(iter: I) -> Amount {
+        iter.copied().sum()
+    }
+}
+
+/// Serialized as a BTC string so that no reader sees it through a float.
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserialized from a string in any [`FromStr`] form, or from a JSON number in BTC.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+
+        impl Visitor<'_> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a BTC number or an amount string")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Amount, E> {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Amount, E> {
+                Amount::from_btc(Decimal::from(value)).map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Amount, E> {
+                Amount::from_btc(Decimal::from(value)).map_err(E::custom)
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Amount, E> {
+                // The shortest round-trip text of a JSON number is the decimal it was written as
+                let text = value.to_string();
+                let btc = Decimal::from_str(&text)
+                    .or_else(|_| Decimal::from_scientific(&text))
+                    .map_err(|_| E::custom(AmountError::Invalid(text)))?;
+                Amount::from_btc(btc).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn parses_every_denomination() {
+        let half = Amount::from_sat(50_000_000);
+        for text in ["0.5", " 0.5 BTC ", "500 mBTC", "500000 bits", "50000000 sat", "50000000sats"] {
+            assert_eq!(text.parse::<Amount>(), Ok(half), "{}", text);
+        }
+        assert_eq!("0.00000001".parse::<Amount>(), Ok(Amount::ONE_SAT));
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_whole_number_of_satoshis() {
+        assert_eq!("0.000000001".parse::<Amount>(), Err(AmountError::TooPrecise));
+        assert_eq!("1.5 sat".parse::<Amount>(), Err(AmountError::TooPrecise));
+        assert_eq!("-1".parse::<Amount>(), Err(AmountError::Negative));
+        assert_eq!("-0".parse::<Amount>(), Ok(Amount::ZERO));
+        assert_eq!("200000000000".parse::<Amount>(), Err(AmountError::TooLarge));
+        assert_eq!("1 doge".parse::<Amount>(), Err(AmountError::UnknownDenomination("doge".to_string())));
+        assert_eq!("one".parse::<Amount>(), Err(AmountError::UnknownDenomination("one".to_string())));
+        assert_eq!("1.2.3".parse::<Amount>(), Err(AmountError::Invalid("1.2.3".to_string())));
+    }
+
+    #[test]
+    fn formats_without_trailing_zeros() {
+        let amount = Amount::from_sat(150_000);
+        assert_eq!(amount.to_string(), "0.0015");
+        assert_eq!(amount.to_string_in(Denomination::MilliBtc), "1.5");
+        assert_eq!(amount.to_string_with_denomination(Denomination::Bits), "1500 bits");
+        assert_eq!(amount.to_string_with_denomination(Denomination::Sat), "150000 sat");
+        assert_eq!(Amount::ONE_BTC.to_string(), "1");
+        assert_eq!(Amount::ZERO.to_string(), "0");
+        assert_eq!(Amount::ONE_BTC.to_btc(), dec!(1));
+    }
+
+    #[test]
+    fn arithmetic_is_checked() {
+        let max = Amount::from_sat(u64::MAX);
+        assert_eq!(max.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::ONE_SAT), None);
+        assert_eq!(Amount::checked_sum([max, Amount::ONE_SAT]), None);
+        assert_eq!(Amount::checked_sum([Amount::ONE_BTC, Amount::ONE_SAT]), Some(Amount::from_sat(100_000_001)));
+        let total: Amount = [Amount::ONE_BTC, Amount::ONE_BTC].iter().sum();
+        assert_eq!(total - Amount::ONE_BTC, Amount::ONE_BTC);
+    }
+
+    #[test]
+    #[should_panic(expected = "amount subtraction underflowed")]
+    fn unchecked_underflow_panics() {
+        let _ = Amount::ZERO - Amount::ONE_SAT;
+    }
+
+    #[test]
+    fn serializes_as_a_btc_string_and_reads_strings_or_numbers() {
+        let amount = Amount::from_sat(12_345_678);
+        assert_eq!(serde_json::to_string(&amount).unwrap(), "\"0.12345678\"");
+        for json in ["\"0.12345678\"", "0.12345678", "\"12345678 sat\"", "1.2345678e-1"] {
+            assert_eq!(serde_json::from_str::<Amount>(json).unwrap(), amount, "{}", json);
+        }
+        assert_eq!(serde_json::from_str::<Amount>("2").unwrap(), Amount::from_sat(200_000_000));
+        assert!(serde_json::from_str::<Amount>("-2").is_err());
+        assert!(serde_json::from_str::<Amount>("0.000000001").is_err());
+        assert!(serde_json::from_str::<Amount>("true").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use chrono::{DateTime, Datelike, FixedOffset, Months, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::period::FiscalPeriod;
+
+/// Month and day on which each fiscal year begins, e.g. `07-01`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FiscalYearStart {
+    month: u32,
+    day: u32,
+}
+
+impl FiscalYearStart {
+    pub const JANUARY_1: FiscalYearStart = FiscalYearStart { month: 1, day: 1 };
+
+    /// `None` unless the day exists in every year, so February 29 is rejected.
+    pub fn new(month: u32, day: u32) -> Option<Self> {
+        NaiveDate::from_ymd_opt(2023, month, day).map(|_| FiscalYearStart { month, day })
+    }
+
+    pub fn month(&self) -> u32 {
+        self.month
+    }
+
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+}
+
+impl Default for FiscalYearStart {
+    fn default() -> Self {
+        FiscalYearStart::JANUARY_1
+    }
+}
+
+impl fmt::Display for FiscalYearStart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}-{:02}", self.month, self.day)
+    }
+}
+
+impl FromStr for FiscalYearStart {
+    type Err = String;
+
+    /// Parses `MM-DD`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a MM-DD fiscal year start", s);
+        let (month, day) = s.split_once('-').ok_or_else(invalid)?;
+        let (month, day) = (month.parse().map_err(|_| invalid())?, day.parse().map_err(|_| invalid())?);
+        FiscalYearStart::new(month, day).ok_or_else(invalid)
+    }
+}
+
+/// A period named in the entity's reporting calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportingPeriod {
+    /// A calendar month.
+    Month { year: i32, month: u32 },
+    /// A quarter (1 to 4) of a fiscal year, counted from the fiscal year start.
+    Quarter { fiscal_year: i32, quarter: u32 },
+    /// A fiscal year, named for the calendar year it ends in.
+    FiscalYear(i32),
+    /// From the start of the fiscal year containing the date through the end of that date.
+    YearToDate(NaiveDate),
+}
+
+impl ReportingPeriod {
+    /// The period of the same kind just before this one; for year to date, the same
+    /// span of the previous fiscal year.
+    pub fn previous(self) -> ReportingPeriod {
+        match self {
+            ReportingPeriod::Month { year, month: 1 } => ReportingPeriod::Month { year: year - 1, month: 12 },
+            ReportingPeriod::Month { year, month } => ReportingPeriod::Month { year, month: month - 1 },
+            ReportingPeriod::Quarter { fiscal_year, quarter: 1 } => ReportingPeriod::Quarter {
+                fiscal_year: fiscal_year - 1,
+                quarter: 4,
+            },
+            ReportingPeriod::Quarter { fiscal_year, quarter } => ReportingPeriod::Quarter {
+                fiscal_year,
+                quarter: quarter - 1,
+            },
+            ReportingPeriod::FiscalYear(year) => ReportingPeriod::FiscalYear(year - 1),
+            ReportingPeriod::YearToDate(through) => {
+                ReportingPeriod::YearToDate(through.checked_sub_months(Months::new(12)).unwrap_or(through))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ReportingPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportingPeriod::Month { year, month } => write!(f, "{:04}-{:02}", year, month),
+            ReportingPeriod::Quarter { fiscal_year, quarter } => write!(f, "FY{}-Q{}", fiscal_year, quarter),
+            ReportingPeriod::FiscalYear(year) => write!(f, "FY{}", year),
+            ReportingPeriod::YearToDate(through) => write!(f, "YTD:{}", through),
+        }
+    }
+}
+
+impl FromStr for ReportingPeriod {
+    type Err = String;
+
+    /// Parses the `Display` form: `2024-03`, `FY2024-Q1`, `FY2024` or `YTD:2024-05-15`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a period (YYYY-MM, FY<year>, FY<year>-Q<n> or YTD:YYYY-MM-DD)", s);
+        let upper = s.to_ascii_uppercase();
+        if let Some(through) = upper.strip_prefix("YTD:") {
+            return NaiveDate::parse_from_str(through, "%Y-%m-%d")
+                .map(ReportingPeriod::YearToDate)
+                .map_err(|_| invalid());
+        }
+        if let Some(fiscal) = upper.strip_prefix("FY") {
+            return match fiscal.split_once("-Q") {
+                Some((year, quarter)) => {
+                    let quarter: u32 = quarter.parse().map_err(|_| invalid())?;
+                    if !(1..=4).contains(&quarter) {
+                        return Err(invalid());
+                    }
+                    Ok(ReportingPeriod::Quarter {
+                        fiscal_year: year.parse().map_err(|_| invalid())?,
+                        quarter,
+                    })
+                }
+                None => fiscal.parse().map(ReportingPeriod::FiscalYear).map_err(|_| invalid()),
+            };
+        }
+        let (year, month) = s.split_once('-').ok_or_else(invalid)?;
+        let month: u32 = month.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) {
+            return Err(invalid());
+        }
+        Ok(ReportingPeriod::Month {
+            year: year.parse().map_err(|_| invalid())?,
+            month,
+        })
+    }
+}
+
+/// The entity's reporting UTC offset and fiscal year.
+///
+/// Local dates are taken at a fixed UTC offset, like the one `RatePolicy::DailyClose`
+/// uses, not in a named time zone with daylight saving rules; an entity observing
+/// daylight saving picks the offset of its year end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportingCalendar {
+    pub utc_offset: FixedOffset,
+    pub fiscal_year_start: FiscalYearStart,
+}
+
+impl Default for ReportingCalendar {
+    /// UTC, with fiscal years matching calendar years.
+    fn default() -> Self {
+        ReportingCalendar::new(FixedOffset::east_opt(0).expect("zero is a valid offset"), FiscalYearStart::JANUARY_1)
+    }
+}
+
+impl ReportingCalendar {
+    pub fn new(utc_offset: FixedOffset, fiscal_year_start: FiscalYearStart) -> Self {
+        ReportingCalendar {
+            utc_offset,
+            fiscal_year_start,
+        }
+    }
+
+    /// The local calendar date of `instant`, saturating at the ends of the date range,
+    /// where open-ended reports start and stop.
+    pub fn local_date(&self, instant: DateTime<Utc>) -> NaiveDate {
+        let offset = self.utc_offset.local_minus_utc();
+        match instant.naive_utc().checked_add_signed(chrono::Duration::seconds(offset.into())) {
+            Some(local) => local.date(),
+            None if offset < 0 => NaiveDate::MIN,
+            None => NaiveDate::MAX,
+        }
+    }
+
+    /// Today's local date.
+    pub fn today(&self) -> NaiveDate {
+        self.local_date(Utc::now())
+    }
+
+    /// The fiscal year `date` falls in, named for the calendar year it ends in.
+    pub fn fiscal_year_of(&self, date: NaiveDate) -> i32 {
+        let start = &self.fiscal_year_start;
+        let year = if (date.month(), date.day()) >= (start.month, start.day) {
+            date.year()
+        } else {
+            date.year() - 1
+        };
+        // A year starting January 1 ends in the year it starts; any other ends in the next
+        if *start == FiscalYearStart::JANUARY_1 {
+            year
+        } else {
+            year + 1
+        }
+    }
+
+    fn fiscal_year_begins(&self, fiscal_year: i32) -> Option<NaiveDate> {
+        let start = &self.fiscal_year_start;
+        let year = if *start == FiscalYearStart::JANUARY_1 { fiscal_year } else { fiscal_year - 1 };
+        NaiveDate::from_ymd_opt(year, start.month, start.day)
+    }
+
+    /// The UTC bounds of `period`, from local midnight on its first day through the
+    /// last instant of its last day. `None` for a month, quarter or year out of range.
+    pub fn period(&self, period: ReportingPeriod) -> Option<FiscalPeriod> {
+        let (first, after) = match period {
+            ReportingPeriod::Month { year, month } => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                (first, first.checked_add_months(Months::new(1))?)
+            }
+            ReportingPeriod::Quarter { fiscal_year, quarter } => {
+                if !(1..=4).contains(&quarter) {
+                    return None;
+                }
+                let first = self.fiscal_year_begins(fiscal_year)?.checked_add_months(Months::new(3 * (quarter - 1)))?;
+                (first, first.checked_add_months(Months::new(3))?)
+            }
+            ReportingPeriod::FiscalYear(fiscal_year) => (self.fiscal_year_begins(fiscal_year)?, self.fiscal_year_begins(fiscal_year + 1)?),
+            ReportingPeriod::YearToDate(through) => (self.fiscal_year_begins(self.fiscal_year_of(through))?, through.succ_opt()?),
+        };
+        let start = self.local_midnight(first)?;
+        let end = self.local_midnight(after)? - chrono::Duration::nanoseconds(1);
+        Some(FiscalPeriod::new(&period.to_string(), start, end))
+    }
+
+    fn local_midnight(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        date.and_time(NaiveTime::MIN)
+            .and_local_timezone(self.utc_offset)
+            .single()
+            .map(|local| local.with_timezone(&Utc))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Fiscal years starting July 1 at `offset_hours` from UTC.
+    fn july(offset_hours: i32) -> ReportingCalendar {
+        ReportingCalendar::new(FixedOffset::east_opt(offset_hours * 3600).unwrap(), FiscalYearStart::new(7, 1).unwrap())
+    }
+
+    fn last_instant(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap() - chrono::Duration::nanoseconds(1)
+    }
+
+    #[test]
+    fn fiscal_year_start_parses_days_in_every_year() {
+        assert_eq!("07-01".parse::<FiscalYearStart>().unwrap().to_string(), "07-01");
+        assert!("02-29".parse::<FiscalYearStart>().is_err());
+        assert!("13-01".parse::<FiscalYearStart>().is_err());
+        assert!("0701".parse::<FiscalYearStart>().is_err());
+    }
+
+    #[test]
+    fn periods_round_trip_through_display() {
+        for text in ["2024-03", "FY2024-Q1", "FY2024", "YTD:2024-05-15"] {
+            assert_eq!(text.parse::<ReportingPeriod>().unwrap().to_string(), text);
+        }
+        assert_eq!("fy2024-q2".parse(), Ok(ReportingPeriod::Quarter { fiscal_year: 2024, quarter: 2 }));
+        for text in ["2024-13", "FY2024-Q5", "2024", "YTD:2024-02-30"] {
+            assert!(text.parse::<ReportingPeriod>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn previous_steps_back_across_years() {
+        assert_eq!(ReportingPeriod::Month { year: 2024, month: 1 }.previous(), ReportingPeriod::Month { year: 2023, month: 12 });
+        assert_eq!(
+            ReportingPeriod::Quarter { fiscal_year: 2024, quarter: 1 }.previous(),
+            ReportingPeriod::Quarter { fiscal_year: 2023, quarter: 4 }
+        );
+        assert_eq!(ReportingPeriod::FiscalYear(2024).previous(), ReportingPeriod::FiscalYear(2023));
+        assert_eq!(ReportingPeriod::YearToDate(date(2024, 2, 29)).previous(), ReportingPeriod::YearToDate(date(2023, 2, 28)));
+    }
+
+    #[test]
+    fn fiscal_years_are_named_for_the_year_they_end_in() {
+        let calendar = july(0);
+        assert_eq!(calendar.fiscal_year_of(date(2023, 6, 30)), 2023);
+        assert_eq!(calendar.fiscal_year_of(date(2023, 7, 1)), 2024);
+        assert_eq!(ReportingCalendar::default().fiscal_year_of(date(2024, 12, 31)), 2024);
+
+        let year = calendar.period(ReportingPeriod::FiscalYear(2024)).unwrap();
+        assert_eq!(year.name, "FY2024");
+        assert_eq!((year.start, year.end), (Utc.with_ymd_and_hms(2023, 7, 1, 0, 0, 0).unwrap(), last_instant(2024, 7, 1, 0)));
+        let q1 = calendar.period(ReportingPeriod::Quarter { fiscal_year: 2024, quarter: 1 }).unwrap();
+        assert_eq!((q1.start, q1.end), (year.start, last_instant(2023, 10, 1, 0)));
+        let q4 = calendar.period(ReportingPeriod::Quarter { fiscal_year: 2024, quarter: 4 }).unwrap();
+        assert_eq!(q4.end, year.end);
+        let ytd = calendar.period(ReportingPeriod::YearToDate(date(2024, 2, 15))).unwrap();
+        assert_eq!((ytd.start, ytd.end), (year.start, last_instant(2024, 2, 16, 0)));
+    }
+
+    #[test]
+    fn periods_run_between_local_midnights() {
+        let month = july(-5).period(ReportingPeriod::Month { year: 2024, month: 1 }).unwrap();
+        assert_eq!(month.start, Utc.with_ymd_and_hms(2024, 1, 1, 5, 0, 0).unwrap());
+        assert_eq!(month.end, last_instant(2024, 2, 1, 5));
+        assert!(!month.contains(Utc.with_ymd_and_hms(2024, 1, 1, 4, 0, 0).unwrap()));
+
+        let calendar = july(0);
+        assert_eq!(calendar.period(ReportingPeriod::Month { year: 2024, month: 13 }), None);
+        assert_eq!(calendar.period(ReportingPeriod::Quarter { fiscal_year: 2024, quarter: 5 }), None);
+    }
+
+    #[test]
+    fn local_dates_saturate_at_the_ends_of_the_range() {
+        assert_eq!(july(-5).local_date(Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap()), date(2023, 12, 31));
+        assert_eq!(july(-5).local_date(DateTime::<Utc>::MIN_UTC), NaiveDate::MIN);
+        assert_eq!(july(5).local_date(DateTime::<Utc>::MAX_UTC), NaiveDate::MAX);
+    }
+}
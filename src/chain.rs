@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::AccountingError;
+use crate::outpoint;
+use crate::reversal::{Reversal, ReversalReason};
+use crate::{BitcoinAccountingApp, Transaction};
+
+/// The block a transaction was mined in.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub hash: String,
+    pub height: u32,
+}
+
+impl fmt::Display for BlockRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at height {}", self.hash, self.height)
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Confirmations a transaction needs before it is posted; zero posts unconfirmed ones.
+    pub fn min_confirmations(&self) -> u64 {
+        self.min_confirmations
+    }
+
+    pub(crate) fn check_confirmed(&self, transaction: &Transaction) -> Result<(), AccountingError> {
+        let confirmations = transaction.confirmations();
+        if confirmations < self.min_confirmations {
+            return Err(AccountingError::Unconfirmed {
+                txid: transaction.txid.clone(),
+                confirmations,
+                required: self.min_confirmations,
+            });
+        }
+        Ok(())
+    }
+
+    /// Records that the transaction `txid` now has `confirmations`, mined in `block`,
+    /// updating its outputs and any of them still held. A pending transaction that
+    /// confirms can no longer be replaced.
+    pub fn update_confirmations(&mut self, txid: &str, confirmations: u64, block: Option<BlockRef>) -> Result<(), AccountingError> {
+        if let Some(block) = block.as_ref().filter(|block| !outpoint::is_valid_txid(&block.hash)) {
+            return Err(AccountingError::InvalidBlockHash(block.hash.clone()));
+        }
+        let position = self
+            .transactions
+            .iter()
+            .position(|transaction| transaction.txid == txid)
+            .ok_or_else(|| AccountingError::UnknownTransaction(txid.to_string()))?;
+        let mut transaction = self.transactions[position].clone();
+        transaction.block = block;
+        for output in &mut transaction.outputs {
+            output.confirmations = confirmations;
+        }
+        self.storage.record_confirmations(&transaction)?;
+        for output in &transaction.outputs {
+            if let Some(utxo) = self.utxo_set.get_mut(&output.outpoint()) {
+                utxo.confirmations = confirmations;
+            }
+        }
+        self.transactions[position] = transaction;
+        Ok(())
+    }
+
+    /// Recorded transactions mined in the block with `hash`, in posting order.
+    pub fn transactions_in_block(&self, hash: &str) -> Vec<&Transaction> {
+        self.transactions
+            .iter()
+            .filter(|transaction| transaction.block.as_ref().is_some_and(|block| block.hash == hash))
+            .collect()
+    }
+
+    /// Rolls back every transaction mined in the block with `hash`, which has left the
+    /// best chain, along with any recorded transaction spending their outputs.
+    ///
+    /// Their entries are reversed on their original dates, spent UTXOs are restored and
+    /// the lots are rebuilt without them. A transaction mined again in the new chain is
+    /// booked afresh with `add_transaction`.
+    pub fn orphan_block(&mut self, hash: &str) -> Result<Vec<Reversal>, AccountingError> {
+        let orphaned = self.transactions_in_block(hash);
+        let block = match orphaned.first().and_then(|transaction| transaction.block.clone()) {
+            Some(block) => block,
+            None => return Err(AccountingError::UnknownBlock(hash.to_string())),
+        };
+        let txids: Vec<String> = orphaned.iter().map(|transaction| transaction.txid.clone()).collect();
+        self.reverse_transactions(&txids, &ReversalReason::Orphaned { block }, None)
+    }
+}
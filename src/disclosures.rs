@@ -0,0 +1,201 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::amount::Amount;
+use crate::error::AccountingError;
+use crate::ledger::{AccountRole, EntryKind};
+use crate::outpoint::OutPoint;
+use crate::period::FiscalPeriod;
+use crate::rates::RateError;
+use crate::restriction::Restriction;
+use crate::statements::before;
+use crate::{BitcoinAccountingApp, Transaction, UTXO};
+
+/// ASU 2023-08 reconciliation of crypto assets from opening to closing fair value.
+///
+/// Opening fair value plus acquisitions, less the cost basis of disposals and
+/// corrections, plus unrealized gain or loss equals closing fair value. Disposal proceeds
+/// and realized gain or loss are shown alongside but do not pass through the asset balance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollForward {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub opening_quantity: Amount,
+    pub opening_fair_value: Decimal,
+    pub acquired_quantity: Amount,
+    /// Fair value on the acquisition dates.
+    pub acquisitions: Decimal,
+    pub disposed_quantity: Amount,
+    pub disposals_cost_basis: Decimal,
+    pub disposal_proceeds: Decimal,
+    pub realized_gain_loss: Decimal,
+    /// Carrying amount taken out of Digital Assets by reversals and restatements posted in
+    /// the period for transactions dated before it.
+    #[serde(default)]
+    pub corrections: Decimal,
+    /// Posted remeasurements plus the change in fair value not yet posted.
+    pub unrealized_gain_loss: Decimal,
+    pub closing_quantity: Amount,
+    /// Equal to Digital Assets on the balance sheet at `end`.
+    pub closing_fair_value: Decimal,
+}
+
+impl RollForward {
+    /// Closing fair value less the reconciled movements; zero when the report ties out.
+    pub fn unreconciled(&self) -> Decimal {
+        self.closing_fair_value - (self.opening_fair_value + self.acquisitions - self.disposals_cost_basis - self.corrections + self.unrealized_gain_loss)
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Roll-forward of crypto assets for `period`.
+    pub fn roll_forward(&self, period: &FiscalPeriod) -> Result<RollForward, AccountingError> {
+        let (start, end) = (period.start, period.end);
+        let replay = self.replay_lots_until(end)?;
+        let in_period = |date: DateTime<Utc>| period.contains(date);
+
+        let acquired: Vec<_> = replay
+            .opened
+            .iter()
+            .chain(&replay.received)
+            .map(|(_, lot)| lot)
+            .filter(|lot| in_period(lot.acquired_at))
+            .collect();
+        let disposed: Vec<_> = replay.realized.iter().filter(|realized| in_period(realized.disposed_at)).collect();
+
+        // Corrections to earlier periods, whose original entries are in the opening balance
+        let digital_assets = self.ledger.chart().code_for(AccountRole::DigitalAssets);
+        let dated_before_start = |txid: &str| {
+            self.transactions
+                .iter()
+                .chain(self.reversals.iter().map(|reversal| &reversal.transaction))
+                .any(|transaction| transaction.txid == txid && transaction.timestamp < start)
+        };
+        let corrections = self
+            .ledger
+            .entries()
+            .iter()
+            .filter(|entry| matches!(entry.kind, EntryKind::Reversal | EntryKind::Restatement) && in_period(entry.date))
+            .filter(|entry| entry.txid.as_deref().is_some_and(dated_before_start))
+            .flat_map(|entry| &entry.lines)
+            .filter(|line| line.account == digital_assets)
+            .map(|line| line.credit - line.debit)
+            .sum();
+
+        let unrealized = self.ledger.chart().code_for(AccountRole::UnrealizedGainLoss);
+        let posted_unrealized = self.ledger.pre_closing_balance(unrealized, before(start)) - self.ledger.pre_closing_balance(unrealized, end);
+        let unposted = self.unposted_fair_value_adjustment(end)? - self.unposted_fair_value_adjustment(before(start))?;
+
+        Ok(RollForward {
+            start,
+            end,
+            opening_quantity: self.holdings_at(before(start)),
+            opening_fair_value: self.fair_value_at(before(start))?,
+            acquired_quantity: acquired.iter().map(|lot| lot.quantity).sum(),
+            acquisitions: acquired.iter().map(|lot| lot.cost_basis).sum(),
+            disposed_quantity: disposed.iter().map(|realized| realized.quantity).sum(),
+            disposals_cost_basis: disposed.iter().map(|realized| realized.cost_basis).sum(),
+            disposal_proceeds: disposed.iter().map(|realized| realized.proceeds).sum(),
+            realized_gain_loss: disposed.iter().map(|realized| realized.gain_loss).sum(),
+            corrections,
+            unrealized_gain_loss: posted_unrealized + unposted,
+            closing_quantity: self.holdings_at(end),
+            closing_fair_value: self.fair_value_at(end)?,
+        })
+    }
+}
+
+/// One restriction on a UTXO held at the reporting date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestrictedHolding {
+    pub outpoint: OutPoint,
+    pub address: String,
+    pub units: Amount,
+    pub cost_basis: Decimal,
+    pub fair_value: Decimal,
+    pub restriction: Restriction,
+}
+
+/// ASU 2023-08 significant holdings disclosure at a reporting date.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoldingsDisclosure {
+    pub reporting_date: DateTime<Utc>,
+    pub asset: String,
+    pub units: Amount,
+    pub rate: Decimal,
+    pub cost_basis: Decimal,
+    pub fair_value: Decimal,
+    /// Restrictions in force at the reporting date, one row per restriction.
+    pub restricted: Vec<RestrictedHolding>,
+}
+
+impl BitcoinAccountingApp {
+    /// Units, cost basis and fair value held at `reporting_date`, with sale restrictions.
+    ///
+    /// Per-UTXO cost basis is that of the open lots carried by its outpoint; relief keeps
+    /// lots on held UTXOs only, so these add up to the total cost basis. Restrictions
+    /// come from the UTXO set for coins still held, and from the recorded output otherwise.
+    pub fn holdings_disclosure(&self, reporting_date: DateTime<Utc>) -> Result<HoldingsDisclosure, AccountingError> {
+        let lots = self.replay_lots_until(reporting_date)?.book;
+        let units = self.holdings_at(reporting_date);
+        let rate = if units.is_zero() {
+            self.exchange_rates.lookup(reporting_date).unwrap_or_default()
+        } else {
+            self.exchange_rates
+                .lookup(reporting_date)
+                .ok_or_else(|| RateError::MissingRates(vec![reporting_date]))?
+        };
+
+        let mut restricted = Vec::new();
+        for (outpoint, utxo) in self.utxos_held_at(reporting_date) {
+            let utxo = self.utxo_set.get(&outpoint).unwrap_or(utxo);
+            let cost_basis: Decimal = lots.lots_of(&outpoint).map(|lot| lot.cost_basis).sum();
+            for restriction in utxo.restrictions.iter().filter(|restriction| restriction.is_active_at(reporting_date)) {
+                restricted.push(RestrictedHolding {
+                    outpoint: outpoint.clone(),
+                    address: utxo.address.clone(),
+                    units: utxo.amount,
+                    cost_basis,
+                    fair_value: utxo.amount.to_btc() * rate,
+                    restriction: restriction.clone(),
+                });
+            }
+        }
+
+        Ok(HoldingsDisclosure {
+            reporting_date,
+            asset: "Bitcoin (BTC)".to_string(),
+            units,
+            rate,
+            cost_basis: lots.open_lots().iter().map(|lot| lot.cost_basis).sum(),
+            fair_value: units.to_btc() * rate,
+            restricted,
+        })
+    }
+
+    /// Owned outputs created at or before `date` and not spent by then, by outpoint.
+    fn utxos_held_at(&self, date: DateTime<Utc>) -> BTreeMap<OutPoint, &UTXO> {
+        let mut held: BTreeMap<OutPoint, &UTXO> = self
+            .opening_balances
+            .iter()
+            .filter(|opening| opening.utxo.timestamp <= date)
+            .map(|opening| (opening.utxo.outpoint(), &opening.utxo))
+            .collect();
+        let mut transactions: Vec<&Transaction> = self
+            .transactions_as_of(date)
+            .filter(|transaction| transaction.timestamp <= date)
+            .collect();
+        transactions.sort_by_key(|transaction| transaction.timestamp);
+        for transaction in transactions {
+            for input in &transaction.inputs {
+                held.remove(&input.outpoint());
+            }
+            for output in transaction.outputs.iter().filter(|output| self.wallets.is_owned(&output.address)) {
+                held.insert(output.outpoint(), output);
+            }
+        }
+        held
+    }
+}
@@ -0,0 +1,189 @@
+use chrono::{DateTime, Utc};
+use crate::amount::Amount;
+use crate::ledger::LedgerError;
+use crate::outpoint::OutPoint;
+use crate::rates::RateError;
+use crate::storage::StorageError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum AccountingError {
+    Rate(RateError),
+    Ledger(LedgerError),
+    Storage(StorageError),
+    /// A transaction or input txid that is not 64 hex digits.
+    InvalidTxid(String),
+    /// A transaction with this txid is already recorded.
+    DuplicateTransaction(String),
+    /// An input claims an output of a recorded transaction or opening balance that does
+    /// not exist or differs, or an owned input spends an output the book does not hold.
+    /// Coins held before the book starts are brought in with `add_opening_balance`.
+    UnknownInput(OutPoint),
+    /// No UTXO is held at this outpoint.
+    UnknownUtxo(OutPoint),
+    /// An input spends an outpoint that another input of the same transaction spends.
+    InputAlreadySpent(OutPoint),
+    /// An output whose `vout` another output of the same transaction already uses.
+    DuplicateOutput(OutPoint),
+    /// Inputs do not equal outputs plus fee.
+    ValueImbalance {
+        txid: String,
+        inputs: Amount,
+        outputs: Amount,
+        fee: Amount,
+    },
+    /// An amount above the 21,000,000 BTC supply cap.
+    AmountOutOfRange {
+        txid: String,
+        amount: Amount,
+    },
+    /// A transaction whose acquisition fee is negative.
+    NegativeAcquisitionFee(String),
+    /// A posting, rate or remeasurement dated inside a closed period.
+    PeriodLocked {
+        date: DateTime<Utc>,
+        period: String,
+    },
+    /// A period that ends before it starts.
+    InvalidPeriod(String),
+    /// A period to close that is named like, or does not start after, one already closed.
+    PeriodOverlap {
+        period: String,
+        closed: String,
+    },
+    /// A prior-period adjustment for a transaction that touches no closed period.
+    NotInClosedPeriod(String),
+    /// A block hash that is not 64 hex digits.
+    InvalidBlockHash(String),
+    /// A transaction with fewer confirmations than the book requires.
+    Unconfirmed {
+        txid: String,
+        confirmations: u64,
+        required: u64,
+    },
+    /// No recorded transaction was mined in this block.
+    UnknownBlock(String),
+    /// A transaction spending outputs already spent by a confirmed recorded transaction.
+    DoubleSpend {
+        txid: String,
+        conflicting: Vec<String>,
+    },
+    /// An unconfirmed transaction spending outputs of pending recorded transactions;
+    /// it replaces them once it confirms.
+    UnconfirmedReplacement {
+        txid: String,
+        replaces: Vec<String>,
+    },
+    /// No transaction with this txid is recorded.
+    UnknownTransaction(String),
+    /// A rate that would change the rate already used for activity posted at `posted`.
+    RateChangesPosted {
+        date: DateTime<Utc>,
+        posted: DateTime<Utc>,
+    },
+    /// A rate policy that would change the rate already used for activity posted at
+    /// `posted`.
+    RatePolicyChangesPosted {
+        posted: DateTime<Utc>,
+    },
+    /// An opening balance paid to an address no registered wallet owns.
+    UnownedAddress(String),
+    /// An opening balance for an outpoint the book already holds or has spent.
+    OpeningBalanceExists(OutPoint),
+    /// A transaction to reverse whose outputs recorded transactions spend.
+    SpentByRecorded {
+        txid: String,
+        spenders: Vec<String>,
+    },
+    /// An address to register that recorded transactions already pay or spend from as
+    /// another party's; owning it would change how they were booked.
+    AddressInHistory(String),
+    /// A posting or policy change that would change the basis relieved by, or the
+    /// proceeds of, the disposal in this posted transaction.
+    DisposalChangesPosted(String),
+}
+
+impl fmt::Display for AccountingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountingError::Rate(err) => write!(f, "{}", err),
+            AccountingError::Ledger(err) => write!(f, "{}", err),
+            AccountingError::Storage(err) => write!(f, "{}", err),
+            AccountingError::InvalidTxid(txid) => write!(f, "'{}' is not a 64-digit hex txid", txid),
+            AccountingError::DuplicateTransaction(txid) => write!(f, "transaction {} is already recorded", txid),
+            AccountingError::UnknownInput(outpoint) => write!(f, "input {} does not match any recorded output or opening balance", outpoint),
+            AccountingError::UnknownUtxo(outpoint) => write!(f, "no UTXO is held at {}", outpoint),
+            AccountingError::InputAlreadySpent(outpoint) => write!(f, "input {} is already spent", outpoint),
+            AccountingError::DuplicateOutput(outpoint) => write!(f, "output {} appears more than once", outpoint),
+            AccountingError::ValueImbalance { inputs, outputs, fee, .. } => {
+                write!(f, "inputs {} do not equal outputs {} plus fee {}", inputs, outputs, fee)
+            }
+            AccountingError::AmountOutOfRange { amount, .. } => write!(f, "amount {} exceeds the 21,000,000 BTC supply", amount),
+            AccountingError::NegativeAcquisitionFee(txid) => write!(f, "acquisition fee of transaction {} is negative", txid),
+            AccountingError::PeriodLocked { date, period } => write!(f, "{} falls in closed period {}", date.to_rfc3339(), period),
+            AccountingError::InvalidPeriod(period) => write!(f, "period {} ends before it starts", period),
+            AccountingError::PeriodOverlap { period, closed } => {
+                write!(f, "period {} must start after closed period {} and have its own name", period, closed)
+            }
+            AccountingError::NotInClosedPeriod(txid) => {
+                write!(f, "transaction {} is not dated in a closed period; add it normally", txid)
+            }
+            AccountingError::InvalidBlockHash(hash) => write!(f, "'{}' is not a 64-digit hex block hash", hash),
+            AccountingError::Unconfirmed {
+                txid,
+                confirmations,
+                required,
+            } => write!(f, "transaction {} has {} confirmation(s); {} required", txid, confirmations, required),
+            AccountingError::UnknownBlock(hash) => write!(f, "no recorded transaction is in block {}", hash),
+            AccountingError::DoubleSpend { txid, conflicting } => {
+                write!(f, "transaction {} spends outputs already spent by confirmed {}", txid, conflicting.join(", "))
+            }
+            AccountingError::UnconfirmedReplacement { txid, replaces } => {
+                write!(f, "transaction {} conflicts with pending {}; add it once confirmed", txid, replaces.join(", "))
+            }
+            AccountingError::UnknownTransaction(txid) => write!(f, "no transaction {} is recorded", txid),
+            AccountingError::RateChangesPosted { date, posted } => write!(
+                f,
+                "a rate at {} would change the rate used for activity already posted at {}",
+                date.to_rfc3339(),
+                posted.to_rfc3339()
+            ),
+            AccountingError::UnownedAddress(address) => write!(f, "address '{}' is not in a registered wallet", address),
+            AccountingError::OpeningBalanceExists(outpoint) => write!(f, "{} is already in the book", outpoint),
+            AccountingError::RatePolicyChangesPosted { posted } => write!(
+                f,
+                "the rate policy would change the rate used for activity already posted at {}",
+                posted.to_rfc3339()
+            ),
+            AccountingError::SpentByRecorded { txid, spenders } => {
+                write!(f, "outputs of transaction {} are spent by {}; reverse those first", txid, spenders.join(", "))
+            }
+            AccountingError::AddressInHistory(address) => {
+                write!(f, "address {} already appears in recorded transactions as another party's", address)
+            }
+            AccountingError::DisposalChangesPosted(txid) => {
+                write!(f, "this would change the basis or proceeds already posted for the disposal in transaction {}", txid)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccountingError {}
+
+impl From<RateError> for AccountingError {
+    fn from(err: RateError) -> Self {
+        AccountingError::Rate(err)
+    }
+}
+
+impl From<LedgerError> for AccountingError {
+    fn from(err: LedgerError) -> Self {
+        AccountingError::Ledger(err)
+    }
+}
+
+impl From<StorageError> for AccountingError {
+    fn from(err: StorageError) -> Self {
+        AccountingError::Storage(err)
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+
+/// Result of an ASU 2023-08 period-end fair value remeasurement.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Remeasurement {
+    pub reporting_date: DateTime<Utc>,
+    pub quantity: Amount,
+    pub rate: Decimal,
+    pub fair_value: Decimal,
+    /// Digital asset balance before the adjustment, including prior remeasurements.
+    pub carrying_amount: Decimal,
+    /// Unrealized gain (positive) or loss (negative) recognized in net income.
+    pub adjustment: Decimal,
+    /// Journal entry posting the adjustment, if it was non-zero.
+    pub entry_id: Option<u64>,
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How network fees and acquisition fees paid by the entity are accounted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FeePolicy {
+    /// Every fee the entity pays is expensed. Disposal proceeds include the BTC spent
+    /// on the fee.
+    #[default]
+    Expense,
+    /// Acquisition fees are added to the basis of the lots received, disposal fees
+    /// reduce proceeds, and fees on internal transfers are expensed. The network fee on
+    /// coins received from outside is the sender's and is ignored.
+    Capitalize,
+}
+
+impl fmt::Display for FeePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeePolicy::Expense => f.write_str("expense"),
+            FeePolicy::Capitalize => f.write_str("capitalize"),
+        }
+    }
+}
+
+impl FromStr for FeePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "expense" => Ok(FeePolicy::Expense),
+            "capitalize" => Ok(FeePolicy::Capitalize),
+            other => Err(format!("unknown fee policy '{}'", other)),
+        }
+    }
+}
+
+/// What a fee policy does with the fee of one transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeTreatment {
+    /// The fee is not the entity's, or there is none.
+    None,
+    /// Subtracted from the proceeds of the disposal.
+    DeductedFromProceeds,
+    /// Posted to Fee Expense; the BTC spent on it is still a disposal.
+    Expensed,
+}
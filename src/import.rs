@@ -0,0 +1,68 @@
+use std::fmt;
+
+use crate::amount::AmountError;
+use crate::error::AccountingError;
+use crate::outpoint::OutPoint;
+use crate::replacement::Replacement;
+
+pub mod bitcoin_core;
+pub mod raw;
+
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    /// The document is valid JSON but not a recognized export.
+    UnrecognizedFormat,
+    /// A wallet send listed without decoded inputs, which cannot be booked.
+    MissingInputs(String),
+    /// A transaction that does not decode or whose outputs exceed its inputs.
+    InvalidTransaction(String),
+    /// `(txid, error)`: an amount that is negative, finer than a satoshi, or out of range.
+    InvalidAmount(String, AmountError),
+    /// `(txid, outpoints)`: inputs spending outputs neither in the book nor the import.
+    MissingPrevout(String, Vec<OutPoint>),
+    Accounting(String, AccountingError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Json(err) => write!(f, "invalid JSON: {}", err),
+            ImportError::UnrecognizedFormat => write!(f, "not a listtransactions, listsinceblock or gettransaction export"),
+            ImportError::MissingInputs(txid) => {
+                write!(f, "{}: send needs `gettransaction {} true` output to resolve its inputs", txid, txid)
+            }
+            ImportError::InvalidTransaction(message) => write!(f, "invalid raw transaction: {}", message),
+            ImportError::InvalidAmount(txid, err) => write!(f, "{}: {}", txid, err),
+            ImportError::MissingPrevout(txid, outpoints) => {
+                let outpoints: Vec<String> = outpoints.iter().map(OutPoint::to_string).collect();
+                write!(f, "{}: spends unknown output(s) {}", txid, outpoints.join(", "))
+            }
+            ImportError::Accounting(txid, err) => write!(f, "{}: {}", txid, err),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        ImportError::Json(err)
+    }
+}
+
+/// Outcome of feeding a batch of imported transactions into the book.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: Vec<String>,
+    /// Already in the book, or not ours to book (conflicted or orphaned).
+    pub skipped: Vec<String>,
+    /// Short of the book's minimum confirmations, or unconfirmed replacements of pending
+    /// transactions; booked by a later import.
+    pub unconfirmed: Vec<String>,
+    /// Already in the book, with new confirmations or a new block.
+    pub updated: Vec<String>,
+    /// Pending transactions voided by an imported confirmed one.
+    pub replaced: Vec<Replacement>,
+    pub failed: Vec<ImportError>,
+}
@@ -0,0 +1,514 @@
+//! Wallet history saved from Bitcoin Core's `listtransactions`, `listsinceblock` and
+//! `gettransaction <txid> true` RPCs.
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
+
+use super::ImportError;
+use crate::amount::{Amount, AmountError};
+use crate::chain::BlockRef;
+use crate::outpoint::OutPoint;
+use crate::{Transaction, UTXO};
+
+/// Core prints signed BTC amounts as JSON numbers; read them through their decimal
+/// text so that no binary rounding creeps in.
+fn btc<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+    let number = serde_json::Number::deserialize(deserializer)?;
+    let text = number.to_string();
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .map_err(serde::de::Error::custom)
+}
+
+fn block_ref(hash: Option<String>, height: Option<u32>) -> Option<BlockRef> {
+    Some(BlockRef { hash: hash?, height: height? })
+}
+
+/// One `listtransactions` / `listsinceblock` row, or one `gettransaction` detail.
+#[derive(Debug, Clone, Deserialize)]
+struct Detail {
+    #[serde(default)]
+    address: Option<String>,
+    category: String,
+    #[serde(deserialize_with = "btc")]
+    amount: Decimal,
+    #[serde(default)]
+    vout: Option<u32>,
+    #[serde(default)]
+    abandoned: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListEntry {
+    txid: String,
+    #[serde(flatten)]
+    detail: Detail,
+    confirmations: i64,
+    #[serde(default)]
+    blockhash: Option<String>,
+    #[serde(default)]
+    blockheight: Option<u32>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    blocktime: Option<DateTime<Utc>>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    time: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SinceBlock {
+    transactions: Vec<ListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetTransaction {
+    txid: String,
+    confirmations: i64,
+    #[serde(default)]
+    blockhash: Option<String>,
+    #[serde(default)]
+    blockheight: Option<u32>,
+    #[serde(default, with = "chrono::serde::ts_seconds_option")]
+    blocktime: Option<DateTime<Utc>>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    time: DateTime<Utc>,
+    details: Vec<Detail>,
+    #[serde(default)]
+    decoded: Option<Decoded>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Decoded {
+    vin: Vec<DecodedInput>,
+    vout: Vec<DecodedOutput>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DecodedInput {
+    /// Absent on coinbase inputs.
+    #[serde(default)]
+    txid: Option<String>,
+    #[serde(default)]
+    vout: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DecodedOutput {
+    value: Amount,
+    n: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: ScriptPubKey,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ScriptPubKey {
+    #[serde(default)]
+    address: Option<String>,
+    /// Used instead of `address` before Core 22.
+    #[serde(default)]
+    addresses: Vec<String>,
+}
+
+impl ScriptPubKey {
+    fn address(&self) -> String {
+        self.address.clone().or_else(|| self.addresses.first().cloned()).unwrap_or_default()
+    }
+}
+
+/// Everything the exports say about one wallet transaction.
+#[derive(Debug, Clone)]
+struct WalletTransaction {
+    time: DateTime<Utc>,
+    blocktime: Option<DateTime<Utc>>,
+    /// Present once mined; Core reports the height from version 0.20.
+    block: Option<BlockRef>,
+    /// Negative when the transaction conflicts with the best chain.
+    confirmations: i64,
+    details: Vec<Detail>,
+    decoded: Option<Decoded>,
+}
+
+impl WalletTransaction {
+    fn timestamp(&self) -> DateTime<Utc> {
+        self.blocktime.unwrap_or(self.time)
+    }
+
+    fn has_detail(&self, detail: &Detail) -> bool {
+        self.details
+            .iter()
+            .any(|existing| existing.category == detail.category && existing.vout == detail.vout && existing.address == detail.address)
+    }
+}
+
+/// Transactions built from a [`CoreExport`], ready for `add_transaction`.
+#[derive(Debug, Default)]
+pub struct CoreBatch {
+    /// In timestamp order.
+    pub transactions: Vec<Transaction>,
+    /// Receiving and change addresses of the exporting wallet.
+    pub owned_addresses: BTreeSet<String>,
+    pub skipped: Vec<String>,
+    /// Confirmations and block now reported for transactions in `booked`.
+    pub confirmations: Vec<(String, u64, Option<BlockRef>)>,
+    pub failed: Vec<ImportError>,
+}
+
+/// Saved Bitcoin Core RPC output, merged by txid.
+///
+/// Documents may overlap; a `gettransaction` result replaces what list rows said about
+/// the same transaction, since only it carries the decoded inputs and outputs.
+#[derive(Debug, Clone, Default)]
+pub struct CoreExport {
+    transactions: BTreeMap<String, WalletTransaction>,
+}
+
+impl CoreExport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Adds one saved RPC result, recognizing the format from its shape: an array is
+    /// `listtransactions`, an object with `transactions` is `listsinceblock` and an
+    /// object with `details` is `gettransaction`.
+    pub fn add_document(&mut self, json: &str) -> Result<(), ImportError> {
+        let value: Value = serde_json::from_str(json)?;
+        match &value {
+            Value::Array(_) => {
+                for entry in serde_json::from_value::<Vec<ListEntry>>(value)? {
+                    self.add_list_entry(entry);
+                }
+            }
+            Value::Object(object) if object.contains_key("transactions") => {
+                for entry in serde_json::from_value::<SinceBlock>(value)?.transactions {
+                    self.add_list_entry(entry);
+                }
+            }
+            Value::Object(object) if object.contains_key("details") => {
+                let transaction: GetTransaction = serde_json::from_value(value)?;
+                self.transactions.insert(
+                    transaction.txid,
+                    WalletTransaction {
+                        time: transaction.time,
+                        blocktime: transaction.blocktime,
+                        block: block_ref(transaction.blockhash, transaction.blockheight),
+                        confirmations: transaction.confirmations,
+                        details: transaction.details,
+                        decoded: transaction.decoded,
+                    },
+                );
+            }
+            _ => return Err(ImportError::UnrecognizedFormat),
+        }
+        Ok(())
+    }
+
+    fn add_list_entry(&mut self, entry: ListEntry) {
+        let block = block_ref(entry.blockhash, entry.blockheight);
+        let transaction = self.transactions.entry(entry.txid).or_insert_with(|| WalletTransaction {
+            time: entry.time,
+            blocktime: entry.blocktime,
+            block: block.clone(),
+            confirmations: entry.confirmations,
+            details: Vec::new(),
+            decoded: None,
+        });
+        if transaction.decoded.is_some() {
+            return;
+        }
+        // A later export knows more about confirmation
+        transaction.confirmations = entry.confirmations;
+        transaction.blocktime = entry.blocktime.or(transaction.blocktime);
+        transaction.block = block;
+        if !transaction.has_detail(&entry.detail) {
+            transaction.details.push(entry.detail);
+        }
+    }
+
+    /// Builds book transactions for everything not already in `booked`.
+    ///
+    /// Inputs are resolved against `known` outputs and then against outputs earlier in
+    /// the export. A transaction the wallet sent must come with decoded data and every
+    /// input resolved; for anything else, inputs are listed only if all resolve. The fee
+    /// is always inputs minus outputs, so it is zero when inputs are not listed.
+    /// Conflicted, abandoned and orphaned transactions are skipped.
+    pub fn build(&self, known: &HashMap<OutPoint, UTXO>, booked: &HashSet<String>) -> CoreBatch {
+        let mut known = known.clone();
+        let mut batch = CoreBatch::default();
+
+        let mut ordered: Vec<(&String, &WalletTransaction)> = self.transactions.iter().collect();
+        ordered.sort_by_key(|(txid, transaction)| (transaction.timestamp(), *txid));
+
+        for (txid, wallet_transaction) in ordered {
+            if booked.contains(txid) {
+                batch.skipped.push(txid.clone());
+                batch.confirmations.push((
+                    txid.clone(),
+                    wallet_transaction.confirmations.max(0) as u64,
+                    wallet_transaction.block.clone(),
+                ));
+                continue;
+            }
+            let dropped = wallet_transaction.confirmations < 0
+                || wallet_transaction.details.iter().any(|detail| detail.abandoned)
+                || wallet_transaction.details.iter().all(|detail| detail.category == "orphan");
+            if dropped {
+                batch.skipped.push(txid.clone());
+                continue;
+            }
+
+            let timestamp = wallet_transaction.timestamp();
+            let immature = wallet_transaction.details.iter().any(|detail| detail.category == "immature");
+            let utxo = |vout: u32, address: String, amount: Amount| UTXO {
+                txid: txid.clone(),
+                vout,
+                amount,
+                address,
+                confirmations: wallet_transaction.confirmations.max(0) as u64,
+                spendable: !immature,
+                timestamp,
+                restrictions: Vec::new(),
+            };
+            let sent: HashSet<Option<u32>> = wallet_transaction
+                .details
+                .iter()
+                .filter(|detail| detail.category == "send")
+                .map(|detail| detail.vout)
+                .collect();
+            let received = wallet_transaction
+                .details
+                .iter()
+                .filter(|detail| matches!(detail.category.as_str(), "receive" | "generate" | "immature"));
+
+            let (inputs, outputs, fee) = match &wallet_transaction.decoded {
+                Some(decoded) => {
+                    let outputs: Vec<UTXO> = decoded
+                        .vout
+                        .iter()
+                        .map(|output| utxo(output.n, output.script_pub_key.address(), output.value))
+                        .collect();
+                    let prevouts: Vec<OutPoint> = decoded
+                        .vin
+                        .iter()
+                        .filter_map(|input| {
+                            Some(OutPoint {
+                                txid: input.txid.clone()?,
+                                vout: input.vout?,
+                            })
+                        })
+                        .collect();
+                    let mut inputs: Vec<UTXO> = prevouts.iter().filter_map(|outpoint| known.get(outpoint).cloned()).collect();
+                    if !sent.is_empty() {
+                        let missing: Vec<OutPoint> = prevouts.iter().filter(|outpoint| !known.contains_key(*outpoint)).cloned().collect();
+                        if !missing.is_empty() {
+                            batch.failed.push(ImportError::MissingPrevout(txid.clone(), missing));
+                            continue;
+                        }
+                        // Core lists every output but change under `send`
+                        batch.owned_addresses.extend(
+                            outputs
+                                .iter()
+                                .filter(|output| !sent.contains(&Some(output.vout)))
+                                .map(|output| output.address.clone()),
+                        );
+                    }
+                    let totals = Amount::checked_sum(inputs.iter().map(|input| input.amount))
+                        .zip(Amount::checked_sum(outputs.iter().map(|output| output.amount)));
+                    let Some((input_total, output_total)) = totals else {
+                        batch.failed.push(ImportError::InvalidAmount(txid.clone(), AmountError::TooLarge));
+                        continue;
+                    };
+                    let complete = !inputs.is_empty() && inputs.len() == prevouts.len();
+                    let fee = match input_total.checked_sub(output_total) {
+                        Some(fee) if complete => fee,
+                        _ if !sent.is_empty() => {
+                            batch.failed.push(ImportError::InvalidTransaction(format!("{}: outputs exceed inputs", txid)));
+                            continue;
+                        }
+                        _ => {
+                            // Someone else's inputs, partly known: list none rather than an unbalanced few
+                            inputs.clear();
+                            Amount::ZERO
+                        }
+                    };
+                    (inputs, outputs, fee)
+                }
+                None if !sent.is_empty() => {
+                    batch.failed.push(ImportError::MissingInputs(txid.clone()));
+                    continue;
+                }
+                None => {
+                    let outputs: Result<Vec<UTXO>, _> = received
+                        .clone()
+                        .filter_map(|detail| Some((detail.vout?, detail.address.clone()?, detail.amount)))
+                        .map(|(vout, address, amount)| Amount::from_btc(amount).map(|amount| utxo(vout, address, amount)))
+                        .collect();
+                    match outputs {
+                        Ok(outputs) => (Vec::new(), outputs, Amount::ZERO),
+                        Err(err) => {
+                            batch.failed.push(ImportError::InvalidAmount(txid.clone(), err));
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            batch
+                .owned_addresses
+                .extend(received.filter_map(|detail| detail.address.clone()));
+            known.extend(outputs.iter().map(|output| (output.outpoint(), output.clone())));
+            batch.transactions.push(Transaction {
+                txid: txid.clone(),
+                timestamp,
+                inputs,
+                outputs,
+                fee,
+                block: wallet_transaction.block.clone(),
+                acquisition_fee: Decimal::ZERO,
+            });
+        }
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn txid(n: u8) -> String {
+        format!("{:02x}", n).repeat(32)
+    }
+
+    /// A `listtransactions` row mined at 2024-01-01T00:00:00Z.
+    fn list_entry(n: u8, category: &str, address: &str, amount: f64, vout: u32) -> Value {
+        json!({
+            "txid": txid(n),
+            "address": address,
+            "category": category,
+            "amount": amount,
+            "vout": vout,
+            "confirmations": 6,
+            "blockhash": "ab".repeat(32),
+            "blockheight": 820000,
+            "blocktime": 1704067200,
+            "time": 1704067100,
+        })
+    }
+
+    /// `gettransaction <txid> true` for a send spending `inputs` to `(address, value)` outputs.
+    fn get_transaction(n: u8, inputs: &[(u8, u32)], outputs: &[(&str, &str)], sent: &[u32]) -> Value {
+        json!({
+            "txid": txid(n),
+            "confirmations": 3,
+            "time": 1706745600,
+            "details": sent.iter().map(|vout| json!({
+                "address": outputs[*vout as usize].0,
+                "category": "send",
+                "amount": -1,
+                "vout": vout,
+            })).collect::<Vec<_>>(),
+            "decoded": {
+                "vin": inputs.iter().map(|(n, vout)| json!({"txid": txid(*n), "vout": vout})).collect::<Vec<_>>(),
+                "vout": outputs.iter().enumerate().map(|(vout, (address, value))| json!({
+                    "value": value,
+                    "n": vout,
+                    "scriptPubKey": {"address": address},
+                })).collect::<Vec<_>>(),
+            },
+        })
+    }
+
+    fn export(documents: &[Value]) -> CoreExport {
+        let mut export = CoreExport::new();
+        for document in documents {
+            export.add_document(&document.to_string()).unwrap();
+        }
+        export
+    }
+
+    fn build(export: &CoreExport) -> CoreBatch {
+        export.build(&HashMap::new(), &HashSet::new())
+    }
+
+    #[test]
+    fn receipts_are_dated_by_block_time() {
+        let batch = build(&export(&[json!([list_entry(1, "receive", "bc1q-hot", 0.5, 1)])]));
+        assert_eq!(batch.transactions.len(), 1);
+        let transaction = &batch.transactions[0];
+        assert_eq!(transaction.timestamp, DateTime::from_timestamp(1704067200, 0).unwrap());
+        assert_eq!(transaction.block.as_ref().map(|block| block.height), Some(820000));
+        assert!(transaction.inputs.is_empty());
+        assert_eq!((transaction.outputs[0].vout, transaction.outputs[0].amount), (1, Amount::from_sat(50_000_000)));
+        assert!(batch.owned_addresses.contains("bc1q-hot"));
+    }
+
+    #[test]
+    fn sends_resolve_inputs_and_compute_the_fee() {
+        let export = export(&[
+            json!([list_entry(1, "receive", "bc1q-hot", 1.0, 0)]),
+            get_transaction(2, &[(1, 0)], &[("bc1q-payee", "0.3"), ("bc1q-change", "0.6999")], &[0]),
+        ]);
+        let batch = build(&export);
+        assert!(batch.failed.is_empty(), "{:?}", batch.failed);
+        let send = &batch.transactions[1];
+        assert_eq!(send.inputs[0].outpoint(), OutPoint { txid: txid(1), vout: 0 });
+        assert_eq!(send.fee, Amount::from_sat(10_000));
+        assert_eq!(send.confirmations(), 3);
+        assert!(batch.owned_addresses.contains("bc1q-change"));
+        assert!(!batch.owned_addresses.contains("bc1q-payee"));
+    }
+
+    #[test]
+    fn sends_without_resolvable_inputs_fail() {
+        let batch = build(&export(&[json!([list_entry(2, "send", "bc1q-payee", -0.3, 0)])]));
+        assert!(matches!(batch.failed.as_slice(), [ImportError::MissingInputs(txid)] if *txid == super::tests::txid(2)));
+
+        let batch = build(&export(&[get_transaction(2, &[(1, 0)], &[("bc1q-payee", "0.3")], &[0])]));
+        assert!(matches!(
+            batch.failed.as_slice(),
+            [ImportError::MissingPrevout(_, missing)] if *missing == vec![OutPoint { txid: txid(1), vout: 0 }]
+        ));
+        assert!(batch.transactions.is_empty());
+    }
+
+    #[test]
+    fn outputs_overflowing_their_total_are_rejected() {
+        let huge = "100000000000";
+        let mut received = get_transaction(3, &[], &[("bc1q-a", huge), ("bc1q-b", huge)], &[]);
+        received["details"] = json!([{"address": "bc1q-a", "category": "receive", "amount": 1, "vout": 0}]);
+        let batch = build(&export(&[received]));
+        assert!(matches!(batch.failed.as_slice(), [ImportError::InvalidAmount(_, AmountError::TooLarge)]));
+    }
+
+    #[test]
+    fn conflicted_abandoned_and_booked_transactions_are_skipped() {
+        let mut conflicted = list_entry(1, "receive", "bc1q-hot", 1.0, 0);
+        conflicted["confirmations"] = json!(-2);
+        let mut abandoned = list_entry(2, "receive", "bc1q-hot", 1.0, 0);
+        abandoned["abandoned"] = json!(true);
+        let export = export(&[json!([conflicted, abandoned, list_entry(3, "receive", "bc1q-hot", 1.0, 0)])]);
+
+        let batch = export.build(&HashMap::new(), &HashSet::from([txid(3)]));
+        assert!(batch.transactions.is_empty());
+        assert_eq!(batch.skipped, vec![txid(1), txid(2), txid(3)]);
+        assert_eq!(batch.confirmations.len(), 1);
+        assert_eq!(batch.confirmations[0].1, 6);
+    }
+
+    #[test]
+    fn unrecognized_documents_are_refused() {
+        assert!(matches!(CoreExport::new().add_document("{\"result\": 1}"), Err(ImportError::UnrecognizedFormat)));
+        assert!(matches!(CoreExport::new().add_document("not json"), Err(ImportError::Json(_))));
+    }
+}
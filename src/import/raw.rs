@@ -0,0 +1,203 @@
+//! Consensus-serialized transactions, legacy or segwit, as returned by
+//! `getrawtransaction` or a signing tool.
+
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::{Address, Network};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+use super::ImportError;
+use crate::amount::{Amount, AmountError};
+use crate::outpoint::OutPoint;
+use crate::{Transaction, UTXO};
+
+/// Decodes `hex` into a `Transaction` dated `timestamp`.
+///
+/// The txid is computed from the non-witness serialization. Output addresses are
+/// derived from each scriptPubKey for `network`; scripts with no address form are
+/// left with an empty address. Every input is looked up by its outpoint in `prevouts`,
+/// and any missing one fails the decode, since the fee is inputs minus outputs and
+/// never taken on trust. An output above `Amount::MAX_MONEY`, or totals that overflow,
+/// fail with `ImportError::InvalidAmount`. Coinbase inputs have no prevout and carry no
+/// fee. New outputs are unconfirmed.
+pub fn decode_transaction(
+    hex: &str,
+    network: Network,
+    timestamp: DateTime<Utc>,
+    prevouts: &HashMap<OutPoint, UTXO>,
+) -> Result<Transaction, ImportError> {
+    let raw: bitcoin::Transaction = deserialize_hex(hex.trim()).map_err(|err| ImportError::InvalidTransaction(err.to_string()))?;
+    let txid = raw.compute_txid().to_string();
+
+    let outputs: Vec<UTXO> = raw
+        .output
+        .iter()
+        .enumerate()
+        .map(|(vout, output)| UTXO {
+            txid: txid.clone(),
+            vout: vout as u32,
+            amount: Amount::from_sat(output.value.to_sat()),
+            address: Address::from_script(&output.script_pubkey, network)
+                .map(|address| address.to_string())
+                .unwrap_or_default(),
+            confirmations: 0,
+            spendable: true,
+            timestamp,
+            restrictions: Vec::new(),
+        })
+        .collect();
+    if outputs.iter().any(|output| output.amount > Amount::MAX_MONEY) {
+        return Err(ImportError::InvalidAmount(txid, AmountError::TooLarge));
+    }
+
+    if raw.is_coinbase() {
+        return Ok(Transaction {
+            txid,
+            timestamp,
+            inputs: Vec::new(),
+            outputs,
+            fee: Amount::ZERO,
+            block: None,
+            acquisition_fee: Decimal::ZERO,
+        });
+    }
+
+    let mut inputs = Vec::new();
+    let mut missing = Vec::new();
+    for input in &raw.input {
+        let outpoint = OutPoint {
+            txid: input.previous_output.txid.to_string(),
+            vout: input.previous_output.vout,
+        };
+        match prevouts.get(&outpoint) {
+            Some(prevout) => inputs.push(prevout.clone()),
+            None => missing.push(outpoint),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(ImportError::MissingPrevout(txid, missing));
+    }
+
+    let totals = Amount::checked_sum(inputs.iter().map(|input| input.amount))
+        .zip(Amount::checked_sum(outputs.iter().map(|output| output.amount)));
+    let Some((input_total, output_total)) = totals else {
+        return Err(ImportError::InvalidAmount(txid, AmountError::TooLarge));
+    };
+    let fee = input_total
+        .checked_sub(output_total)
+        .ok_or_else(|| ImportError::InvalidTransaction(format!("{}: outputs exceed inputs", txid)))?;
+    Ok(Transaction {
+        txid,
+        timestamp,
+        inputs,
+        outputs,
+        fee,
+        block: None,
+        acquisition_fee: Decimal::ZERO,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::transaction::Version;
+    use bitcoin::{ScriptBuf, Sequence, TxIn, TxOut, Witness};
+    use std::str::FromStr;
+
+    const PAYEE: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+
+    fn prevout(amount: u64) -> UTXO {
+        UTXO {
+            txid: "11".repeat(32),
+            vout: 1,
+            amount: Amount::from_sat(amount),
+            address: "owned".to_string(),
+            confirmations: 6,
+            spendable: true,
+            timestamp: Utc::now(),
+            restrictions: Vec::new(),
+        }
+    }
+
+    /// Segwit hex spending `prevout(..)` to `PAYEE` outputs of `values` sats.
+    fn spend(values: &[u64]) -> String {
+        let payee = Address::from_str(PAYEE).unwrap().require_network(Network::Bitcoin).unwrap();
+        let transaction = bitcoin::Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: bitcoin::OutPoint {
+                    txid: "11".repeat(32).parse().unwrap(),
+                    vout: 1,
+                },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::from_slice(&[vec![1u8; 72], vec![2u8; 33]]),
+            }],
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    value: bitcoin::Amount::from_sat(*value),
+                    script_pubkey: payee.script_pubkey(),
+                })
+                .collect(),
+        };
+        serialize_hex(&transaction)
+    }
+
+    fn prevouts(amount: u64) -> HashMap<OutPoint, UTXO> {
+        let prevout = prevout(amount);
+        HashMap::from([(prevout.outpoint(), prevout)])
+    }
+
+    #[test]
+    fn segwit_spend_takes_its_fee_from_the_prevouts() {
+        let hex = spend(&[60_000, 30_000]);
+        let transaction = decode_transaction(&hex, Network::Bitcoin, Utc::now(), &prevouts(100_000)).unwrap();
+        let raw: bitcoin::Transaction = deserialize_hex(&hex).unwrap();
+        assert_eq!(transaction.txid, raw.compute_txid().to_string());
+        assert_ne!(transaction.txid, raw.compute_wtxid().to_string());
+        assert_eq!(transaction.fee, Amount::from_sat(10_000));
+        assert_eq!(transaction.inputs[0].address, "owned");
+        assert_eq!(transaction.outputs[1].vout, 1);
+        assert_eq!(transaction.outputs[1].address, PAYEE);
+        assert_eq!(transaction.outputs[1].confirmations, 0);
+    }
+
+    #[test]
+    fn coinbase_has_no_prevouts_or_fee() {
+        let genesis = &bitcoin::constants::genesis_block(Network::Bitcoin).txdata[0];
+        let transaction = decode_transaction(&serialize_hex(genesis), Network::Bitcoin, Utc::now(), &HashMap::new()).unwrap();
+        assert_eq!(transaction.txid, "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b");
+        assert!(transaction.inputs.is_empty());
+        assert_eq!(transaction.fee, Amount::ZERO);
+        assert_eq!(transaction.outputs[0].amount, Amount::from_sat(5_000_000_000));
+        // Pay-to-pubkey has no address form
+        assert_eq!(transaction.outputs[0].address, "");
+    }
+
+    #[test]
+    fn missing_prevouts_are_reported() {
+        let err = decode_transaction(&spend(&[60_000]), Network::Bitcoin, Utc::now(), &HashMap::new()).unwrap_err();
+        assert!(matches!(err, ImportError::MissingPrevout(_, missing) if missing == vec![prevout(0).outpoint()]));
+    }
+
+    #[test]
+    fn amounts_must_balance_and_stay_in_range() {
+        let err = decode_transaction(&spend(&[60_000, 50_000]), Network::Bitcoin, Utc::now(), &prevouts(100_000)).unwrap_err();
+        assert!(matches!(err, ImportError::InvalidTransaction(_)));
+
+        let above_supply = Amount::MAX_MONEY.to_sat() + 1;
+        let err = decode_transaction(&spend(&[above_supply]), Network::Bitcoin, Utc::now(), &prevouts(u64::MAX)).unwrap_err();
+        assert!(matches!(err, ImportError::InvalidAmount(_, AmountError::TooLarge)));
+    }
+
+    #[test]
+    fn malformed_hex_is_rejected() {
+        let err = decode_transaction("0200zz", Network::Bitcoin, Utc::now(), &HashMap::new()).unwrap_err();
+        assert!(matches!(err, ImportError::InvalidTransaction(_)));
+    }
+}
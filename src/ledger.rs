@@ -0,0 +1,487 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountType {
+    Asset,
+    Liability,
+    Equity,
+    Revenue,
+    Expense,
+}
+
+/// The part an account plays in automatically generated postings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccountRole {
+    Cash,
+    DigitalAssets,
+    RetainedEarnings,
+    Revenue,
+    RealizedGainLoss,
+    UnrealizedGainLoss,
+    FeeExpense,
+}
+
+impl AccountRole {
+    pub const ALL: [AccountRole; 7] = [
+        AccountRole::Cash,
+        AccountRole::DigitalAssets,
+        AccountRole::RetainedEarnings,
+        AccountRole::Revenue,
+        AccountRole::RealizedGainLoss,
+        AccountRole::UnrealizedGainLoss,
+        AccountRole::FeeExpense,
+    ];
+
+    /// Type the account playing this role must have for its postings to report correctly.
+    pub fn account_type(self) -> AccountType {
+        match self {
+            AccountRole::Cash | AccountRole::DigitalAssets => AccountType::Asset,
+            AccountRole::RetainedEarnings => AccountType::Equity,
+            AccountRole::Revenue | AccountRole::RealizedGainLoss | AccountRole::UnrealizedGainLoss => AccountType::Revenue,
+            AccountRole::FeeExpense => AccountType::Expense,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Account {
+    pub code: String,
+    pub name: String,
+    pub account_type: AccountType,
+}
+
+impl Account {
+    pub fn new(code: &str, name: &str, account_type: AccountType) -> Self {
+        Account {
+            code: code.to_string(),
+            name: name.to_string(),
+            account_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChartOfAccounts {
+    accounts: Vec<Account>,
+    roles: HashMap<AccountRole, String>,
+}
+
+impl Default for ChartOfAccounts {
+    fn default() -> Self {
+        let mut chart = ChartOfAccounts {
+            accounts: Vec::new(),
+            roles: HashMap::new(),
+        };
+        chart.assign(AccountRole::Cash, Account::new("1000", "Cash", AccountType::Asset));
+        chart.assign(AccountRole::DigitalAssets, Account::new("1500", "Digital Assets – BTC", AccountType::Asset));
+        chart.assign(AccountRole::RetainedEarnings, Account::new("3000", "Retained Earnings", AccountType::Equity));
+        chart.assign(AccountRole::Revenue, Account::new("4000", "Revenue", AccountType::Revenue));
+        chart.assign(AccountRole::RealizedGainLoss, Account::new("4100", "Realized Gain/Loss", AccountType::Revenue));
+        chart.assign(AccountRole::UnrealizedGainLoss, Account::new("4200", "Unrealized Gain/Loss", AccountType::Revenue));
+        chart.assign(AccountRole::FeeExpense, Account::new("6000", "Transaction Fee Expense", AccountType::Expense));
+        chart
+    }
+}
+
+impl ChartOfAccounts {
+    /// Adds `account` (replacing any account with the same code) and uses it for `role`.
+    pub fn assign(&mut self, role: AccountRole, account: Account) {
+        self.roles.insert(role, account.code.clone());
+        self.add_account(account);
+    }
+
+    pub fn add_account(&mut self, account: Account) {
+        self.accounts.retain(|existing| existing.code != account.code);
+        self.accounts.push(account);
+    }
+
+    pub fn account(&self, code: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.code == code)
+    }
+
+    pub fn code_for(&self, role: AccountRole) -> &str {
+        &self.roles[&role]
+    }
+
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    /// Checks that every role is assigned to an account in the chart of the role's type.
+    pub fn check_roles(&self) -> Result<(), LedgerError> {
+        for role in AccountRole::ALL {
+            let code = self.roles.get(&role).ok_or(LedgerError::UnassignedRole(role))?;
+            let account = self.account(code).ok_or_else(|| LedgerError::UnknownAccount(code.clone()))?;
+            if account.account_type != role.account_type() {
+                return Err(LedgerError::RoleAccountType {
+                    role,
+                    code: code.clone(),
+                    account_type: account.account_type,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalLine {
+    pub account: String,
+    pub debit: Decimal,
+    pub credit: Decimal,
+}
+
+impl JournalLine {
+    pub fn debit(account: &str, amount: Decimal) -> Self {
+        JournalLine {
+            account: account.to_string(),
+            debit: amount,
+            credit: Decimal::ZERO,
+        }
+    }
+
+    pub fn credit(account: &str, amount: Decimal) -> Self {
+        JournalLine {
+            account: account.to_string(),
+            debit: Decimal::ZERO,
+            credit: amount,
+        }
+    }
+}
+
+/// Why an entry was posted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntryKind {
+    #[default]
+    Regular,
+    /// Closes revenue and expense accounts to retained earnings at a period end.
+    Closing,
+    /// Books activity dated in a closed period, in the open period, against retained earnings.
+    PriorPeriodAdjustment,
+    /// Cancels an earlier entry of a transaction that is no longer part of the book.
+    Reversal,
+    /// Moves basis between Digital Assets and realized gain or loss for a disposal whose
+    /// relief changed when another transaction was reversed.
+    Restatement,
+}
+
+impl fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryKind::Regular => f.write_str("regular"),
+            EntryKind::Closing => f.write_str("closing"),
+            EntryKind::PriorPeriodAdjustment => f.write_str("prior-period-adjustment"),
+            EntryKind::Reversal => f.write_str("reversal"),
+            EntryKind::Restatement => f.write_str("restatement"),
+        }
+    }
+}
+
+impl FromStr for EntryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "regular" => Ok(EntryKind::Regular),
+            "closing" => Ok(EntryKind::Closing),
+            "prior-period-adjustment" => Ok(EntryKind::PriorPeriodAdjustment),
+            "reversal" => Ok(EntryKind::Reversal),
+            "restatement" => Ok(EntryKind::Restatement),
+            other => Err(format!("unknown entry kind '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Assigned by the ledger when the entry is posted.
+    pub id: u64,
+    pub date: DateTime<Utc>,
+    pub description: String,
+    pub txid: Option<String>,
+    #[serde(default)]
+    pub kind: EntryKind,
+    pub lines: Vec<JournalLine>,
+}
+
+impl JournalEntry {
+    pub fn new(date: DateTime<Utc>, description: String, txid: Option<String>) -> Self {
+        JournalEntry {
+            id: 0,
+            date,
+            description,
+            txid,
+            kind: EntryKind::Regular,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Appends a line, skipping zero amounts and flipping negative ones to the other side.
+    pub fn push_debit(&mut self, account: &str, amount: Decimal) {
+        if amount > Decimal::ZERO {
+            self.lines.push(JournalLine::debit(account, amount));
+        } else if amount < Decimal::ZERO {
+            self.lines.push(JournalLine::credit(account, -amount));
+        }
+    }
+
+    pub fn push_credit(&mut self, account: &str, amount: Decimal) {
+        self.push_debit(account, -amount);
+    }
+
+    pub fn total_debits(&self) -> Decimal {
+        self.lines.iter().map(|line| line.debit).sum()
+    }
+
+    pub fn total_credits(&self) -> Decimal {
+        self.lines.iter().map(|line| line.credit).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerError {
+    Unbalanced { description: String, debits: Decimal, credits: Decimal },
+    UnknownAccount(String),
+    EmptyEntry(String),
+    UnassignedRole(AccountRole),
+    RoleAccountType { role: AccountRole, code: String, account_type: AccountType },
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::Unbalanced { description, debits, credits } => {
+                write!(f, "entry '{}' is unbalanced: debits {} != credits {}", description, debits, credits)
+            }
+            LedgerError::UnknownAccount(code) => write!(f, "account {} is not in the chart of accounts", code),
+            LedgerError::EmptyEntry(description) => write!(f, "entry '{}' has no lines", description),
+            LedgerError::UnassignedRole(role) => write!(f, "no account is assigned to the {:?} role", role),
+            LedgerError::RoleAccountType { role, code, account_type } => write!(
+                f,
+                "account {} is {:?}, but the {:?} role needs {:?}",
+                code,
+                account_type,
+                role,
+                role.account_type()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    chart: ChartOfAccounts,
+    entries: Vec<JournalEntry>,
+}
+
+impl Ledger {
+    /// Rebuilds a ledger from previously posted entries, re-checking the chart's roles and
+    /// each entry.
+    pub fn restore(chart: ChartOfAccounts, entries: Vec<JournalEntry>) -> Result<Self, LedgerError> {
+        chart.check_roles()?;
+        let ledger = Ledger { chart, entries };
+        ledger.verify()?;
+        Ok(ledger)
+    }
+
+    pub fn chart(&self) -> &ChartOfAccounts {
+        &self.chart
+    }
+
+    /// Replaces the chart of accounts, provided every role maps to an account of its type and
+    /// every posted line still has an account.
+    pub fn set_chart(&mut self, chart: ChartOfAccounts) -> Result<(), LedgerError> {
+        chart.check_roles()?;
+        if let Some(line) = self
+            .entries
+            .iter()
+            .flat_map(|entry| &entry.lines)
+            .find(|line| chart.account(&line.account).is_none())
+        {
+            return Err(LedgerError::UnknownAccount(line.account.clone()));
+        }
+        self.chart = chart;
+        Ok(())
+    }
+
+    /// Id the next posted entry will receive.
+    pub fn next_id(&self) -> u64 {
+        self.entries.last().map_or(1, |last| last.id + 1)
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Checks that `entry` could be posted without changing the ledger.
+    pub fn validate(&self, entry: &JournalEntry) -> Result<(), LedgerError> {
+        if entry.lines.is_empty() {
+            return Err(LedgerError::EmptyEntry(entry.description.clone()));
+        }
+        if let Some(line) = entry.lines.iter().find(|line| self.chart.account(&line.account).is_none()) {
+            return Err(LedgerError::UnknownAccount(line.account.clone()));
+        }
+        let (debits, credits) = (entry.total_debits(), entry.total_credits());
+        if debits != credits {
+            return Err(LedgerError::Unbalanced {
+                description: entry.description.clone(),
+                debits,
+                credits,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn post(&mut self, mut entry: JournalEntry) -> Result<u64, LedgerError> {
+        self.validate(&entry)?;
+        entry.id = self.next_id();
+        let id = entry.id;
+        self.entries.push(entry);
+        Ok(id)
+    }
+
+    /// Re-checks the double-entry invariant across every posted entry.
+    pub fn verify(&self) -> Result<(), LedgerError> {
+        self.entries.iter().try_for_each(|entry| self.validate(entry))
+    }
+
+    /// Debit-positive balance of `account` from entries dated up to and including `as_of`.
+    pub fn balance(&self, account: &str, as_of: DateTime<Utc>) -> Decimal {
+        self.entries
+            .iter()
+            .filter(|entry| entry.date <= as_of)
+            .flat_map(|entry| &entry.lines)
+            .filter(|line| line.account == account)
+            .map(|line| line.debit - line.credit)
+            .sum()
+    }
+    /// Like `balance`, but ignoring closing entries, so revenue and expense accounts
+    /// keep the income already closed to retained earnings.
+    pub fn pre_closing_balance(&self, account: &str, as_of: DateTime<Utc>) -> Decimal {
+        self.entries
+            .iter()
+            .filter(|entry| entry.date <= as_of && entry.kind != EntryKind::Closing)
+            .flat_map(|entry| &entry.lines)
+            .filter(|line| line.account == account)
+            .map(|line| line.debit - line.credit)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn entry(lines: Vec<JournalLine>) -> JournalEntry {
+        let mut entry = JournalEntry::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(), "test".to_string(), None);
+        entry.lines = lines;
+        entry
+    }
+
+    #[test]
+    fn post_rejects_entries_that_do_not_balance() {
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .post(entry(vec![JournalLine::debit("1500", dec!(100)), JournalLine::credit("4000", dec!(90))]))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            LedgerError::Unbalanced {
+                description: "test".to_string(),
+                debits: dec!(100),
+                credits: dec!(90),
+            }
+        );
+        assert_eq!(ledger.post(entry(Vec::new())), Err(LedgerError::EmptyEntry("test".to_string())));
+        assert!(ledger.entries().is_empty());
+    }
+
+    #[test]
+    fn post_rejects_accounts_outside_the_chart() {
+        let mut ledger = Ledger::default();
+        let err = ledger
+            .post(entry(vec![JournalLine::debit("1500", dec!(100)), JournalLine::credit("9999", dec!(100))]))
+            .unwrap_err();
+        assert_eq!(err, LedgerError::UnknownAccount("9999".to_string()));
+    }
+
+    #[test]
+    fn posted_entries_get_sequential_ids_and_balances() {
+        let mut ledger = Ledger::default();
+        let first = ledger
+            .post(entry(vec![JournalLine::debit("1500", dec!(100)), JournalLine::credit("4000", dec!(100))]))
+            .unwrap();
+        let second = ledger
+            .post(entry(vec![JournalLine::debit("6000", dec!(5)), JournalLine::credit("1500", dec!(5))]))
+            .unwrap();
+        assert_eq!((first, second), (1, 2));
+
+        let as_of = Utc.with_ymd_and_hms(2024, 12, 31, 0, 0, 0).unwrap();
+        assert_eq!(ledger.balance("1500", as_of), dec!(95));
+        assert_eq!(ledger.balance("4000", as_of), dec!(-100));
+        let total: Decimal = ledger.chart().accounts().iter().map(|account| ledger.balance(&account.code, as_of)).sum();
+        assert_eq!(total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn push_skips_zero_and_flips_negative_amounts() {
+        let mut entry = entry(Vec::new());
+        entry.push_debit("1500", Decimal::ZERO);
+        entry.push_debit("1500", dec!(-10));
+        entry.push_credit("4100", dec!(-10));
+        assert_eq!(entry.lines.len(), 2);
+        assert_eq!((entry.lines[0].debit, entry.lines[0].credit), (Decimal::ZERO, dec!(10)));
+        assert_eq!((entry.lines[1].debit, entry.lines[1].credit), (dec!(10), Decimal::ZERO));
+    }
+
+    #[test]
+    fn restore_and_set_chart_recheck_posted_lines() {
+        let unbalanced = entry(vec![JournalLine::debit("1500", dec!(1))]);
+        assert!(Ledger::restore(ChartOfAccounts::default(), vec![unbalanced]).is_err());
+
+        let mut ledger = Ledger::default();
+        ledger
+            .post(entry(vec![JournalLine::debit("1500", dec!(1)), JournalLine::credit("4000", dec!(1))]))
+            .unwrap();
+        let mut chart = ChartOfAccounts::default();
+        chart.assign(AccountRole::Revenue, Account::new("4010", "Mining Revenue", AccountType::Revenue));
+        assert!(ledger.set_chart(chart.clone()).is_ok());
+        chart.accounts.retain(|account| account.code != "4000");
+        assert_eq!(ledger.set_chart(chart), Err(LedgerError::UnknownAccount("4000".to_string())));
+    }
+
+    #[test]
+    fn set_chart_requires_every_role_with_its_account_type() {
+        let mut ledger = Ledger::default();
+        let mut missing = ChartOfAccounts::default();
+        missing.roles.remove(&AccountRole::FeeExpense);
+        assert_eq!(ledger.set_chart(missing.clone()), Err(LedgerError::UnassignedRole(AccountRole::FeeExpense)));
+        assert!(Ledger::restore(missing, Vec::new()).is_err());
+
+        let mut dangling = ChartOfAccounts::default();
+        dangling.accounts.retain(|account| account.code != "6000");
+        assert_eq!(ledger.set_chart(dangling), Err(LedgerError::UnknownAccount("6000".to_string())));
+
+        let mut mistyped = ChartOfAccounts::default();
+        mistyped.assign(AccountRole::FeeExpense, Account::new("6000", "Transaction Fee Expense", AccountType::Asset));
+        assert_eq!(
+            ledger.set_chart(mistyped),
+            Err(LedgerError::RoleAccountType {
+                role: AccountRole::FeeExpense,
+                code: "6000".to_string(),
+                account_type: AccountType::Asset,
+            })
+        );
+        assert_eq!(ledger.chart().code_for(AccountRole::FeeExpense), "6000");
+    }
+}
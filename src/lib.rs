@@ -0,0 +1,1126 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+pub mod amount;
+pub mod calendar;
+pub mod chain;
+pub mod disclosures;
+pub mod error;
+pub mod fair_value;
+pub mod fees;
+pub mod import;
+pub mod ledger;
+pub mod lots;
+pub mod opening;
+pub mod outpoint;
+pub mod period;
+pub mod rates;
+pub mod restriction;
+pub mod replacement;
+pub mod reversal;
+pub mod statements;
+pub mod storage;
+pub mod tax;
+pub mod wallet;
+
+use amount::Amount;
+use calendar::{FiscalYearStart, ReportingCalendar};
+use chain::BlockRef;
+use error::AccountingError;
+use fair_value::Remeasurement;
+use fees::{FeePolicy, FeeTreatment};
+use import::bitcoin_core::CoreExport;
+use import::{ImportError, ImportSummary};
+use ledger::{Account, AccountRole, AccountType, ChartOfAccounts, EntryKind, JournalEntry, Ledger};
+use lots::{CostBasisMethod, HoldingTerm, Lot, LotBook, LotReplay, RealizedGains, RealizedLot};
+use opening::OpeningBalance;
+use outpoint::OutPoint;
+use period::{FiscalPeriod, PeriodClose};
+use rates::{RateError, RatePolicy, RateStore};
+use restriction::Restriction;
+use reversal::{Reversal, ReversalPlan};
+use storage::{MemoryStorage, Posting, Storage, StorageError};
+use wallet::{OutputClass, OutputClassification, WalletRegistry};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UTXO {
+    pub txid: String,
+    pub vout: u32,
+    pub amount: Amount,
+    pub address: String,
+    pub confirmations: u64,
+    pub spendable: bool,
+    pub timestamp: DateTime<Utc>,
+    /// Sale restrictions disclosed for this output.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restrictions: Vec<Restriction>,
+}
+
+impl UTXO {
+    /// Where this output lives; for an input, the output it spends.
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint {
+            txid: self.txid.clone(),
+            vout: self.vout,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    pub txid: String,
+    pub timestamp: DateTime<Utc>,
+    pub inputs: Vec<UTXO>,
+    pub outputs: Vec<UTXO>,
+    pub fee: Amount,
+    /// The block it was mined in, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<BlockRef>,
+    /// Fiat the entity paid to acquire the coins it receives, such as an exchange or
+    /// broker commission, on top of any network fee.
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub acquisition_fee: Decimal,
+}
+
+impl Transaction {
+    /// Confirmations reported on its outputs; the lowest if they disagree.
+    pub fn confirmations(&self) -> u64 {
+        self.outputs.iter().map(|output| output.confirmations).min().unwrap_or(0)
+    }
+}
+
+pub struct BitcoinAccountingApp {
+    utxo_set: HashMap<OutPoint, UTXO>,
+    transactions: Vec<Transaction>,
+    opening_balances: Vec<OpeningBalance>,
+    ledger: Ledger,
+    exchange_rates: RateStore,
+    cost_basis_method: CostBasisMethod,
+    fee_policy: FeePolicy,
+    calendar: ReportingCalendar,
+    min_confirmations: u64,
+    remeasurements: Vec<Remeasurement>,
+    period_closes: Vec<PeriodClose>,
+    reversals: Vec<Reversal>,
+    wallets: WalletRegistry,
+    storage: Box<dyn Storage>,
+}
+
+const COST_BASIS_METHOD_SETTING: &str = "cost_basis_method";
+const FEE_POLICY_SETTING: &str = "fee_policy";
+const RATE_POLICY_SETTING: &str = "rate_policy";
+const RATE_MAX_STALENESS_SETTING: &str = "rate_max_staleness_seconds";
+const CHART_OF_ACCOUNTS_SETTING: &str = "chart_of_accounts";
+const REPORTING_UTC_OFFSET_SETTING: &str = "reporting_utc_offset";
+const FISCAL_YEAR_START_SETTING: &str = "fiscal_year_start";
+const MIN_CONFIRMATIONS_SETTING: &str = "min_confirmations";
+
+impl Default for BitcoinAccountingApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Creates an empty book kept in memory only.
+    pub fn new() -> Self {
+        Self::open(Box::new(MemoryStorage::default())).expect("an empty in-memory book always opens")
+    }
+
+    /// Opens the book held by `storage`, restoring its settings and posted history.
+    pub fn open(storage: Box<dyn Storage>) -> Result<Self, AccountingError> {
+        let book = storage.load()?;
+        let setting = |key: &str| book.settings.get(key).map(String::as_str);
+        let corrupt = |key: &str, err: String| StorageError::Corrupt(format!("setting {}: {}", key, err));
+
+        let chart = match setting(CHART_OF_ACCOUNTS_SETTING) {
+            Some(json) => serde_json::from_str(json).map_err(|err| corrupt(CHART_OF_ACCOUNTS_SETTING, err.to_string()))?,
+            None => ChartOfAccounts::default(),
+        };
+        let cost_basis_method = match setting(COST_BASIS_METHOD_SETTING) {
+            Some(method) => method.parse().map_err(|err| corrupt(COST_BASIS_METHOD_SETTING, err))?,
+            None => CostBasisMethod::Fifo,
+        };
+        let fee_policy = match setting(FEE_POLICY_SETTING) {
+            Some(policy) => policy.parse().map_err(|err| corrupt(FEE_POLICY_SETTING, err))?,
+            None => FeePolicy::Expense,
+        };
+        let utc_offset = match setting(REPORTING_UTC_OFFSET_SETTING) {
+            Some(offset) => offset
+                .parse()
+                .map_err(|err: chrono::ParseError| corrupt(REPORTING_UTC_OFFSET_SETTING, err.to_string()))?,
+            None => ReportingCalendar::default().utc_offset,
+        };
+        let fiscal_year_start = match setting(FISCAL_YEAR_START_SETTING) {
+            Some(start) => start.parse().map_err(|err| corrupt(FISCAL_YEAR_START_SETTING, err))?,
+            None => FiscalYearStart::JANUARY_1,
+        };
+        let min_confirmations = match setting(MIN_CONFIRMATIONS_SETTING) {
+            Some(count) => count
+                .parse()
+                .map_err(|err: std::num::ParseIntError| corrupt(MIN_CONFIRMATIONS_SETTING, err.to_string()))?,
+            None => 0,
+        };
+        let policy = match setting(RATE_POLICY_SETTING) {
+            Some(policy) => policy.parse().map_err(|err| corrupt(RATE_POLICY_SETTING, err))?,
+            None => RatePolicy::NearestPrior,
+        };
+        let max_staleness = match setting(RATE_MAX_STALENESS_SETTING) {
+            Some("none") => None,
+            Some(seconds) => Some(chrono::Duration::seconds(
+                seconds
+                    .parse()
+                    .map_err(|err: std::num::ParseIntError| corrupt(RATE_MAX_STALENESS_SETTING, err.to_string()))?,
+            )),
+            None => Some(chrono::Duration::days(1)),
+        };
+
+        let mut exchange_rates = RateStore::new(policy, max_staleness);
+        for (date, rate) in book.rates {
+            exchange_rates.insert(date, rate);
+        }
+        let mut wallets = WalletRegistry::default();
+        for (wallet, address) in &book.wallet_addresses {
+            wallets.add_address(wallet, address);
+        }
+
+        Ok(BitcoinAccountingApp {
+            utxo_set: book.utxos.into_iter().collect(),
+            transactions: book.transactions,
+            opening_balances: book.opening_balances,
+            ledger: Ledger::restore(chart, book.journal_entries)?,
+            exchange_rates,
+            cost_basis_method,
+            fee_policy,
+            calendar: ReportingCalendar::new(utc_offset, fiscal_year_start),
+            min_confirmations,
+            remeasurements: book.remeasurements,
+            period_closes: book.period_closes,
+            reversals: book.reversals,
+            wallets,
+            storage,
+        })
+    }
+
+    pub fn utxo_set(&self) -> &HashMap<OutPoint, UTXO> {
+        &self.utxo_set
+    }
+
+    pub fn get_utxo(&self, outpoint: &OutPoint) -> Option<&UTXO> {
+        self.utxo_set.get(outpoint)
+    }
+
+    /// Held UTXOs paid to `address`, in outpoint order.
+    pub fn utxos_by_address(&self, address: &str) -> Vec<&UTXO> {
+        let mut utxos: Vec<&UTXO> = self.utxo_set.values().filter(|utxo| utxo.address == address).collect();
+        utxos.sort_by_key(|utxo| utxo.outpoint());
+        utxos
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn remeasurements(&self) -> &[Remeasurement] {
+        &self.remeasurements
+    }
+
+    pub fn wallets(&self) -> &WalletRegistry {
+        &self.wallets
+    }
+
+    pub fn cost_basis_method(&self) -> CostBasisMethod {
+        self.cost_basis_method
+    }
+
+    pub fn fee_policy(&self) -> FeePolicy {
+        self.fee_policy
+    }
+
+    /// The reporting UTC offset and fiscal year that `ReportingPeriod`s resolve in.
+    pub fn calendar(&self) -> &ReportingCalendar {
+        &self.calendar
+    }
+
+    /// Every account in the chart with its debit-positive balance as of `as_of`.
+    pub fn trial_balance(&self, as_of: DateTime<Utc>) -> Vec<(Account, Decimal)> {
+        self.ledger
+            .chart()
+            .accounts()
+            .iter()
+            .map(|account| (account.clone(), self.ledger.balance(&account.code, as_of)))
+            .collect()
+    }
+
+    /// Replaces the chart of accounts, provided every posted line keeps an account.
+    pub fn set_chart_of_accounts(&mut self, chart: ChartOfAccounts) -> Result<(), AccountingError> {
+        let mut ledger = self.ledger.clone();
+        ledger.set_chart(chart)?;
+        let json = serde_json::to_string(ledger.chart()).expect("chart of accounts serializes to JSON");
+        self.storage.set_setting(CHART_OF_ACCOUNTS_SETTING, &json)?;
+        self.ledger = ledger;
+        Ok(())
+    }
+
+    /// Sets the cost basis method. It applies to the whole history when lots are
+    /// replayed, so it is refused once it would change a disposal already posted.
+    pub fn set_cost_basis_method(&mut self, method: CostBasisMethod) -> Result<(), AccountingError> {
+        self.check_disposals_unchanged(method, self.fee_policy)?;
+        self.storage.set_setting(COST_BASIS_METHOD_SETTING, &method.to_string())?;
+        self.cost_basis_method = method;
+        Ok(())
+    }
+
+    /// Sets how fees are accounted for. Like the cost basis method, it applies to the
+    /// whole history when lots are replayed, so it is refused once it would change a
+    /// disposal already posted.
+    pub fn set_fee_policy(&mut self, policy: FeePolicy) -> Result<(), AccountingError> {
+        self.check_disposals_unchanged(self.cost_basis_method, policy)?;
+        self.storage.set_setting(FEE_POLICY_SETTING, &policy.to_string())?;
+        self.fee_policy = policy;
+        Ok(())
+    }
+
+    /// Fails if replaying the lots under `method` and `policy` would relieve a different
+    /// basis or proceeds for any posted disposal. The book keeps its own settings.
+    fn check_disposals_unchanged(&mut self, method: CostBasisMethod, policy: FeePolicy) -> Result<(), AccountingError> {
+        let posted = self.replay_lots()?;
+        let settings = (self.cost_basis_method, self.fee_policy);
+        (self.cost_basis_method, self.fee_policy) = (method, policy);
+        let replay = self.replay_lots();
+        (self.cost_basis_method, self.fee_policy) = settings;
+        match posted.changed_disposal(&replay?) {
+            Some(txid) => Err(AccountingError::DisposalChangesPosted(txid.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn set_reporting_calendar(&mut self, calendar: ReportingCalendar) -> Result<(), AccountingError> {
+        self.storage.set_setting(REPORTING_UTC_OFFSET_SETTING, &calendar.utc_offset.to_string())?;
+        self.storage.set_setting(FISCAL_YEAR_START_SETTING, &calendar.fiscal_year_start.to_string())?;
+        self.calendar = calendar;
+        Ok(())
+    }
+
+    /// Sets the confirmations a transaction needs before it is posted. Transactions
+    /// already in the book are not revisited.
+    pub fn set_min_confirmations(&mut self, confirmations: u64) -> Result<(), AccountingError> {
+        self.storage.set_setting(MIN_CONFIRMATIONS_SETTING, &confirmations.to_string())?;
+        self.min_confirmations = confirmations;
+        Ok(())
+    }
+
+    /// Marks `address` as owned by the entity, in `wallet`. An address recorded
+    /// transactions already treat as another party's is refused, since their entries and
+    /// lots would no longer match; coins it held are brought in as opening balances.
+    pub fn register_address(&mut self, wallet: &str, address: &str) -> Result<(), AccountingError> {
+        self.check_unseen(address)?;
+        self.storage.insert_wallet_address(wallet, address)?;
+        self.wallets.add_address(wallet, address);
+        Ok(())
+    }
+
+    /// Fails if `address` is not owned but appears in the recorded history.
+    fn check_unseen(&self, address: &str) -> Result<(), AccountingError> {
+        let seen = self
+            .history()
+            .flat_map(|transaction| transaction.inputs.iter().chain(&transaction.outputs))
+            .any(|utxo| utxo.address == address);
+        if seen && !self.wallets.is_owned(address) {
+            return Err(AccountingError::AddressInHistory(address.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Adds a sale restriction to the held UTXO at `outpoint`.
+    pub fn add_restriction(&mut self, outpoint: &OutPoint, restriction: Restriction) -> Result<(), AccountingError> {
+        let utxo = self
+            .utxo_set
+            .get(outpoint)
+            .ok_or_else(|| AccountingError::UnknownUtxo(outpoint.clone()))?;
+        let mut restrictions = utxo.restrictions.clone();
+        restrictions.push(restriction);
+        self.set_restrictions(outpoint, restrictions)
+    }
+
+    /// Removes every sale restriction from the held UTXO at `outpoint`.
+    pub fn clear_restrictions(&mut self, outpoint: &OutPoint) -> Result<(), AccountingError> {
+        if !self.utxo_set.contains_key(outpoint) {
+            return Err(AccountingError::UnknownUtxo(outpoint.clone()));
+        }
+        self.set_restrictions(outpoint, Vec::new())
+    }
+
+    fn set_restrictions(&mut self, outpoint: &OutPoint, restrictions: Vec<Restriction>) -> Result<(), AccountingError> {
+        self.storage.set_restrictions(outpoint, &restrictions)?;
+        if let Some(utxo) = self.utxo_set.get_mut(outpoint) {
+            utxo.restrictions = restrictions;
+        }
+        Ok(())
+    }
+
+    pub fn classify_outputs(&self, transaction: &Transaction) -> Vec<OutputClassification> {
+        self.wallets.classify(transaction)
+    }
+
+    /// Validates and posts `transaction`, leaving the book untouched if anything fails.
+    ///
+    /// Outputs belong to the transaction that creates them, so their `txid` is set from
+    /// it whatever the caller supplied. A transaction whose date, or any of whose
+    /// entries, falls in a closed period is rejected with `PeriodLocked`, and one with
+    /// fewer confirmations than `min_confirmations` with `Unconfirmed`.
+    ///
+    /// A confirmed transaction spending outputs of pending ones replaces them: their
+    /// entries are voided in the same write that posts it, so if it fails to post they
+    /// stay booked. Conflicts with a confirmed transaction are rejected.
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), AccountingError> {
+        self.record_transaction(transaction, None)
+    }
+
+    /// Books `transaction`, dated in a closed period, as a prior-period adjustment.
+    ///
+    /// The transaction keeps its own date for lots and gains, but entries that would
+    /// fall in a closed period are posted on `posted_on`, which must be open, with
+    /// their revenue and expense lines taken to retained earnings.
+    pub fn add_prior_period_adjustment(&mut self, transaction: Transaction, posted_on: DateTime<Utc>) -> Result<(), AccountingError> {
+        self.check_unlocked(posted_on)?;
+        self.record_transaction(transaction, Some(posted_on))
+    }
+
+    fn record_transaction(&mut self, mut transaction: Transaction, adjustment_date: Option<DateTime<Utc>>) -> Result<(), AccountingError> {
+        for output in &mut transaction.outputs {
+            output.txid.clone_from(&transaction.txid);
+        }
+        self.validate_transaction(&transaction)?;
+        self.check_confirmed(&transaction)?;
+        if adjustment_date.is_none() {
+            self.check_unlocked(transaction.timestamp)?;
+        }
+        let replacing = self.plan_replacement(&transaction)?;
+        let posted = match &replacing {
+            Some(plan) => plan.replay.clone(),
+            None => self.replay_lots()?,
+        };
+        // Record the transaction first, in place of any it replaces, so the lot replay sees
+        // it, and roll back on any failure
+        let recorded = replacing
+            .as_ref()
+            .map(|plan| std::mem::replace(&mut self.transactions, plan.kept.clone()));
+        self.transactions.push(transaction);
+        if let Err(err) = self.post_last_transaction(&posted, adjustment_date, replacing) {
+            match recorded {
+                Some(recorded) => self.transactions = recorded,
+                None => {
+                    self.transactions.pop();
+                }
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Books the transactions in a Bitcoin Core export, skipping any already recorded.
+    ///
+    /// Receiving and change addresses seen in the export are registered to `wallet`
+    /// unless another wallet already owns them; if the book already records any of them
+    /// as another party's, the import fails with `AddressInHistory` before anything is
+    /// booked. Failures are collected per transaction, and transactions short of
+    /// `min_confirmations` are left to a later import. Booked transactions take the
+    /// confirmations and block the export now reports.
+    pub fn import_bitcoin_core(&mut self, wallet: &str, export: &CoreExport) -> Result<ImportSummary, AccountingError> {
+        let booked: HashSet<String> = self.transactions.iter().map(|transaction| transaction.txid.clone()).collect();
+        let known: HashMap<OutPoint, UTXO> = self
+            .transactions
+            .iter()
+            .flat_map(|transaction| &transaction.outputs)
+            .chain(self.opening_balances.iter().map(|opening| &opening.utxo))
+            .map(|output| (output.outpoint(), output.clone()))
+            .collect();
+        let batch = export.build(&known, &booked);
+
+        let unowned: Vec<&String> = batch
+            .owned_addresses
+            .iter()
+            .filter(|address| !address.is_empty() && !self.wallets.is_owned(address))
+            .collect();
+        // Check them all first so a refused address registers none
+        for address in &unowned {
+            self.check_unseen(address)?;
+        }
+        for address in unowned {
+            self.register_address(wallet, address)?;
+        }
+        let mut summary = ImportSummary {
+            skipped: batch.skipped,
+            failed: batch.failed,
+            ..ImportSummary::default()
+        };
+        for (txid, confirmations, block) in batch.confirmations {
+            let changed = self
+                .transactions
+                .iter()
+                .find(|transaction| transaction.txid == txid)
+                .is_some_and(|transaction| transaction.confirmations() != confirmations || transaction.block != block);
+            if changed {
+                self.update_confirmations(&txid, confirmations, block)?;
+                summary.updated.push(txid);
+            }
+        }
+        for transaction in batch.transactions {
+            let txid = transaction.txid.clone();
+            match self.add_transaction(transaction) {
+                Ok(()) => {
+                    summary
+                        .replaced
+                        .extend(self.replacements().into_iter().filter(|replacement| replacement.replacement == txid));
+                    summary.imported.push(txid);
+                }
+                Err(AccountingError::Unconfirmed { .. } | AccountingError::UnconfirmedReplacement { .. }) => summary.unconfirmed.push(txid),
+                Err(err) => summary.failed.push(ImportError::Accounting(txid, err)),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Decodes raw transaction hex, resolving inputs against the UTXO set first and
+    /// then `prevouts`.
+    pub fn decode_raw_transaction(
+        &self,
+        hex: &str,
+        network: bitcoin::Network,
+        timestamp: DateTime<Utc>,
+        prevouts: &HashMap<OutPoint, UTXO>,
+    ) -> Result<Transaction, ImportError> {
+        let mut known = prevouts.clone();
+        known.extend(self.utxo_set.iter().map(|(outpoint, utxo)| (outpoint.clone(), utxo.clone())));
+        import::raw::decode_transaction(hex, network, timestamp, &known)
+    }
+
+    /// Checks `transaction` against the recorded history.
+    ///
+    /// An input naming a recorded transaction or opening balance must match that output
+    /// exactly, and an owned input must name one of them. Inputs of other parties are
+    /// taken as given. Value balance is checked only when inputs are listed, since
+    /// receipts may omit them.
+    fn validate_transaction(&self, transaction: &Transaction) -> Result<(), AccountingError> {
+        let txid = &transaction.txid;
+        if let Some(invalid) = std::iter::once(txid)
+            .chain(transaction.inputs.iter().map(|input| &input.txid))
+            .find(|txid| !outpoint::is_valid_txid(txid))
+        {
+            return Err(AccountingError::InvalidTxid(invalid.clone()));
+        }
+        // Block hashes are written like txids
+        if let Some(block) = transaction.block.as_ref().filter(|block| !outpoint::is_valid_txid(&block.hash)) {
+            return Err(AccountingError::InvalidBlockHash(block.hash.clone()));
+        }
+        let recorded = self.transactions.iter().any(|recorded| &recorded.txid == txid);
+        if recorded || self.opening_balances.iter().any(|opening| &opening.utxo.txid == txid) {
+            return Err(AccountingError::DuplicateTransaction(txid.clone()));
+        }
+
+        let out_of_range = |amount: Amount| AccountingError::AmountOutOfRange { txid: txid.clone(), amount };
+        for utxo in transaction.inputs.iter().chain(&transaction.outputs) {
+            if utxo.amount > Amount::MAX_MONEY {
+                return Err(out_of_range(utxo.amount));
+            }
+        }
+        // A total that overflows is reported saturated
+        let total = |utxos: &[UTXO]| {
+            Amount::checked_sum(utxos.iter().map(|utxo| utxo.amount)).ok_or_else(|| out_of_range(Amount::from_sat(u64::MAX)))
+        };
+        let input_total = total(&transaction.inputs)?;
+        let output_total = total(&transaction.outputs)?;
+        for amount in [transaction.fee, input_total, output_total] {
+            if amount > Amount::MAX_MONEY {
+                return Err(out_of_range(amount));
+            }
+        }
+
+        if transaction.acquisition_fee < Decimal::ZERO {
+            return Err(AccountingError::NegativeAcquisitionFee(txid.clone()));
+        }
+
+        let mut vouts = HashSet::new();
+        if let Some(output) = transaction.outputs.iter().find(|output| !vouts.insert(output.vout)) {
+            return Err(AccountingError::DuplicateOutput(output.outpoint()));
+        }
+
+        // Outputs spent by recorded transactions are conflicts, handled by `plan_replacement`
+        let mut spending = HashSet::new();
+        for input in &transaction.inputs {
+            let outpoint = input.outpoint();
+            if !spending.insert(outpoint.clone()) {
+                return Err(AccountingError::InputAlreadySpent(outpoint));
+            }
+            let funding = self.transactions.iter().find(|recorded| recorded.txid == input.txid);
+            let opening = self.opening_balances.iter().find(|opening| opening.utxo.outpoint() == outpoint);
+            let matches = |output: &UTXO| output.amount == input.amount && output.address == input.address;
+            let known = match (funding, opening) {
+                (Some(funding), _) => funding.outputs.iter().any(|output| output.vout == input.vout && matches(output)),
+                (None, Some(opening)) => matches(&opening.utxo),
+                // Owned coins must come from the book
+                (None, None) => !self.wallets.is_owned(&input.address),
+            };
+            if !known {
+                return Err(AccountingError::UnknownInput(outpoint));
+            }
+        }
+
+        if !transaction.inputs.is_empty() && input_total != output_total + transaction.fee {
+            return Err(AccountingError::ValueImbalance {
+                txid: txid.clone(),
+                inputs: input_total,
+                outputs: output_total,
+                fee: transaction.fee,
+            });
+        }
+        Ok(())
+    }
+
+    /// Posts the most recently recorded transaction, storing it together with `replacing`,
+    /// the reversal of the pending transactions it replaces, whose entries come first.
+    fn post_last_transaction(
+        &mut self,
+        posted: &LotReplay,
+        adjustment_date: Option<DateTime<Utc>>,
+        replacing: Option<ReversalPlan>,
+    ) -> Result<(), AccountingError> {
+        let replay = self.replay_lots()?;
+        let mut entries = self.journal_entries_for_last_transaction(&replay)?;
+        let transaction = self.transactions.last().expect("transaction was just recorded");
+        match adjustment_date {
+            None => {
+                for entry in &entries {
+                    self.check_unlocked(entry.date)?;
+                }
+            }
+            Some(posted_on) => {
+                if self.locked_period(transaction.timestamp).is_none() && entries.iter().all(|entry| self.locked_period(entry.date).is_none()) {
+                    return Err(AccountingError::NotInClosedPeriod(transaction.txid.clone()));
+                }
+                let chart = self.ledger.chart();
+                let retained_earnings = chart.code_for(AccountRole::RetainedEarnings);
+                for entry in entries.iter_mut().filter(|entry| self.locked_period(entry.date).is_some()) {
+                    entry.date = posted_on;
+                    entry.kind = EntryKind::PriorPeriodAdjustment;
+                    entry.description = format!("Prior-period adjustment: {}", entry.description);
+                    // The closed period's income is already in retained earnings
+                    for line in &mut entry.lines {
+                        if chart
+                            .account(&line.account)
+                            .is_some_and(|account| matches!(account.account_type, AccountType::Revenue | AccountType::Expense))
+                        {
+                            line.account = retained_earnings.to_string();
+                        }
+                    }
+                }
+            }
+        }
+        // A backdated transaction must leave the disposals already posted as they are
+        if let Some(txid) = posted.changed_disposal(&replay) {
+            return Err(AccountingError::DisposalChangesPosted(txid.to_string()));
+        }
+        let next_id = self.ledger.next_id() + replacing.as_ref().map_or(0, |plan| plan.entries.len() as u64);
+        for (offset, entry) in entries.iter_mut().enumerate() {
+            self.ledger.validate(entry)?;
+            entry.id = next_id + offset as u64;
+        }
+
+        let transaction = self.transactions.last().expect("transaction was just recorded");
+        let spent: Vec<OutPoint> = transaction.inputs.iter().map(UTXO::outpoint).collect();
+        // New UTXOs are only those paid to our own addresses
+        let created: Vec<(OutPoint, UTXO)> = transaction
+            .outputs
+            .iter()
+            .filter(|output| self.wallets.is_owned(&output.address))
+            .map(|output| (output.outpoint(), output.clone()))
+            .collect();
+
+        let posting = Posting {
+            transaction,
+            entries: &entries,
+            spent: &spent,
+            created: &created,
+            lots: replay.book.open_lots(),
+        };
+        match &replacing {
+            Some(plan) => self.storage.record_replacement(&plan.posting(), &posting)?,
+            None => self.storage.record_posting(&posting)?,
+        }
+
+        if let Some(plan) = replacing {
+            self.apply_reversal(plan)?;
+        }
+        for entry in entries {
+            self.ledger.post(entry)?;
+        }
+        for outpoint in &spent {
+            self.utxo_set.remove(outpoint);
+        }
+        self.utxo_set.extend(created);
+        Ok(())
+    }
+
+    /// Builds the journal entries for the most recently recorded transaction.
+    ///
+    /// Owned inputs funded outside the book are first brought in as revenue at their
+    /// acquisition-date value, and value received from outside the entity is revenue at
+    /// fair value, plus any capitalized acquisition fee paid in Cash; an acquisition fee
+    /// not capitalized goes to Fee Expense. Value leaving the entity relieves lot basis
+    /// from Digital Assets, with an expensed fee to Fee Expense, the rest of the proceeds
+    /// to Cash and the difference to realized gain or loss. Change and internal
+    /// transfers post nothing beyond the fee they spend.
+    fn journal_entries_for_last_transaction(&self, replay: &LotReplay) -> Result<Vec<JournalEntry>, AccountingError> {
+        let transaction = self.transactions.last().expect("transaction was just recorded");
+        let txid = Some(transaction.txid.clone());
+
+        let chart = self.ledger.chart();
+        let digital_assets = chart.code_for(AccountRole::DigitalAssets);
+        let revenue = chart.code_for(AccountRole::Revenue);
+        let mut entries = Vec::new();
+
+        for (_, lot) in replay.opened.iter().filter(|(spender, _)| spender == &transaction.txid) {
+            let mut entry = JournalEntry::new(lot.acquired_at, format!("Received BTC - {}", lot.outpoint), txid.clone());
+            entry.push_debit(digital_assets, lot.cost_basis);
+            entry.push_credit(revenue, lot.cost_basis);
+            entries.push(entry);
+        }
+
+        let treatment = self.fee_treatment(transaction);
+        let fee_value = match treatment {
+            FeeTreatment::None => Decimal::ZERO,
+            _ => {
+                let rate = self
+                    .exchange_rates
+                    .lookup(transaction.timestamp)
+                    .ok_or_else(|| RateError::MissingRates(vec![transaction.timestamp]))?;
+                transaction.fee.to_btc() * rate
+            }
+        };
+
+        let received: Vec<&Lot> = replay
+            .received
+            .iter()
+            .filter(|(receiver, _)| receiver == &transaction.txid)
+            .map(|(_, lot)| lot)
+            .collect();
+        let received_value: Decimal = received.iter().map(|lot| lot.cost_basis).sum();
+        let capitalized_fee = self.capitalized_acquisition_fee(transaction, !received.is_empty());
+        let mut receipt = JournalEntry::new(transaction.timestamp, format!("Received BTC - {}", transaction.txid), txid.clone());
+        receipt.push_debit(digital_assets, received_value);
+        receipt.push_credit(revenue, received_value - capitalized_fee);
+        receipt.push_credit(chart.code_for(AccountRole::Cash), capitalized_fee);
+        entries.push(receipt);
+
+        let expensed_acquisition_fee = transaction.acquisition_fee - capitalized_fee;
+        let mut acquisition = JournalEntry::new(transaction.timestamp, format!("Acquisition fee - {}", transaction.txid), txid.clone());
+        acquisition.push_debit(chart.code_for(AccountRole::FeeExpense), expensed_acquisition_fee);
+        acquisition.push_credit(chart.code_for(AccountRole::Cash), expensed_acquisition_fee);
+        entries.push(acquisition);
+
+        let disposed: Vec<&RealizedLot> = replay.realized.iter().filter(|realized| realized.txid == transaction.txid).collect();
+        if !disposed.is_empty() {
+            let proceeds: Decimal = disposed.iter().map(|realized| realized.proceeds).sum();
+            let relieved_basis: Decimal = disposed.iter().map(|realized| realized.cost_basis).sum();
+            // A deducted fee is already out of the proceeds
+            let expensed_fee = if treatment == FeeTreatment::Expensed { fee_value } else { Decimal::ZERO };
+            let mut entry = JournalEntry::new(transaction.timestamp, format!("Sent BTC - {}", transaction.txid), txid.clone());
+            entry.push_debit(chart.code_for(AccountRole::Cash), proceeds - expensed_fee);
+            entry.push_debit(chart.code_for(AccountRole::FeeExpense), expensed_fee);
+            entry.push_credit(digital_assets, relieved_basis);
+            entry.push_credit(chart.code_for(AccountRole::RealizedGainLoss), proceeds - relieved_basis);
+            entries.push(entry);
+        }
+
+        entries.retain(|entry| !entry.lines.is_empty());
+        Ok(entries)
+    }
+
+    /// Journal entries dated within `period`.
+    pub fn generate_fasb_report(&self, period: &FiscalPeriod) -> Vec<JournalEntry> {
+        self.ledger
+            .entries()
+            .iter()
+            .filter(|entry| period.contains(entry.date))
+            .cloned()
+            .collect()
+    }
+
+    /// Remeasures the holdings at `reporting_date` to fair value then (ASU 2023-08).
+    ///
+    /// The carrying amount is the Digital Assets balance at the reporting date, so the
+    /// previous remeasurement becomes the starting point for this one. The difference
+    /// is posted to Unrealized Gain/Loss, which flows through net income. Activity dated
+    /// after `reporting_date` does not affect it, so a period can be remeasured in arrears.
+    pub fn remeasure_fair_value(&mut self, reporting_date: DateTime<Utc>) -> Result<Remeasurement, AccountingError> {
+        let (remeasurement, entry) = self.prepare_remeasurement(reporting_date)?;
+        self.storage.record_remeasurement(&remeasurement, entry.as_ref())?;
+        if let Some(entry) = entry {
+            self.ledger.post(entry)?;
+        }
+        self.remeasurements.push(remeasurement.clone());
+        Ok(remeasurement)
+    }
+
+    /// Builds and validates the remeasurement at `reporting_date` and its entry, numbered
+    /// as the next one posted, without changing the book.
+    fn prepare_remeasurement(&self, reporting_date: DateTime<Utc>) -> Result<(Remeasurement, Option<JournalEntry>), AccountingError> {
+        self.check_unlocked(reporting_date)?;
+        let rate = self
+            .exchange_rates
+            .lookup(reporting_date)
+            .ok_or_else(|| RateError::MissingRates(vec![reporting_date]))?;
+        let quantity = self.holdings_at(reporting_date);
+        let fair_value = quantity.to_btc() * rate;
+
+        let chart = self.ledger.chart();
+        let digital_assets = chart.code_for(AccountRole::DigitalAssets).to_string();
+        let carrying_amount = self.ledger.balance(&digital_assets, reporting_date);
+        let adjustment = fair_value - carrying_amount;
+
+        let mut entry = JournalEntry::new(reporting_date, "Fair value remeasurement - BTC".to_string(), None);
+        entry.push_debit(&digital_assets, adjustment);
+        entry.push_credit(chart.code_for(AccountRole::UnrealizedGainLoss), adjustment);
+        let entry = if entry.lines.is_empty() {
+            None
+        } else {
+            self.ledger.validate(&entry)?;
+            entry.id = self.ledger.next_id();
+            Some(entry)
+        };
+        let entry_id = entry.as_ref().map(|entry| entry.id);
+
+        let remeasurement = Remeasurement {
+            reporting_date,
+            quantity,
+            rate,
+            fair_value,
+            carrying_amount,
+            adjustment,
+            entry_id,
+        };
+        Ok((remeasurement, entry))
+    }
+
+    /// Realized gain or loss on disposals within `period`, short- and long-term.
+    pub fn calculate_realized_gains_losses(&self, period: &FiscalPeriod) -> Result<RealizedGains, RateError> {
+        Ok(self.realized_gains_by_lot(period)?.iter().collect())
+    }
+
+    pub fn realized_gains_by_lot(&self, period: &FiscalPeriod) -> Result<Vec<RealizedLot>, RateError> {
+        Ok(self
+            .replay_lots_until(period.end)?
+            .realized
+            .into_iter()
+            .filter(|lot| period.contains(lot.disposed_at))
+            .collect())
+    }
+
+    pub fn open_lots(&self) -> Result<Vec<Lot>, RateError> {
+        Ok(self.replay_lots()?.book.open_lots().to_vec())
+    }
+
+    /// Rebuilds the lot book from the recorded transactions in timestamp order.
+    ///
+    /// Only value crossing the entity boundary moves lots in or out: value leaving
+    /// (owned inputs minus owned outputs) is relieved as a disposal, change and internal
+    /// transfers inherit the basis of the spent lots, and anything left over on owned
+    /// outputs is a receipt at fair value. Opening balances are lots from the start.
+    /// Books recorded before opening balances existed may spend owned inputs that were
+    /// never seen as an output; those are opened as lots from their own amount and
+    /// timestamp when spent. Every instant without a usable rate is collected and
+    /// reported together.
+    fn replay_lots(&self) -> Result<LotReplay, RateError> {
+        self.replay_lots_until(DateTime::<Utc>::MAX_UTC)
+    }
+
+    /// The transactions in the book as of `date`: those recorded, and those reversed by
+    /// a correction posted after `date`, which stand until then.
+    fn transactions_as_of(&self, date: DateTime<Utc>) -> impl Iterator<Item = &Transaction> {
+        let corrected = self
+            .reversals
+            .iter()
+            .filter(move |reversal| reversal.posted_on.is_some_and(|posted_on| date < posted_on))
+            .map(|reversal| &reversal.transaction);
+        self.transactions.iter().chain(corrected)
+    }
+
+    /// The recorded transactions and those corrected since, which statements for dates
+    /// before the correction still include.
+    fn history(&self) -> impl Iterator<Item = &Transaction> {
+        let corrected = self
+            .reversals
+            .iter()
+            .filter(|reversal| reversal.posted_on.is_some())
+            .map(|reversal| &reversal.transaction);
+        self.transactions.iter().chain(corrected)
+    }
+
+    /// What the fee policy does with the fee of `transaction`.
+    ///
+    /// The fee is the entity's when it funded every input. It is a transfer fee when
+    /// nothing is paid out of the entity, and a disposal fee otherwise. On coins received
+    /// from outside, where the entity funded no input, the sender paid the fee.
+    fn fee_treatment(&self, transaction: &Transaction) -> FeeTreatment {
+        if transaction.inputs.is_empty() || transaction.fee.is_zero() {
+            return FeeTreatment::None;
+        }
+        let owned_inputs = transaction
+            .inputs
+            .iter()
+            .filter(|input| self.wallets.is_owned(&input.address))
+            .count();
+        if owned_inputs == 0 || owned_inputs < transaction.inputs.len() {
+            return FeeTreatment::None;
+        }
+        let pays_out = self
+            .wallets
+            .classify(transaction)
+            .iter()
+            .any(|classification| classification.class == OutputClass::ExternalPayment);
+        match self.fee_policy {
+            FeePolicy::Capitalize if pays_out => FeeTreatment::DeductedFromProceeds,
+            FeePolicy::Capitalize | FeePolicy::Expense => FeeTreatment::Expensed,
+        }
+    }
+
+    /// The part of the acquisition fee of `transaction` added to the basis of the lots it
+    /// `receives`: all of it under `Capitalize`, and none when no coins come in.
+    fn capitalized_acquisition_fee(&self, transaction: &Transaction, receives: bool) -> Decimal {
+        match self.fee_policy {
+            FeePolicy::Capitalize if receives => transaction.acquisition_fee,
+            _ => Decimal::ZERO,
+        }
+    }
+
+    /// Replays only the transactions in the book as of `until` and dated at or before it.
+    fn replay_lots_until(&self, until: DateTime<Utc>) -> Result<LotReplay, RateError> {
+        let mut book = LotBook::new(self.cost_basis_method);
+        let mut realized = Vec::new();
+        let mut opened = Vec::new();
+        let mut received = Vec::new();
+        let mut missing = BTreeSet::new();
+        let mut rate_at = |date: DateTime<Utc>| {
+            self.exchange_rates.lookup(date).unwrap_or_else(|| {
+                missing.insert(date);
+                Decimal::ZERO
+            })
+        };
+
+        for opening in self.opening_balances.iter().filter(|opening| opening.utxo.timestamp <= until) {
+            let lot = Lot {
+                outpoint: opening.utxo.outpoint(),
+                acquired_at: opening.utxo.timestamp,
+                quantity: opening.utxo.amount,
+                cost_basis: opening.cost_basis,
+            };
+            opened.push((opening.utxo.txid.clone(), lot.clone()));
+            book.acquire(lot);
+        }
+
+        let mut transactions: Vec<&Transaction> = self
+            .transactions_as_of(until)
+            .filter(|transaction| transaction.timestamp <= until)
+            .collect();
+        transactions.sort_by_key(|transaction| transaction.timestamp);
+
+        for transaction in transactions {
+            let owned_inputs: Vec<&UTXO> = transaction
+                .inputs
+                .iter()
+                .filter(|input| self.wallets.is_owned(&input.address))
+                .collect();
+            let owned_outputs: Vec<(OutPoint, Amount)> = transaction
+                .outputs
+                .iter()
+                .zip(self.wallets.classify(transaction))
+                .filter(|(_, classification)| {
+                    matches!(
+                        classification.class,
+                        OutputClass::Receipt | OutputClass::Change | OutputClass::InternalTransfer
+                    )
+                })
+                .map(|(output, _)| (output.outpoint(), output.amount))
+                .collect();
+            if owned_inputs.is_empty() && owned_outputs.is_empty() {
+                continue;
+            }
+            let rate = rate_at(transaction.timestamp);
+            let treatment = self.fee_treatment(transaction);
+
+            let spent: Vec<OutPoint> = owned_inputs.iter().map(|input| input.outpoint()).collect();
+            for (input, outpoint) in owned_inputs.iter().zip(&spent) {
+                if !book.contains(outpoint) {
+                    let lot = Lot {
+                        outpoint: outpoint.clone(),
+                        acquired_at: input.timestamp,
+                        quantity: input.amount,
+                        cost_basis: input.amount.to_btc() * rate_at(input.timestamp),
+                    };
+                    opened.push((transaction.txid.clone(), lot.clone()));
+                    book.acquire(lot);
+                }
+            }
+
+            // Fees paid from our inputs are part of the value leaving the entity
+            let owned_in: Amount = owned_inputs.iter().map(|utxo| utxo.amount).sum();
+            let owned_out: Amount = owned_outputs.iter().map(|(_, amount)| *amount).sum();
+            if let Some(leaving) = owned_in.checked_sub(owned_out).filter(|leaving| !leaving.is_zero()) {
+                let sold = match treatment {
+                    FeeTreatment::DeductedFromProceeds => leaving.checked_sub(transaction.fee).unwrap_or(Amount::ZERO),
+                    _ => leaving,
+                };
+                let total_proceeds = sold.to_btc() * rate;
+                let reliefs = book.relieve(leaving, &spent);
+                let count = reliefs.len();
+                let mut allocated = Decimal::ZERO;
+                for (index, relief) in reliefs.into_iter().enumerate() {
+                    // The last lot absorbs rounding so per-lot proceeds sum to the total.
+                    let proceeds = if index + 1 == count {
+                        total_proceeds - allocated
+                    } else {
+                        (total_proceeds * Decimal::from(relief.quantity.to_sat()) / Decimal::from(leaving.to_sat())).round_dp(8)
+                    };
+                    allocated += proceeds;
+                    realized.push(RealizedLot {
+                        txid: transaction.txid.clone(),
+                        disposed_at: transaction.timestamp,
+                        outpoint: relief.outpoint,
+                        acquired_at: relief.acquired_at,
+                        term: HoldingTerm::of(
+                            self.calendar.local_date(relief.acquired_at),
+                            self.calendar.local_date(transaction.timestamp),
+                        ),
+                        quantity: relief.quantity,
+                        proceeds,
+                        cost_basis: relief.cost_basis,
+                        gain_loss: proceeds - relief.cost_basis,
+                    });
+                }
+            }
+
+            let uncovered = book.transfer(&spent, &owned_outputs);
+            let received_quantity: Amount = uncovered.iter().map(|(_, quantity)| *quantity).sum();
+            let capitalized_fee = self.capitalized_acquisition_fee(transaction, !uncovered.is_empty());
+            let count = uncovered.len();
+            let mut allocated = Decimal::ZERO;
+            for (index, (outpoint, quantity)) in uncovered.into_iter().enumerate() {
+                // The holding period runs from the receiving output's own timestamp
+                let acquired_at = transaction
+                    .outputs
+                    .iter()
+                    .find(|output| output.outpoint() == outpoint)
+                    .map_or(transaction.timestamp, |output| output.timestamp);
+                // A capitalized fee is spread over the lots received by sats, the last taking the rounding
+                let fee_share = if index + 1 == count {
+                    capitalized_fee - allocated
+                } else {
+                    (capitalized_fee * Decimal::from(quantity.to_sat()) / Decimal::from(received_quantity.to_sat())).round_dp(8)
+                };
+                allocated += fee_share;
+                let lot = Lot {
+                    outpoint,
+                    acquired_at,
+                    quantity,
+                    cost_basis: quantity.to_btc() * rate + fee_share,
+                };
+                received.push((transaction.txid.clone(), lot.clone()));
+                book.acquire(lot);
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(RateError::MissingRates(missing.into_iter().collect()));
+        }
+        Ok(LotReplay {
+            book,
+            realized,
+            opened,
+            received,
+        })
+    }
+
+    /// Records a rate observation.
+    ///
+    /// A rate is rejected if it would change the rate already used for a posted
+    /// transaction, owned input, opening balance or remeasurement, since their entries
+    /// would no longer match the lots replayed from them: with `PeriodLocked` when dated
+    /// in a closed period, and `RateChangesPosted` otherwise. Rates that only affect
+    /// instants with nothing posted, such as those needed for prior-period adjustments,
+    /// are accepted.
+    pub fn add_exchange_rate(&mut self, date: DateTime<Utc>, rate: Decimal) -> Result<(), AccountingError> {
+        if self.exchange_rates.get(date) != Some(rate) {
+            let current: Vec<(DateTime<Utc>, Option<Decimal>)> = self
+                .rated_instants()
+                .into_iter()
+                .map(|instant| (instant, self.exchange_rates.lookup(instant)))
+                .collect();
+            // Try the rate in place, then put back whatever it replaced
+            let previous = self.exchange_rates.insert(date, rate);
+            let changed = current
+                .into_iter()
+                .find(|(instant, used)| self.exchange_rates.lookup(*instant) != *used);
+            match previous {
+                Some(previous) => self.exchange_rates.insert(date, previous),
+                None => self.exchange_rates.remove(date),
+            };
+            if let Some((posted, _)) = changed {
+                self.check_unlocked(date)?;
+                return Err(AccountingError::RateChangesPosted { date, posted });
+            }
+        }
+        self.storage.insert_rate(date, rate)?;
+        self.exchange_rates.insert(date, rate);
+        Ok(())
+    }
+
+    /// Every instant a posted entry took a rate at, earliest first.
+    fn rated_instants(&self) -> BTreeSet<DateTime<Utc>> {
+        self.history()
+            .flat_map(|transaction| {
+                let inputs = transaction.inputs.iter().filter(|input| self.wallets.is_owned(&input.address));
+                std::iter::once(transaction.timestamp).chain(inputs.map(|input| input.timestamp))
+            })
+            .chain(self.opening_balances.iter().map(|opening| opening.utxo.timestamp))
+            .chain(self.remeasurements.iter().map(|remeasurement| remeasurement.reporting_date))
+            .collect()
+    }
+
+    pub fn rate_at(&self, date: DateTime<Utc>) -> Option<Decimal> {
+        self.exchange_rates.lookup(date)
+    }
+
+    /// The latest rate observation or transaction, whichever is later: the last instant
+    /// the book can value without waiting for newer rates.
+    pub fn latest_activity(&self) -> Option<DateTime<Utc>> {
+        let latest_transaction = self.transactions.iter().map(|transaction| transaction.timestamp).max();
+        self.exchange_rates.latest().max(latest_transaction)
+    }
+
+    /// Sets how rates are looked up. Like a new rate, it is refused with
+    /// `RatePolicyChangesPosted` if it would change the rate used for anything posted.
+    pub fn set_rate_policy(&mut self, policy: RatePolicy, max_staleness: Option<chrono::Duration>) -> Result<(), AccountingError> {
+        let mut rates = self.exchange_rates.clone();
+        rates.set_policy(policy, max_staleness);
+        if let Some(posted) = self
+            .rated_instants()
+            .into_iter()
+            .find(|instant| rates.lookup(*instant) != self.exchange_rates.lookup(*instant))
+        {
+            return Err(AccountingError::RatePolicyChangesPosted { posted });
+        }
+        let staleness = max_staleness.map_or("none".to_string(), |limit| limit.num_seconds().to_string());
+        self.storage.set_setting(RATE_POLICY_SETTING, &policy.to_string())?;
+        self.storage.set_setting(RATE_MAX_STALENESS_SETTING, &staleness)?;
+        self.exchange_rates.set_policy(policy, max_staleness);
+        Ok(())
+    }
+}
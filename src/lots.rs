@@ -0,0 +1,528 @@
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::amount::Amount;
+use crate::outpoint::OutPoint;
+
+/// Order in which open lots are relieved when BTC leaves the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CostBasisMethod {
+    Fifo,
+    Lifo,
+    Hifo,
+    /// Relieve the lots of the exact outpoints (`txid:vout`) being spent.
+    SpecificId,
+    /// Relieve every open lot pro rata, so disposals carry the running weighted-average
+    /// cost (Canadian adjusted cost base).
+    AverageCost,
+}
+
+impl fmt::Display for CostBasisMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CostBasisMethod::Fifo => "fifo",
+            CostBasisMethod::Lifo => "lifo",
+            CostBasisMethod::Hifo => "hifo",
+            CostBasisMethod::SpecificId => "specific-id",
+            CostBasisMethod::AverageCost => "average-cost",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for CostBasisMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(CostBasisMethod::Fifo),
+            "lifo" => Ok(CostBasisMethod::Lifo),
+            "hifo" => Ok(CostBasisMethod::Hifo),
+            "specific-id" => Ok(CostBasisMethod::SpecificId),
+            "average-cost" => Ok(CostBasisMethod::AverageCost),
+            other => Err(format!("unknown cost basis method '{}'", other)),
+        }
+    }
+}
+
+/// Cost basis carried by a single UTXO from the moment it enters the book.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lot {
+    pub outpoint: OutPoint,
+    pub acquired_at: DateTime<Utc>,
+    pub quantity: Amount,
+    pub cost_basis: Decimal,
+}
+
+impl Lot {
+    pub fn unit_cost(&self) -> Decimal {
+        if self.quantity.is_zero() {
+            Decimal::ZERO
+        } else {
+            self.cost_basis / self.quantity.to_btc()
+        }
+    }
+
+    /// Removes up to `quantity` from this lot, returning the removed part with prorated basis.
+    fn split_off(&mut self, quantity: Amount) -> Lot {
+        let taken = quantity.min(self.quantity);
+        let cost_basis = if taken == self.quantity {
+            self.cost_basis
+        } else {
+            (self.cost_basis * Decimal::from(taken.to_sat()) / Decimal::from(self.quantity.to_sat())).round_dp(8)
+        };
+        self.quantity -= taken;
+        self.cost_basis -= cost_basis;
+        Lot {
+            outpoint: self.outpoint.clone(),
+            acquired_at: self.acquired_at,
+            quantity: taken,
+            cost_basis,
+        }
+    }
+}
+
+/// The portion of a lot consumed by a disposal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LotRelief {
+    pub outpoint: OutPoint,
+    pub acquired_at: DateTime<Utc>,
+    pub quantity: Amount,
+    pub cost_basis: Decimal,
+}
+
+/// Capital gains holding period bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HoldingTerm {
+    ShortTerm,
+    LongTerm,
+}
+
+impl HoldingTerm {
+    /// Long-term when held more than one year, counted in calendar days: the local
+    /// dates of acquisition and disposal, from `ReportingCalendar::local_date`.
+    ///
+    /// The holding period starts the day after acquisition, so a lot becomes long-term
+    /// the day after its first anniversary. A lot acquired on 29 February has its
+    /// anniversary on 28 February of the following year.
+    pub fn of(acquired: NaiveDate, disposed: NaiveDate) -> HoldingTerm {
+        match acquired.checked_add_months(Months::new(12)) {
+            Some(anniversary) if disposed > anniversary => HoldingTerm::LongTerm,
+            _ => HoldingTerm::ShortTerm,
+        }
+    }
+}
+
+impl fmt::Display for HoldingTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HoldingTerm::ShortTerm => f.write_str("short-term"),
+            HoldingTerm::LongTerm => f.write_str("long-term"),
+        }
+    }
+}
+
+/// Gain or loss realized on one lot by one disposal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealizedLot {
+    pub txid: String,
+    pub disposed_at: DateTime<Utc>,
+    pub outpoint: OutPoint,
+    pub acquired_at: DateTime<Utc>,
+    pub term: HoldingTerm,
+    pub quantity: Amount,
+    pub proceeds: Decimal,
+    pub cost_basis: Decimal,
+    pub gain_loss: Decimal,
+}
+
+/// Realized gain or loss split by holding period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RealizedGains {
+    pub short_term: Decimal,
+    pub long_term: Decimal,
+}
+
+impl RealizedGains {
+    pub fn total(&self) -> Decimal {
+        self.short_term + self.long_term
+    }
+}
+
+impl<'a> FromIterator<&'a RealizedLot> for RealizedGains {
+    fn from_iter<I: IntoIterator<Item = &'a RealizedLot>>(iter: I) -> Self {
+        let mut gains = RealizedGains::default();
+        for realized in iter {
+            match realized.term {
+                HoldingTerm::ShortTerm => gains.short_term += realized.gain_loss,
+                HoldingTerm::LongTerm => gains.long_term += realized.gain_loss,
+            }
+        }
+        gains
+    }
+}
+
+/// Open lots, each carried by an outpoint the entity still holds.
+///
+/// The cost basis method decides which lots a disposal relieves, not which coins were
+/// spent, so relief may take a lot carried by a UTXO that stays in the book. That UTXO
+/// then takes over an equal quantity of the spent outpoints' lots, so the lots on every
+/// held outpoint always add up to its amount and none are left on spent outpoints.
+#[derive(Debug, Clone)]
+pub struct LotBook {
+    method: CostBasisMethod,
+    lots: Vec<Lot>,
+    seen: HashSet<OutPoint>,
+}
+
+impl LotBook {
+    pub fn new(method: CostBasisMethod) -> Self {
+        LotBook {
+            method,
+            lots: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Whether a lot was ever opened for `outpoint`, even if it has since been relieved.
+    pub fn contains(&self, outpoint: &OutPoint) -> bool {
+        self.seen.contains(outpoint)
+    }
+
+    pub fn acquire(&mut self, lot: Lot) {
+        self.seen.insert(lot.outpoint.clone());
+        self.lots.push(lot);
+    }
+
+    pub fn open_lots(&self) -> &[Lot] {
+        &self.lots
+    }
+
+    /// The open lots carried by `outpoint`.
+    pub fn lots_of<'a>(&'a self, outpoint: &'a OutPoint) -> impl Iterator<Item = &'a Lot> {
+        self.lots.iter().filter(move |lot| &lot.outpoint == outpoint)
+    }
+
+    /// Relieves `quantity` BTC from the open lots using the book's method.
+    ///
+    /// `spent` lists the outpoints consumed by the disposal. Specific identification
+    /// relieves their lots; the other methods may relieve lots of held outpoints, which
+    /// are made whole from the lots of `spent`. Relief stops early if the open lots run out.
+    pub fn relieve(&mut self, quantity: Amount, spent: &[OutPoint]) -> Vec<LotRelief> {
+        let reliefs = match self.method {
+            CostBasisMethod::AverageCost => self.relieve_pro_rata(quantity),
+            method => self.relieve_in_order(method, quantity, spent),
+        };
+        for relief in reliefs.iter().filter(|relief| !spent.contains(&relief.outpoint)) {
+            self.reassign(spent, &relief.outpoint, relief.quantity);
+        }
+        reliefs
+    }
+
+    fn relieve_in_order(&mut self, method: CostBasisMethod, quantity: Amount, spent: &[OutPoint]) -> Vec<LotRelief> {
+        let mut order: Vec<usize> = (0..self.lots.len()).collect();
+        match method {
+            CostBasisMethod::Fifo => order.sort_by_key(|&i| self.lots[i].acquired_at),
+            CostBasisMethod::Lifo => order.sort_by_key(|&i| std::cmp::Reverse(self.lots[i].acquired_at)),
+            CostBasisMethod::Hifo => {
+                order.sort_by_key(|&i| std::cmp::Reverse(self.lots[i].unit_cost()));
+            }
+            CostBasisMethod::SpecificId | CostBasisMethod::AverageCost => order = self.indices_of(spent),
+        }
+
+        let mut remaining = quantity;
+        let mut reliefs = Vec::new();
+        for index in order {
+            if remaining.is_zero() {
+                break;
+            }
+            let taken = self.lots[index].split_off(remaining);
+            remaining -= taken.quantity;
+            reliefs.push(LotRelief {
+                outpoint: taken.outpoint,
+                acquired_at: taken.acquired_at,
+                quantity: taken.quantity,
+                cost_basis: taken.cost_basis,
+            });
+        }
+
+        self.lots.retain(|lot| !lot.quantity.is_zero());
+        reliefs
+    }
+
+    /// Takes the same share of every open lot, leaving the average unit cost unchanged.
+    ///
+    /// Each lot gives up its proportion of `quantity` rounded down to the satoshi; the
+    /// sats lost to rounding come from the first lots that still have some left.
+    fn relieve_pro_rata(&mut self, quantity: Amount) -> Vec<LotRelief> {
+        let held: u128 = self.lots.iter().map(|lot| u128::from(lot.quantity.to_sat())).sum();
+        let wanted = u128::from(quantity.to_sat()).min(held);
+        let mut shares: Vec<u64> = self
+            .lots
+            .iter()
+            .map(|lot| (wanted * u128::from(lot.quantity.to_sat()) / held.max(1)) as u64)
+            .collect();
+        let mut short = wanted - shares.iter().map(|&share| u128::from(share)).sum::<u128>();
+        for (share, lot) in shares.iter_mut().zip(&self.lots) {
+            if short == 0 {
+                break;
+            }
+            if *share < lot.quantity.to_sat() {
+                *share += 1;
+                short -= 1;
+            }
+        }
+
+        let mut reliefs = Vec::new();
+        for (lot, share) in self.lots.iter_mut().zip(shares) {
+            if share == 0 {
+                continue;
+            }
+            let taken = lot.split_off(Amount::from_sat(share));
+            reliefs.push(LotRelief {
+                outpoint: taken.outpoint,
+                acquired_at: taken.acquired_at,
+                quantity: taken.quantity,
+                cost_basis: taken.cost_basis,
+            });
+        }
+        self.lots.retain(|lot| !lot.quantity.is_zero());
+        reliefs
+    }
+
+    /// Moves up to `quantity` of the lots carried by `from` onto `to`, in the order given.
+    fn reassign(&mut self, from: &[OutPoint], to: &OutPoint, quantity: Amount) {
+        let mut needed = quantity;
+        let mut moved = Vec::new();
+        for index in self.indices_of(from) {
+            if needed.is_zero() {
+                break;
+            }
+            let mut piece = self.lots[index].split_off(needed);
+            needed -= piece.quantity;
+            piece.outpoint = to.clone();
+            moved.push(piece);
+        }
+        self.lots.retain(|lot| !lot.quantity.is_zero());
+        self.lots.extend(moved.into_iter().filter(|lot| !lot.quantity.is_zero()));
+    }
+
+    /// Moves the basis left on the `spent` lots onto `destinations`, keeping acquisition dates.
+    ///
+    /// Used when coins stay within the entity (change and internal transfers). Returns the
+    /// part of each destination that the spent lots could not cover.
+    pub fn transfer(&mut self, spent: &[OutPoint], destinations: &[(OutPoint, Amount)]) -> Vec<(OutPoint, Amount)> {
+        let sources = self.indices_of(spent);
+        let mut cursor = 0;
+        let mut moved = Vec::new();
+        let mut uncovered = Vec::new();
+
+        for (outpoint, quantity) in destinations {
+            self.seen.insert(outpoint.clone());
+            let mut needed = *quantity;
+            while !needed.is_zero() && cursor < sources.len() {
+                let source = &mut self.lots[sources[cursor]];
+                let mut piece = source.split_off(needed);
+                if source.quantity.is_zero() {
+                    cursor += 1;
+                }
+                needed -= piece.quantity;
+                piece.outpoint = outpoint.clone();
+                moved.push(piece);
+            }
+            if !needed.is_zero() {
+                uncovered.push((outpoint.clone(), needed));
+            }
+        }
+
+        self.lots.retain(|lot| !lot.quantity.is_zero());
+        self.lots.extend(moved.into_iter().filter(|lot| !lot.quantity.is_zero()));
+        uncovered
+    }
+
+    /// Indices of the open lots carrying any of `outpoints`, in the order given.
+    fn indices_of(&self, outpoints: &[OutPoint]) -> Vec<usize> {
+        outpoints
+            .iter()
+            .flat_map(|outpoint| {
+                self.lots
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, lot)| &lot.outpoint == outpoint)
+                    .map(|(index, _)| index)
+            })
+            .collect()
+    }
+}
+
+/// Outcome of replaying the recorded transactions through a `LotBook`.
+#[derive(Debug, Clone)]
+pub struct LotReplay {
+    pub book: LotBook,
+    pub realized: Vec<RealizedLot>,
+    /// Opening balances, keyed by their own txid, and lots opened for owned inputs the
+    /// book never held, keyed by the spending txid.
+    pub opened: Vec<(String, Lot)>,
+    /// Lots opened for value entering the entity, keyed by the receiving txid.
+    pub received: Vec<(String, Lot)>,
+}
+
+impl LotReplay {
+    /// Cost basis relieved by the disposal in transaction `txid`.
+    pub fn relieved(&self, txid: &str) -> Decimal {
+        self.realized.iter().filter(|realized| realized.txid == txid).map(|realized| realized.cost_basis).sum()
+    }
+
+    /// The first disposal in this replay that `other` relieves a different basis for or
+    /// values at different proceeds.
+    pub fn changed_disposal(&self, other: &LotReplay) -> Option<&str> {
+        let proceeds = |replay: &LotReplay, txid: &str| -> Decimal {
+            replay.realized.iter().filter(|realized| realized.txid == txid).map(|realized| realized.proceeds).sum()
+        };
+        self.realized
+            .iter()
+            .map(|realized| realized.txid.as_str())
+            .find(|txid| self.relieved(txid) != other.relieved(txid) || proceeds(self, txid) != proceeds(other, txid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn outpoint(n: u8) -> OutPoint {
+        OutPoint {
+            txid: format!("{:02x}", n).repeat(32),
+            vout: 0,
+        }
+    }
+
+    fn lot(n: u8, day: u32, btc: u64, cost_basis: Decimal) -> Lot {
+        Lot {
+            outpoint: outpoint(n),
+            acquired_at: Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap(),
+            quantity: Amount::from_sat(btc * 100_000_000),
+            cost_basis,
+        }
+    }
+
+    /// Three 1 BTC lots bought on days 1, 2 and 3 at 30k, 50k and 40k.
+    fn book(method: CostBasisMethod) -> LotBook {
+        let mut book = LotBook::new(method);
+        book.acquire(lot(1, 1, 1, dec!(30000)));
+        book.acquire(lot(2, 2, 1, dec!(50000)));
+        book.acquire(lot(3, 3, 1, dec!(40000)));
+        book
+    }
+
+    fn relieved(reliefs: &[LotRelief]) -> Vec<(OutPoint, Decimal)> {
+        reliefs.iter().map(|relief| (relief.outpoint.clone(), relief.cost_basis)).collect()
+    }
+
+    fn basis_of(book: &LotBook, outpoint: &OutPoint) -> Decimal {
+        book.lots_of(outpoint).map(|lot| lot.cost_basis).sum()
+    }
+
+    #[test]
+    fn fifo_relieves_the_oldest_lot_first() {
+        let mut book = book(CostBasisMethod::Fifo);
+        let reliefs = book.relieve(Amount::from_sat(150_000_000), &[outpoint(1), outpoint(2)]);
+        assert_eq!(relieved(&reliefs), vec![(outpoint(1), dec!(30000)), (outpoint(2), dec!(25000))]);
+    }
+
+    #[test]
+    fn lifo_relieves_the_newest_lot_first() {
+        let mut book = book(CostBasisMethod::Lifo);
+        let reliefs = book.relieve(Amount::from_sat(50_000_000), &[outpoint(1)]);
+        assert_eq!(relieved(&reliefs), vec![(outpoint(3), dec!(20000))]);
+    }
+
+    #[test]
+    fn hifo_relieves_the_highest_unit_cost_first() {
+        let mut book = book(CostBasisMethod::Hifo);
+        let reliefs = book.relieve(Amount::from_sat(150_000_000), &[outpoint(1), outpoint(3)]);
+        assert_eq!(relieved(&reliefs), vec![(outpoint(2), dec!(50000)), (outpoint(3), dec!(20000))]);
+    }
+
+    #[test]
+    fn specific_id_relieves_the_spent_outpoints() {
+        let mut book = book(CostBasisMethod::SpecificId);
+        let reliefs = book.relieve(Amount::from_sat(100_000_000), &[outpoint(3)]);
+        assert_eq!(relieved(&reliefs), vec![(outpoint(3), dec!(40000))]);
+        assert_eq!(book.open_lots().len(), 2);
+    }
+
+    #[test]
+    fn average_cost_relieves_every_lot_pro_rata() {
+        let mut book = book(CostBasisMethod::AverageCost);
+        let reliefs = book.relieve(Amount::from_sat(150_000_000), &[outpoint(1), outpoint(2)]);
+        let basis: Decimal = reliefs.iter().map(|relief| relief.cost_basis).sum();
+        assert_eq!(basis, dec!(60000));
+        assert_eq!(reliefs.len(), 3);
+        for remaining in book.open_lots() {
+            assert_eq!(remaining.quantity, Amount::from_sat(50_000_000));
+        }
+    }
+
+    #[test]
+    fn relief_stops_when_the_lots_run_out() {
+        let mut book = book(CostBasisMethod::Fifo);
+        let reliefs = book.relieve(Amount::from_sat(500_000_000), &[outpoint(1), outpoint(2), outpoint(3)]);
+        let quantity: Amount = reliefs.iter().map(|relief| relief.quantity).sum();
+        assert_eq!(quantity, Amount::from_sat(300_000_000));
+        assert!(book.open_lots().is_empty());
+    }
+
+    #[test]
+    fn relief_of_a_held_outpoint_moves_the_spent_lots_onto_it() {
+        // FIFO takes outpoint 1's lot while outpoint 3 is the one spent
+        let mut book = book(CostBasisMethod::Fifo);
+        let reliefs = book.relieve(Amount::from_sat(100_000_000), &[outpoint(3)]);
+        assert_eq!(relieved(&reliefs), vec![(outpoint(1), dec!(30000))]);
+        assert_eq!(book.lots_of(&outpoint(3)).count(), 0);
+        assert_eq!(basis_of(&book, &outpoint(1)), dec!(40000));
+        assert_eq!(basis_of(&book, &outpoint(2)), dec!(50000));
+        let held: Amount = book.lots_of(&outpoint(1)).map(|lot| lot.quantity).sum();
+        assert_eq!(held, Amount::from_sat(100_000_000));
+    }
+
+    #[test]
+    fn transfer_carries_basis_and_date_to_the_destinations() {
+        let mut book = book(CostBasisMethod::Fifo);
+        let change = outpoint(9);
+        let uncovered = book.transfer(&[outpoint(2)], &[(change.clone(), Amount::from_sat(150_000_000))]);
+        assert_eq!(uncovered, vec![(change.clone(), Amount::from_sat(50_000_000))]);
+        let moved: Vec<&Lot> = book.lots_of(&change).collect();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].cost_basis, dec!(50000));
+        assert_eq!(moved[0].acquired_at, Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap());
+        assert!(book.contains(&change));
+    }
+
+    fn term(acquired: (i32, u32, u32), disposed: (i32, u32, u32)) -> HoldingTerm {
+        let date = |(year, month, day)| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        HoldingTerm::of(date(acquired), date(disposed))
+    }
+
+    #[test]
+    fn long_term_starts_the_day_after_the_anniversary() {
+        assert_eq!(term((2023, 3, 15), (2024, 3, 15)), HoldingTerm::ShortTerm);
+        assert_eq!(term((2023, 3, 15), (2024, 3, 16)), HoldingTerm::LongTerm);
+        // A year spanning 29 February is still counted by calendar date
+        assert_eq!(term((2023, 3, 1), (2024, 3, 1)), HoldingTerm::ShortTerm);
+        assert_eq!(term((2023, 3, 1), (2024, 3, 2)), HoldingTerm::LongTerm);
+    }
+
+    #[test]
+    fn leap_day_anniversary_falls_on_28_february() {
+        assert_eq!(term((2024, 2, 29), (2025, 2, 28)), HoldingTerm::ShortTerm);
+        assert_eq!(term((2024, 2, 29), (2025, 3, 1)), HoldingTerm::LongTerm);
+    }
+}
@@ -1,182 +1,968 @@
-use chrono::{DateTime, Utc};
+use bitcoin_accounting::amount::Amount;
+use bitcoin_accounting::calendar::{FiscalYearStart, ReportingCalendar, ReportingPeriod};
+use bitcoin_accounting::disclosures::{HoldingsDisclosure, RollForward};
+use bitcoin_accounting::fees::FeePolicy;
+use bitcoin_accounting::import::bitcoin_core::CoreExport;
+use bitcoin_accounting::lots::{CostBasisMethod, RealizedGains};
+use bitcoin_accounting::outpoint::OutPoint;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::rates::RatePolicy;
+use bitcoin_accounting::restriction::{LockTime, Restriction};
+use bitcoin_accounting::replacement::{Replacement, TransactionStatus};
+use bitcoin_accounting::reversal::Reversal;
+use bitcoin_accounting::statements::{BalanceSheet, IncomeStatement, StatementLine};
+use bitcoin_accounting::storage::SqliteStorage;
+use bitcoin_accounting::tax::acb::{AcbComputation, AcbEventKind};
+use bitcoin_accounting::tax::form8949::{Form8949, Form8949Totals};
+use bitcoin_accounting::tax::section104::CgtComputation;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction, UTXO};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
 use rust_decimal::Decimal;
-use rust_decimal_macros::dec;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct UTXO {
-    txid: String,
-    vout: u32,
-    amount: Decimal,
-    address: String,
-    confirmations: u64,
-    spendable: bool,
-    timestamp: DateTime<Utc>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Transaction {
-    txid: String,
-    timestamp: DateTime<Utc>,
-    inputs: Vec<UTXO>,
-    outputs: Vec<UTXO>,
-    fee: Decimal,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct AccountingEntry {
-    date: DateTime<Utc>,
-    description: String,
-    debit: Decimal,
-    credit: Decimal,
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+mod output;
+
+use output::{Format, Table};
+
+/// Bitcoin UTXO accounting with FASB reporting.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// SQLite file holding the book
+    #[arg(long, global = true, default_value = "book.sqlite")]
+    book: PathBuf,
+
+    /// Output format for listings and reports
+    #[arg(long, global = true, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a new, empty book
+    Init {
+        /// fifo, lifo, hifo, specific-id or average-cost
+        #[arg(long, default_value = "fifo")]
+        cost_basis: CostBasisMethod,
+        /// expense, or capitalize to add acquisition fees to basis and deduct disposal fees
+        /// from proceeds
+        #[arg(long, default_value = "expense")]
+        fee_policy: FeePolicy,
+        /// exact, nearest-prior, interpolate or daily-close@<offset> (e.g. daily-close@-05:00)
+        #[arg(long, default_value = "nearest-prior")]
+        rate_policy: RatePolicy,
+        /// Oldest usable rate observation in seconds, or "none"
+        #[arg(long, default_value = "86400")]
+        max_staleness: String,
+        /// Reporting UTC offset (e.g. -05:00); dates on the command line are local to it
+        #[arg(long, default_value = "+00:00", allow_hyphen_values = true)]
+        utc_offset: FixedOffset,
+        /// First day of the fiscal year as MM-DD (e.g. 07-01)
+        #[arg(long, default_value = "01-01")]
+        fiscal_year_start: FiscalYearStart,
+        /// Confirmations a transaction needs before it is booked
+        #[arg(long, default_value_t = 0)]
+        min_confirmations: u64,
+    },
+    /// Register addresses as owned by the entity
+    AddAddress {
+        wallet: String,
+        #[arg(required = true)]
+        addresses: Vec<String>,
+    },
+    /// Import transactions, opening balances or exchange rates from files
+    Import {
+        kind: ImportKind,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Wallet that receiving and change addresses from a Bitcoin Core export belong to
+        #[arg(long, default_value = "bitcoin-core")]
+        wallet: String,
+        /// Book transactions dated in a closed period as prior-period adjustments posted on this open date
+        #[arg(long)]
+        prior_period_adjustment: Option<DateBound>,
+    },
+    /// Print a report for a date range
+    Report {
+        kind: ReportKind,
+        /// Start of the range (YYYY-MM-DD or RFC 3339); defaults to the beginning of the book
+        #[arg(long)]
+        from: Option<DateBound>,
+        /// End of the range, inclusive (YYYY-MM-DD or RFC 3339); defaults to the latest rate or transaction
+        #[arg(long)]
+        to: Option<DateBound>,
+        /// Reporting period instead of a range: YYYY-MM, FY<year>, FY<year>-Q<n>, YTD or YTD:YYYY-MM-DD
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        period: Option<String>,
+    },
+    /// List recorded and reversed transactions with their status
+    Transactions,
+    /// List the current UTXO set
+    Utxos,
+    /// Record a sale restriction on a held UTXO
+    Restrict {
+        /// `txid:vout` of the UTXO
+        outpoint: OutPoint,
+        kind: RestrictionKind,
+        /// End of the restriction (YYYY-MM-DD or RFC 3339); required for lock-up
+        #[arg(long)]
+        until: Option<DateBound>,
+        /// Block height a timelock ends at, instead of --until
+        #[arg(long)]
+        height: Option<u32>,
+        /// Party holding a collateral pledge
+        #[arg(long, required_if_eq("kind", "pledged"))]
+        counterparty: Option<String>,
+    },
+    /// Remove all sale restrictions from a held UTXO
+    Unrestrict { outpoint: OutPoint },
+    /// Close a period: remeasure holdings to fair value at its end, close income to retained earnings and lock it
+    ClosePeriod {
+        /// Period end (YYYY-MM-DD or RFC 3339)
+        #[arg(long, required_unless_present = "period")]
+        date: Option<DateBound>,
+        /// Reporting period to close instead of a range, e.g. FY2024 or FY2024-Q4
+        #[arg(long, conflicts_with_all = ["date", "from"])]
+        period: Option<String>,
+        /// Period start; defaults to just after the last closed period, or the start of the book
+        #[arg(long)]
+        from: Option<DateBound>,
+        /// Period name; defaults to its end date
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// List closed periods
+    Periods,
+    /// Roll back the transactions mined in a block that left the best chain
+    OrphanBlock {
+        /// Hash of the orphaned block
+        hash: String,
+    },
+    /// Reverse a transaction booked in error, restoring the UTXOs it spent
+    Reverse {
+        txid: String,
+        /// Date of the reversing entries (YYYY-MM-DD or RFC 3339); defaults to now
+        #[arg(long)]
+        date: Option<DateBound>,
+        /// Who is making the correction
+        #[arg(long)]
+        by: String,
+        /// Why the transaction is reversed
+        #[arg(long)]
+        reason: String,
+    },
+    /// List transactions taken out of the book
+    Reversals,
+    /// List pending transactions replaced by confirmed ones, with the fee difference
+    Replacements,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ImportKind {
+    /// JSON array of transactions
+    Transactions,
+    /// JSON array of UTXOs held before the book starts, each with an optional `cost_basis`
+    OpeningBalances,
+    /// CSV of `timestamp,rate` rows, or a JSON array of `{"date", "rate"}` objects
+    Rates,
+    /// Saved `listtransactions`, `listsinceblock` or `gettransaction <txid> true` output
+    BitcoinCore,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum RestrictionKind {
+    Timelocked,
+    Pledged,
+    LockUp,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ReportKind {
+    Journal,
+    /// As of the end of the range, compared with the day before it starts
+    BalanceSheet,
+    /// For the range, compared with the equally long period before it
+    IncomeStatement,
+    /// ASU 2023-08 crypto asset roll-forward for the range and the period before it
+    RollForward,
+    /// Holdings and sale restrictions at the end of the range
+    Holdings,
+    Gains,
+    /// IRS Form 8949 lines for disposals in the range; the form layout in table format
+    #[value(name = "form-8949")]
+    Form8949,
+    /// Schedule D lines 3 and 10 carried from Form 8949
+    ScheduleD,
+    /// HMRC capital gains computation with Section 104 pooling; the computation in table format
+    UkCgt,
+    /// Canadian adjusted cost base with superficial losses
+    Acb,
 }
 
-struct BitcoinAccountingApp {
-    utxo_set: HashMap<String, UTXO>,
-    transactions: Vec<Transaction>,
-    accounting_entries: Vec<AccountingEntry>,
-    exchange_rates: HashMap<DateTime<Utc>, Decimal>,
+/// A date given on the command line, either a calendar day at the book's reporting UTC
+/// offset or an exact instant.
+#[derive(Debug, Clone, Copy)]
+enum DateBound {
+    Day(NaiveDate),
+    Instant(DateTime<Utc>),
 }
 
-impl BitcoinAccountingApp {
-    fn new() -> Self {
-        BitcoinAccountingApp {
-            utxo_set: HashMap::new(),
-            transactions: Vec::new(),
-            accounting_entries: Vec::new(),
-            exchange_rates: HashMap::new(),
+impl FromStr for DateBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(day) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            return Ok(DateBound::Day(day));
         }
+        DateTime::parse_from_rfc3339(s)
+            .map(|instant| DateBound::Instant(instant.with_timezone(&Utc)))
+            .map_err(|_| format!("'{}' is not a YYYY-MM-DD date or RFC 3339 timestamp", s))
     }
+}
 
-    fn add_transaction(&mut self, transaction: Transaction) {
-        // Remove spent UTXOs
-        for input in &transaction.inputs {
-            self.utxo_set.remove(&format!("{}:{}", input.txid, input.vout));
+impl DateBound {
+    fn start(self, utc_offset: FixedOffset) -> DateTime<Utc> {
+        match self {
+            DateBound::Day(day) => (day.and_time(NaiveTime::MIN) - utc_offset).and_utc(),
+            DateBound::Instant(instant) => instant,
         }
+    }
 
-        // Add new UTXOs
-        for output in &transaction.outputs {
-            let key = format!("{}:{}", transaction.txid, output.vout);
-            self.utxo_set.insert(key, output.clone());
+    /// Last instant covered; a calendar day runs through its final nanosecond.
+    fn end(self, utc_offset: FixedOffset) -> DateTime<Utc> {
+        match self {
+            DateBound::Day(_) => self.start(utc_offset) + chrono::Duration::days(1) - chrono::Duration::nanoseconds(1),
+            DateBound::Instant(instant) => instant,
         }
+    }
+}
+
+/// Resolves a `--period` value in the book's calendar; `YTD` runs through today.
+fn reporting_period(calendar: &ReportingCalendar, spec: &str) -> Result<ReportingPeriod, String> {
+    if spec.eq_ignore_ascii_case("ytd") {
+        Ok(ReportingPeriod::YearToDate(calendar.today()))
+    } else {
+        spec.parse()
+    }
+}
+
+fn fiscal_period(calendar: &ReportingCalendar, period: ReportingPeriod) -> Result<FiscalPeriod, String> {
+    calendar.period(period).ok_or_else(|| format!("period {} is out of range", period))
+}
+
+#[derive(Debug, Deserialize)]
+struct OpeningBalanceRecord {
+    #[serde(flatten)]
+    utxo: UTXO,
+    /// Defaults to the fair value at the UTXO's timestamp.
+    cost_basis: Option<Decimal>,
+}
 
-        // Create accounting entries
-        let total_input: Decimal = transaction.inputs.iter().map(|utxo| utxo.amount).sum();
-        let total_output: Decimal = transaction.outputs.iter().map(|utxo| utxo.amount).sum();
+#[derive(Debug, Deserialize)]
+struct RateRecord {
+    date: DateTime<Utc>,
+    rate: Decimal,
+}
 
-        // Debit entry (for received funds)
-        if total_output > Decimal::ZERO {
-            self.accounting_entries.push(AccountingEntry {
-                date: transaction.timestamp,
-                description: format!("Received BTC - {}", transaction.txid),
-                debit: total_output,
-                credit: Decimal::ZERO,
-            });
+fn main() {
+    let cli = Cli::parse();
+    if let Err(err) = run(cli) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    if let Command::Init {
+        cost_basis,
+        fee_policy,
+        rate_policy,
+        max_staleness,
+        utc_offset,
+        fiscal_year_start,
+        min_confirmations,
+    } = &cli.command
+    {
+        if cli.book.exists() {
+            return Err(format!("{} already exists", cli.book.display()).into());
         }
+        let max_staleness = match max_staleness.as_str() {
+            "none" => None,
+            seconds => Some(chrono::Duration::seconds(seconds.parse()?)),
+        };
+        let mut app = BitcoinAccountingApp::open(Box::new(SqliteStorage::open(&cli.book)?))?;
+        app.set_cost_basis_method(*cost_basis)?;
+        app.set_fee_policy(*fee_policy)?;
+        app.set_rate_policy(*rate_policy, max_staleness)?;
+        app.set_reporting_calendar(ReportingCalendar::new(*utc_offset, *fiscal_year_start))?;
+        app.set_min_confirmations(*min_confirmations)?;
+        println!("Initialized book {}", cli.book.display());
+        return Ok(());
+    }
 
-        // Credit entry (for sent funds)
-        if total_input > Decimal::ZERO {
-            self.accounting_entries.push(AccountingEntry {
-                date: transaction.timestamp,
-                description: format!("Sent BTC - {}", transaction.txid),
-                debit: Decimal::ZERO,
-                credit: total_input,
-            });
+    if !cli.book.exists() {
+        return Err(format!("no book at {}; run `init` first", cli.book.display()).into());
+    }
+    let mut app = BitcoinAccountingApp::open(Box::new(SqliteStorage::open(&cli.book)?))?;
+    let calendar = *app.calendar();
+    let utc_offset = calendar.utc_offset;
+
+    match cli.command {
+        Command::Init { .. } => unreachable!("handled above"),
+        Command::AddAddress { wallet, addresses } => {
+            for address in &addresses {
+                app.register_address(&wallet, address)?;
+            }
+            println!("Registered {} address(es) in wallet {}", addresses.len(), wallet);
+        }
+        Command::Import {
+            kind,
+            files,
+            wallet,
+            prior_period_adjustment,
+        } => match kind {
+            ImportKind::Transactions => {
+                for file in &files {
+                    import_transactions(&mut app, file, prior_period_adjustment.map(|date| date.start(utc_offset)))?;
+                }
+            }
+            ImportKind::OpeningBalances => {
+                for file in &files {
+                    import_opening_balances(&mut app, file)?;
+                }
+            }
+            ImportKind::Rates => {
+                for file in &files {
+                    import_rates(&mut app, file)?;
+                }
+            }
+            ImportKind::BitcoinCore => import_bitcoin_core(&mut app, &wallet, &files)?,
+        },
+        Command::Report { kind, from, to, period } => {
+            let (current, prior) = match period {
+                // A named period is compared with the one of the same kind before it
+                Some(spec) => {
+                    let period = reporting_period(&calendar, &spec)?;
+                    (fiscal_period(&calendar, period)?, fiscal_period(&calendar, period.previous())?)
+                }
+                None => {
+                    let start = from.map_or(DateTime::<Utc>::MIN_UTC, |from| from.start(utc_offset));
+                    // Without --to, end at the last instant the book has a rate for rather
+                    // than now, which would be stale under a staleness limit
+                    let end = match to {
+                        Some(to) => to.end(utc_offset),
+                        None => app.latest_activity().unwrap_or_else(Utc::now),
+                    };
+                    // The comparative period is as long as the current one and ends just before it
+                    let prior_end = start.checked_sub_signed(chrono::Duration::nanoseconds(1)).unwrap_or(start);
+                    let prior_start = prior_end.checked_sub_signed(end - start).unwrap_or(DateTime::<Utc>::MIN_UTC);
+                    (FiscalPeriod::new("current", start, end), FiscalPeriod::new("prior", prior_start, prior_end))
+                }
+            };
+            let table = match kind {
+                ReportKind::Journal => journal_report(&app, &current),
+                ReportKind::BalanceSheet => balance_sheet_report(&app.balance_sheet(current.end, prior.end)?),
+                ReportKind::IncomeStatement => income_statement_report(&app.income_statement(&current, &prior)?),
+                ReportKind::Holdings => holdings_report(&app.holdings_disclosure(current.end)?),
+                ReportKind::RollForward => roll_forward_report(&app.roll_forward(&current)?, &app.roll_forward(&prior)?),
+                ReportKind::Gains => gains_report(&app, &current)?,
+                ReportKind::Form8949 if cli.format == Format::Table => {
+                    print!("{}", app.form_8949(&current)?.line_layout());
+                    return Ok(());
+                }
+                ReportKind::Form8949 => form_8949_report(&app.form_8949(&current)?),
+                ReportKind::ScheduleD => schedule_d_report(&app.form_8949(&current)?),
+                ReportKind::UkCgt if cli.format == Format::Table => {
+                    print!("{}", app.uk_cgt_computation(&current)?.computation());
+                    return Ok(());
+                }
+                ReportKind::UkCgt => uk_cgt_report(&app.uk_cgt_computation(&current)?),
+                ReportKind::Acb => acb_report(&app.acb_computation(&current)?),
+            };
+            print!("{}", table.render(cli.format));
+        }
+        Command::Transactions => print!("{}", transactions_report(&app).render(cli.format)),
+        Command::Utxos => print!("{}", utxo_report(&app).render(cli.format)),
+        Command::Restrict {
+            outpoint,
+            kind,
+            until,
+            height,
+            counterparty,
+        } => {
+            let restriction = match (kind, until, height) {
+                (RestrictionKind::Timelocked, None, Some(height)) => Restriction::Timelocked {
+                    until: LockTime::Height(height),
+                },
+                (RestrictionKind::Timelocked, Some(until), None) => Restriction::Timelocked {
+                    until: LockTime::Time(until.start(utc_offset)),
+                },
+                (RestrictionKind::Timelocked, _, _) => return Err("a timelock needs exactly one of --until or --height".into()),
+                (RestrictionKind::Pledged, until, None) => Restriction::PledgedAsCollateral {
+                    counterparty: counterparty.unwrap_or_default(),
+                    until: until.map(|until| until.start(utc_offset)),
+                },
+                (RestrictionKind::LockUp, Some(until), None) => Restriction::LockUp { until: until.start(utc_offset) },
+                (RestrictionKind::LockUp, None, _) => return Err("a lock-up needs --until".into()),
+                (_, _, Some(_)) => return Err("--height only applies to timelocks".into()),
+            };
+            app.add_restriction(&outpoint, restriction.clone())?;
+            println!("{}: {}", outpoint, restriction);
+        }
+        Command::Unrestrict { outpoint } => {
+            app.clear_restrictions(&outpoint)?;
+            println!("Cleared restrictions on {}", outpoint);
+        }
+        Command::ClosePeriod { date, period, from, name } => {
+            let period = match (period, date) {
+                (Some(spec), _) => fiscal_period(&calendar, reporting_period(&calendar, &spec)?)?,
+                (None, Some(date)) => {
+                    let end = date.end(utc_offset);
+                    let start = match (from, app.period_closes().last()) {
+                        (Some(from), _) => from.start(utc_offset),
+                        (None, Some(last)) => last.period.end + chrono::Duration::nanoseconds(1),
+                        (None, None) => app
+                            .ledger()
+                            .entries()
+                            .iter()
+                            .map(|entry| entry.date)
+                            .chain(app.transactions().iter().map(|transaction| transaction.timestamp))
+                            .min()
+                            .map_or(end, |first| first.min(end)),
+                    };
+                    FiscalPeriod::new(&calendar.local_date(end).to_string(), start, end)
+                }
+                (None, None) => unreachable!("clap requires --date or --period"),
+            };
+            let period = match name {
+                Some(name) => FiscalPeriod::new(&name, period.start, period.end),
+                None => period,
+            };
+            let (close, remeasurement) = app.close_period(period)?;
+            let mut table = Table::new(&[
+                "period",
+                "start",
+                "end",
+                "quantity",
+                "rate",
+                "carrying_amount",
+                "fair_value",
+                "adjustment",
+                "remeasurement_entry",
+                "net_income",
+                "closing_entry",
+            ]);
+            table.push(vec![
+                close.period.name.clone(),
+                close.period.start.to_rfc3339(),
+                close.period.end.to_rfc3339(),
+                remeasurement.quantity.to_string(),
+                remeasurement.rate.to_string(),
+                remeasurement.carrying_amount.to_string(),
+                remeasurement.fair_value.to_string(),
+                remeasurement.adjustment.to_string(),
+                remeasurement.entry_id.map_or(String::new(), |id| id.to_string()),
+                close.net_income.to_string(),
+                close.entry_id.map_or(String::new(), |id| id.to_string()),
+            ]);
+            print!("{}", table.render(cli.format));
         }
+        Command::Periods => print!("{}", periods_report(&app).render(cli.format)),
+        Command::OrphanBlock { hash } => print!("{}", reversals_report(&app.orphan_block(&hash)?).render(cli.format)),
+        Command::Reverse { txid, date, by, reason } => {
+            let posted_on = date.map_or_else(Utc::now, |date| date.start(utc_offset));
+            let reversal = app.reverse_transaction(&txid, posted_on, &by, &reason)?;
+            print!("{}", reversals_report(std::slice::from_ref(&reversal)).render(cli.format));
+        }
+        Command::Reversals => print!("{}", reversals_report(app.reversals()).render(cli.format)),
+        Command::Replacements => print!("{}", replacements_report(&app.replacements()).render(cli.format)),
+    }
+    Ok(())
+}
 
-        // Fee entry
-        if transaction.fee > Decimal::ZERO {
-            self.accounting_entries.push(AccountingEntry {
-                date: transaction.timestamp,
-                description: format!("Transaction fee - {}", transaction.txid),
-                debit: Decimal::ZERO,
-                credit: transaction.fee,
-            });
+fn import_transactions(app: &mut BitcoinAccountingApp, file: &Path, adjustment_date: Option<DateTime<Utc>>) -> Result<(), Box<dyn Error>> {
+    let transactions: Vec<Transaction> = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    let total = transactions.len();
+    let mut failed = 0;
+    for transaction in transactions {
+        let txid = transaction.txid.clone();
+        let result = match adjustment_date {
+            Some(posted_on) => app.add_prior_period_adjustment(transaction, posted_on),
+            None => app.add_transaction(transaction),
+        };
+        match result {
+            Ok(()) => {
+                for replacement in app.replacements().iter().filter(|replacement| replacement.replacement == txid) {
+                    print_replacement(replacement);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", txid, err);
+                failed += 1;
+            }
         }
+    }
+    println!("Imported {} of {} transactions", total - failed, total);
+    if failed > 0 {
+        return Err(format!("{} transaction(s) were not imported", failed).into());
+    }
+    Ok(())
+}
 
-        self.transactions.push(transaction);
+fn import_opening_balances(app: &mut BitcoinAccountingApp, file: &Path) -> Result<(), Box<dyn Error>> {
+    let records: Vec<OpeningBalanceRecord> = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    for record in &records {
+        let outpoint = record.utxo.outpoint();
+        app.add_opening_balance(record.utxo.clone(), record.cost_basis)
+            .map_err(|err| format!("{}: {}", outpoint, err))?;
     }
+    println!("Imported {} opening balances", records.len());
+    Ok(())
+}
 
-    fn generate_fasb_report(&self, start_date: DateTime<Utc>, end_date: DateTime<Utc>) -> Vec<AccountingEntry> {
-        self.accounting_entries
-            .iter()
-            .filter(|entry| entry.date >= start_date && entry.date <= end_date)
-            .cloned()
-            .collect()
+fn import_bitcoin_core(app: &mut BitcoinAccountingApp, wallet: &str, files: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let mut export = CoreExport::new();
+    for file in files {
+        export
+            .add_document(&std::fs::read_to_string(file)?)
+            .map_err(|err| format!("{}: {}", file.display(), err))?;
     }
+    let summary = app.import_bitcoin_core(wallet, &export)?;
+    for err in &summary.failed {
+        eprintln!("{}", err);
+    }
+    for replacement in &summary.replaced {
+        print_replacement(replacement);
+    }
+    println!(
+        "Imported {} of {} transactions ({} skipped, {} updated, {} awaiting confirmation)",
+        summary.imported.len(),
+        export.len(),
+        summary.skipped.len(),
+        summary.updated.len(),
+        summary.unconfirmed.len()
+    );
+    if !summary.failed.is_empty() {
+        return Err(format!("{} transaction(s) were not imported", summary.failed.len()).into());
+    }
+    Ok(())
+}
 
-    fn calculate_realized_gains_losses(&self, start_date: DateTime<Utc>, end_date: DateTime<Utc>) -> Decimal {
-        let mut realized_gain_loss = Decimal::ZERO;
+fn print_replacement(replacement: &Replacement) {
+    println!(
+        "{} replaced {}: fee {} -> {} ({:+} sat)",
+        replacement.replacement,
+        replacement.replaced,
+        replacement.fee,
+        replacement.replacement_fee,
+        replacement.fee_difference()
+    );
+}
 
-        for transaction in &self.transactions {
-            if transaction.timestamp < start_date || transaction.timestamp > end_date {
+fn import_rates(app: &mut BitcoinAccountingApp, file: &Path) -> Result<(), Box<dyn Error>> {
+    let utc_offset = app.calendar().utc_offset;
+    let contents = std::fs::read_to_string(file)?;
+    let records: Vec<RateRecord> = if file.extension().is_some_and(|extension| extension == "json") {
+        serde_json::from_str(&contents)?
+    } else {
+        let mut records = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
                 continue;
             }
+            let (date, rate) = line
+                .split_once(',')
+                .ok_or_else(|| format!("line {}: expected `timestamp,rate`", index + 1))?;
+            let date = match date.trim().parse::<DateBound>() {
+                Ok(date) => date.start(utc_offset),
+                // A non-date first line is a header
+                Err(_) if index == 0 => continue,
+                Err(err) => return Err(format!("line {}: {}", index + 1, err).into()),
+            };
+            let rate = Decimal::from_str(rate.trim()).map_err(|err| format!("line {}: {}", index + 1, err))?;
+            records.push(RateRecord { date, rate });
+        }
+        records
+    };
 
-            let acquisition_cost: Decimal = transaction.inputs.iter().map(|utxo| {
-                let acquisition_rate = self.exchange_rates.get(&utxo.timestamp).unwrap_or(&Decimal::ONE);
-                utxo.amount * acquisition_rate
-            }).sum();
-
-            let sale_value: Decimal = transaction.outputs.iter().map(|utxo| {
-                let sale_rate = self.exchange_rates.get(&transaction.timestamp).unwrap_or(&Decimal::ONE);
-                utxo.amount * sale_rate
-            }).sum();
+    for record in &records {
+        app.add_exchange_rate(record.date, record.rate)?;
+    }
+    println!("Imported {} exchange rates", records.len());
+    Ok(())
+}
 
-            realized_gain_loss += sale_value - acquisition_cost;
+fn journal_report(app: &BitcoinAccountingApp, period: &FiscalPeriod) -> Table {
+    let chart = app.ledger().chart();
+    let mut table = Table::new(&["entry", "date", "description", "account", "name", "debit", "credit"]);
+    for entry in app.generate_fasb_report(period) {
+        for line in &entry.lines {
+            table.push(vec![
+                entry.id.to_string(),
+                entry.date.to_rfc3339(),
+                entry.description.clone(),
+                line.account.clone(),
+                chart.account(&line.account).map_or(String::new(), |account| account.name.clone()),
+                line.debit.to_string(),
+                line.credit.to_string(),
+            ]);
         }
+    }
+    table
+}
 
-        realized_gain_loss
+fn balance_sheet_report(sheet: &BalanceSheet) -> Table {
+    let mut table = Table::new(&["section", "account", "name", "current", "prior"]);
+    let sections = [("Assets", &sheet.assets), ("Liabilities", &sheet.liabilities), ("Equity", &sheet.equity)];
+    for (section, lines) in sections {
+        for line in lines {
+            table.push(statement_row(section, line));
+        }
     }
+    table.push_footer(statement_row("", &sheet.total_assets));
+    table.push_footer(statement_row("", &sheet.total_liabilities_and_equity));
+    table
+}
 
-    fn add_exchange_rate(&mut self, date: DateTime<Utc>, rate: Decimal) {
-        self.exchange_rates.insert(date, rate);
+fn income_statement_report(statement: &IncomeStatement) -> Table {
+    let mut table = Table::new(&["section", "account", "name", "current", "prior"]);
+    let sections = [("Revenue", &statement.revenue), ("Gains", &statement.gains), ("Expenses", &statement.expenses)];
+    for (section, lines) in sections {
+        for line in lines {
+            table.push(statement_row(section, line));
+        }
     }
+    table.push_footer(statement_row("", &statement.net_income));
+    table
 }
 
-fn main() {
-    let mut app = BitcoinAccountingApp::new();
-
-    // Example usage
-    let utxo1 = UTXO {
-        txid: "tx1".to_string(),
-        vout: 0,
-        amount: dec!(1.0), // 1 BTC
-        address: "addr1".to_string(),
-        confirmations: 6,
-        spendable: true,
-        timestamp: Utc::now(),
+fn roll_forward_report(current: &RollForward, prior: &RollForward) -> Table {
+    let row = |label: &str, quantities: Option<(Amount, Amount)>, current: Decimal, prior: Decimal| {
+        let (current_quantity, prior_quantity) = quantities.map_or((String::new(), String::new()), |(c, p)| (c.to_string(), p.to_string()));
+        vec![label.to_string(), current_quantity, current.to_string(), prior_quantity, prior.to_string()]
     };
+    let mut table = Table::new(&["line", "quantity", "amount", "prior_quantity", "prior_amount"]);
+    table.push(row(
+        "Opening fair value",
+        Some((current.opening_quantity, prior.opening_quantity)),
+        current.opening_fair_value,
+        prior.opening_fair_value,
+    ));
+    table.push(row(
+        "Acquisitions",
+        Some((current.acquired_quantity, prior.acquired_quantity)),
+        current.acquisitions,
+        prior.acquisitions,
+    ));
+    table.push(row(
+        "Disposals at cost basis",
+        Some((current.disposed_quantity, prior.disposed_quantity)),
+        credit_balance(current.disposals_cost_basis),
+        credit_balance(prior.disposals_cost_basis),
+    ));
+    table.push(row("Corrections", None, credit_balance(current.corrections), credit_balance(prior.corrections)));
+    table.push(row("Unrealized gain/loss", None, current.unrealized_gain_loss, prior.unrealized_gain_loss));
+    table.push(row(
+        "Closing fair value",
+        Some((current.closing_quantity, prior.closing_quantity)),
+        current.closing_fair_value,
+        prior.closing_fair_value,
+    ));
+    table.push(row("Disposal proceeds", None, current.disposal_proceeds, prior.disposal_proceeds));
+    table.push(row("Realized gain/loss", None, current.realized_gain_loss, prior.realized_gain_loss));
+    table
+}
 
-    let utxo2 = UTXO {
-        txid: "tx2".to_string(),
-        vout: 1,
-        amount: dec!(0.5), // 0.5 BTC
-        address: "addr2".to_string(),
-        confirmations: 6,
-        spendable: true,
-        timestamp: Utc::now(),
-    };
+/// Negates an amount shown as a deduction without printing `-0`.
+fn credit_balance(balance: Decimal) -> Decimal {
+    if balance.is_zero() {
+        Decimal::ZERO
+    } else {
+        -balance
+    }
+}
+
+fn holdings_report(disclosure: &HoldingsDisclosure) -> Table {
+    let mut table = Table::new(&["holding", "outpoint", "units", "cost_basis", "fair_value", "restriction", "until"]);
+    table.push(vec![
+        disclosure.asset.clone(),
+        String::new(),
+        disclosure.units.to_string(),
+        disclosure.cost_basis.to_string(),
+        disclosure.fair_value.to_string(),
+        String::new(),
+        String::new(),
+    ]);
+    for holding in &disclosure.restricted {
+        let restriction = match &holding.restriction {
+            Restriction::Timelocked { .. } => "timelocked".to_string(),
+            Restriction::PledgedAsCollateral { counterparty, .. } => format!("pledged as collateral to {}", counterparty),
+            Restriction::LockUp { .. } => "lock-up".to_string(),
+        };
+        table.push(vec![
+            format!("{} (restricted)", disclosure.asset),
+            holding.outpoint.to_string(),
+            holding.units.to_string(),
+            holding.cost_basis.to_string(),
+            holding.fair_value.to_string(),
+            restriction,
+            holding.restriction.ends().unwrap_or_default(),
+        ]);
+    }
+    table
+}
+
+fn statement_row(section: &str, line: &StatementLine) -> Vec<String> {
+    vec![
+        section.to_string(),
+        line.account.clone().unwrap_or_default(),
+        line.label.clone(),
+        line.current.to_string(),
+        line.prior.to_string(),
+    ]
+}
+
+fn gains_report(app: &BitcoinAccountingApp, period: &FiscalPeriod) -> Result<Table, Box<dyn Error>> {
+    let mut table = Table::new(&[
+        "txid",
+        "disposed_at",
+        "lot",
+        "acquired_at",
+        "term",
+        "quantity",
+        "proceeds",
+        "cost_basis",
+        "gain_loss",
+    ]);
+    let realized = app.realized_gains_by_lot(period)?;
+    for lot in &realized {
+        table.push(vec![
+            lot.txid.clone(),
+            lot.disposed_at.to_rfc3339(),
+            lot.outpoint.to_string(),
+            lot.acquired_at.to_rfc3339(),
+            lot.term.to_string(),
+            lot.quantity.to_string(),
+            lot.proceeds.to_string(),
+            lot.cost_basis.to_string(),
+            lot.gain_loss.to_string(),
+        ]);
+    }
+    let gains: RealizedGains = realized.iter().collect();
+    for (label, amount) in [("Short-term", gains.short_term), ("Long-term", gains.long_term), ("Total", gains.total())] {
+        let mut footer = vec![String::new(); 8];
+        footer[0] = label.to_string();
+        footer.push(amount.to_string());
+        table.push_footer(footer);
+    }
+    Ok(table)
+}
+
+fn form_8949_report(form: &Form8949) -> Table {
+    let mut table = Table::new(&[
+        "part",
+        "description",
+        "date_acquired",
+        "date_sold",
+        "proceeds",
+        "cost_basis",
+        "adjustment_code",
+        "adjustment",
+        "gain_loss",
+        "txid",
+        "lot",
+    ]);
+    for (part, rows) in [("I", &form.short_term), ("II", &form.long_term)] {
+        for row in rows {
+            table.push(vec![
+                part.to_string(),
+                row.description.clone(),
+                row.date_acquired.format("%m/%d/%Y").to_string(),
+                row.date_sold.format("%m/%d/%Y").to_string(),
+                row.proceeds.to_string(),
+                row.cost_basis.to_string(),
+                row.adjustment_code.clone(),
+                row.adjustment.to_string(),
+                row.gain_loss.to_string(),
+                row.txid.clone(),
+                row.outpoint.to_string(),
+            ]);
+        }
+    }
+    table
+}
 
-    let transaction = Transaction {
-        txid: "tx3".to_string(),
-        timestamp: Utc::now(),
-        inputs: vec![utxo1],
-        outputs: vec![utxo2.clone()],
-        fee: dec!(0.0001), // 0.0001 BTC
+fn schedule_d_report(form: &Form8949) -> Table {
+    let schedule = form.schedule_d();
+    let mut table = Table::new(&["line", "description", "proceeds", "cost_basis", "adjustment", "gain_loss"]);
+    let row = |line: &str, description: &str, totals: Form8949Totals| {
+        vec![
+            line.to_string(),
+            description.to_string(),
+            totals.proceeds.to_string(),
+            totals.cost_basis.to_string(),
+            totals.adjustment.to_string(),
+            totals.gain_loss.to_string(),
+        ]
     };
+    table.push(row("3", "Short-term from Form 8949, box C", schedule.line_3));
+    table.push(row("10", "Long-term from Form 8949, box F", schedule.line_10));
+    table
+}
+
+fn uk_cgt_report(computation: &CgtComputation) -> Table {
+    let mut table = Table::new(&["date", "txids", "rule", "quantity", "proceeds", "allowable_cost", "gain_loss"]);
+    for disposal in &computation.disposals {
+        for matched in &disposal.matches {
+            table.push(vec![
+                disposal.date.to_string(),
+                disposal.txids.join(" "),
+                matched.rule.to_string(),
+                matched.quantity.to_string(),
+                matched.proceeds.to_string(),
+                matched.allowable_cost.to_string(),
+                matched.gain_loss.to_string(),
+            ]);
+        }
+    }
+    table
+}
+
+fn acb_report(computation: &AcbComputation) -> Table {
+    let mut table = Table::new(&[
+        "date",
+        "txid",
+        "kind",
+        "units",
+        "amount",
+        "acb_disposed",
+        "superficial_loss",
+        "gain_loss",
+        "units_held",
+        "total_acb",
+        "acb_per_unit",
+    ]);
+    for entry in &computation.entries {
+        let kind = match entry.kind {
+            AcbEventKind::Acquisition => "acquisition",
+            AcbEventKind::Disposition => "disposition",
+        };
+        table.push(vec![
+            entry.date.to_rfc3339(),
+            entry.txid.clone(),
+            kind.to_string(),
+            entry.units.to_string(),
+            entry.amount.to_string(),
+            entry.acb_disposed.to_string(),
+            entry.superficial_loss.to_string(),
+            entry.gain_loss.to_string(),
+            entry.units_held.to_string(),
+            entry.total_acb.to_string(),
+            entry.acb_per_unit().round_dp(8).to_string(),
+        ]);
+    }
+    let mut footer = vec![String::new(); 11];
+    footer[0] = "Total".to_string();
+    footer[4] = computation.proceeds().to_string();
+    footer[5] = computation.acb_disposed().to_string();
+    footer[6] = computation.superficial_losses().to_string();
+    footer[7] = computation.gain_loss().to_string();
+    footer[8] = computation.closing_units.to_string();
+    footer[9] = computation.closing_acb.to_string();
+    table.push_footer(footer);
+    table
+}
 
-    app.add_transaction(transaction);
+fn transactions_report(app: &BitcoinAccountingApp) -> Table {
+    let mut table = Table::new(&["txid", "timestamp", "block", "height", "confirmations", "fee", "status"]);
+    let recorded = app.transactions().iter().map(|transaction| {
+        let status = app.transaction_status(&transaction.txid).expect("recorded transactions have a status");
+        (transaction, status)
+    });
+    let reversed = app
+        .reversals()
+        .iter()
+        .map(|reversal| (&reversal.transaction, TransactionStatus::Reversed(reversal.reason.clone())));
+    for (transaction, status) in recorded.chain(reversed) {
+        table.push(vec![
+            transaction.txid.clone(),
+            transaction.timestamp.to_rfc3339(),
+            transaction.block.as_ref().map_or(String::new(), |block| block.hash.clone()),
+            transaction.block.as_ref().map_or(String::new(), |block| block.height.to_string()),
+            transaction.confirmations().to_string(),
+            transaction.fee.to_string(),
+            status.to_string(),
+        ]);
+    }
+    table
+}
 
-    // Add exchange rates
-    app.add_exchange_rate(Utc::now(), dec!(50000)); // Assume 1 BTC = $50,000 USD
+fn utxo_report(app: &BitcoinAccountingApp) -> Table {
+    let mut utxos: Vec<_> = app.utxo_set().iter().collect();
+    utxos.sort_by(|a, b| a.0.cmp(b.0));
+    let mut table = Table::new(&["outpoint", "wallet", "address", "amount", "confirmations", "timestamp"]);
+    for (outpoint, utxo) in utxos {
+        table.push(vec![
+            outpoint.to_string(),
+            app.wallets().wallet_of(&utxo.address).unwrap_or_default().to_string(),
+            utxo.address.clone(),
+            utxo.amount.to_string(),
+            utxo.confirmations.to_string(),
+            utxo.timestamp.to_rfc3339(),
+        ]);
+    }
+    table
+}
 
-    // Generate FASB report
-    let report = app.generate_fasb_report(Utc::now() - chrono::Duration::days(1), Utc::now());
-    println!("FASB Report: {:?}", report);
+fn periods_report(app: &BitcoinAccountingApp) -> Table {
+    let mut table = Table::new(&["period", "start", "end", "closed_at", "net_income", "closing_entry"]);
+    for close in app.period_closes() {
+        table.push(vec![
+            close.period.name.clone(),
+            close.period.start.to_rfc3339(),
+            close.period.end.to_rfc3339(),
+            close.closed_at.to_rfc3339(),
+            close.net_income.to_string(),
+            close.entry_id.map_or(String::new(), |id| id.to_string()),
+        ]);
+    }
+    table
+}
+
+fn reversals_report(reversals: &[Reversal]) -> Table {
+    let mut table = Table::new(&["txid", "timestamp", "reason", "recorded_at", "reversed_entries", "reversal_entries"]);
+    let ids = |ids: &[u64]| ids.iter().map(u64::to_string).collect::<Vec<_>>().join(" ");
+    for reversal in reversals {
+        table.push(vec![
+            reversal.transaction.txid.clone(),
+            reversal.transaction.timestamp.to_rfc3339(),
+            reversal.reason.to_string(),
+            reversal.recorded_at.to_rfc3339(),
+            ids(&reversal.reversed_entries),
+            ids(&reversal.entry_ids),
+        ]);
+    }
+    table
+}
 
-    // Calculate realized gains/losses
-    let gains_losses = app.calculate_realized_gains_losses(Utc::now() - chrono::Duration::days(1), Utc::now());
-    println!("Realized Gains/Losses: ${}", gains_losses);
-}
\ No newline at end of file
+fn replacements_report(replacements: &[Replacement]) -> Table {
+    let mut table = Table::new(&["replaced", "replacement", "fee", "replacement_fee", "fee_difference_sat"]);
+    for replacement in replacements {
+        table.push(vec![
+            replacement.replaced.clone(),
+            replacement.replacement.clone(),
+            replacement.fee.to_string(),
+            replacement.replacement_fee.to_string(),
+            replacement.fee_difference().to_string(),
+        ]);
+    }
+    table
+}
@@ -0,0 +1,114 @@
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::error::AccountingError;
+use crate::ledger::{AccountRole, JournalEntry};
+use crate::outpoint;
+use crate::rates::RateError;
+use crate::{BitcoinAccountingApp, UTXO};
+
+/// A UTXO held before the book starts, brought in at its carried-over cost basis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpeningBalance {
+    pub utxo: UTXO,
+    pub cost_basis: Decimal,
+    /// Journal entry bringing it into Digital Assets, if its basis is non-zero.
+    pub entry_id: Option<u64>,
+}
+
+impl BitcoinAccountingApp {
+    /// UTXOs brought in as opening balances, in the order added.
+    pub fn opening_balances(&self) -> &[OpeningBalance] {
+        &self.opening_balances
+    }
+
+    /// Brings in `utxo`, funded by a transaction the book does not record, as an
+    /// opening balance.
+    ///
+    /// It opens a lot acquired at the UTXO's timestamp, at `cost_basis` or, if none is
+    /// given, its fair value then, and posts that basis to Digital Assets against
+    /// Retained Earnings on the same date, which must fall in an open period. The UTXO
+    /// must be paid to an owned address and not already be in the book, and may not change
+    /// the lots relieved by a disposal already posted; recorded transactions may then
+    /// spend it.
+    pub fn add_opening_balance(&mut self, utxo: UTXO, cost_basis: Option<Decimal>) -> Result<OpeningBalance, AccountingError> {
+        if !outpoint::is_valid_txid(&utxo.txid) {
+            return Err(AccountingError::InvalidTxid(utxo.txid.clone()));
+        }
+        if utxo.amount > Amount::MAX_MONEY {
+            return Err(AccountingError::AmountOutOfRange {
+                txid: utxo.txid.clone(),
+                amount: utxo.amount,
+            });
+        }
+        if !self.wallets.is_owned(&utxo.address) {
+            return Err(AccountingError::UnownedAddress(utxo.address.clone()));
+        }
+        let outpoint = utxo.outpoint();
+        if self.transactions.iter().any(|transaction| transaction.txid == utxo.txid) {
+            return Err(AccountingError::DuplicateTransaction(utxo.txid.clone()));
+        }
+        let in_book = self.utxo_set.contains_key(&outpoint)
+            || self
+                .transactions
+                .iter()
+                .any(|transaction| transaction.inputs.iter().any(|input| input.outpoint() == outpoint));
+        if in_book {
+            return Err(AccountingError::OpeningBalanceExists(outpoint));
+        }
+        self.check_unlocked(utxo.timestamp)?;
+        let cost_basis = match cost_basis {
+            Some(cost_basis) => cost_basis,
+            None => {
+                let rate = self
+                    .exchange_rates
+                    .lookup(utxo.timestamp)
+                    .ok_or_else(|| RateError::MissingRates(vec![utxo.timestamp]))?;
+                utxo.amount.to_btc() * rate
+            }
+        };
+
+        let chart = self.ledger.chart();
+        let mut entry = JournalEntry::new(utxo.timestamp, format!("Opening balance - {}", outpoint), Some(utxo.txid.clone()));
+        entry.push_debit(chart.code_for(AccountRole::DigitalAssets), cost_basis);
+        entry.push_credit(chart.code_for(AccountRole::RetainedEarnings), cost_basis);
+        let entry = if entry.lines.is_empty() {
+            None
+        } else {
+            self.ledger.validate(&entry)?;
+            entry.id = self.ledger.next_id();
+            Some(entry)
+        };
+        let opening = OpeningBalance {
+            utxo,
+            cost_basis,
+            entry_id: entry.as_ref().map(|entry| entry.id),
+        };
+
+        // Replay with the opening balance so the stored lots include it
+        let posted = self.replay_lots()?;
+        self.opening_balances.push(opening.clone());
+        let replay = match self.replay_lots() {
+            Ok(replay) => replay,
+            Err(err) => {
+                self.opening_balances.pop();
+                return Err(err.into());
+            }
+        };
+        if let Some(txid) = posted.changed_disposal(&replay) {
+            let txid = txid.to_string();
+            self.opening_balances.pop();
+            return Err(AccountingError::DisposalChangesPosted(txid));
+        }
+        if let Err(err) = self.storage.record_opening_balance(&opening, entry.as_ref(), replay.book.open_lots()) {
+            self.opening_balances.pop();
+            return Err(err.into());
+        }
+        if let Some(entry) = entry {
+            self.ledger.post(entry)?;
+        }
+        self.utxo_set.insert(outpoint, opening.utxo.clone());
+        Ok(opening)
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Reference to one output of a transaction, written `txid:vout`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: String,
+    pub vout: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutPointError {
+    /// Not 64 hex digits.
+    InvalidTxid(String),
+    /// Not in `txid:vout` form.
+    Malformed(String),
+}
+
+impl fmt::Display for OutPointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutPointError::InvalidTxid(txid) => write!(f, "'{}' is not a 64-digit hex txid", txid),
+            OutPointError::Malformed(s) => write!(f, "'{}' is not a txid:vout outpoint", s),
+        }
+    }
+}
+
+impl std::error::Error for OutPointError {}
+
+/// Whether `txid` is 32 bytes of hex, as bitcoin displays transaction ids.
+pub fn is_valid_txid(txid: &str) -> bool {
+    txid.len() == 64 && txid.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+impl OutPoint {
+    /// Outpoint with a checked txid.
+    pub fn new(txid: &str, vout: u32) -> Result<Self, OutPointError> {
+        if !is_valid_txid(txid) {
+            return Err(OutPointError::InvalidTxid(txid.to_string()));
+        }
+        Ok(OutPoint {
+            txid: txid.to_string(),
+            vout,
+        })
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+impl FromStr for OutPoint {
+    type Err = OutPointError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, vout) = s.split_once(':').ok_or_else(|| OutPointError::Malformed(s.to_string()))?;
+        let vout = vout.parse().map_err(|_| OutPointError::Malformed(s.to_string()))?;
+        OutPoint::new(txid, vout)
+    }
+}
+
+impl Serialize for OutPoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OutPoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_displays_txid_vout() {
+        let text = format!("{}:7", "ab".repeat(32));
+        let outpoint: OutPoint = text.parse().unwrap();
+        assert_eq!(outpoint, OutPoint::new(&"ab".repeat(32), 7).unwrap());
+        assert_eq!(outpoint.to_string(), text);
+        assert_eq!(serde_json::to_string(&outpoint).unwrap(), format!("\"{}\"", text));
+        assert_eq!(serde_json::from_str::<OutPoint>(&format!("\"{}\"", text)).unwrap(), outpoint);
+    }
+
+    #[test]
+    fn rejects_malformed_outpoints() {
+        let txid = "ab".repeat(32);
+        assert_eq!(txid.parse::<OutPoint>(), Err(OutPointError::Malformed(txid.clone())));
+        assert_eq!(format!("{}:x", txid).parse::<OutPoint>(), Err(OutPointError::Malformed(format!("{}:x", txid))));
+        assert_eq!("abc:0".parse::<OutPoint>(), Err(OutPointError::InvalidTxid("abc".to_string())));
+        let not_hex = "zz".repeat(32);
+        assert_eq!(OutPoint::new(&not_hex, 0), Err(OutPointError::InvalidTxid(not_hex)));
+        assert!(serde_json::from_str::<OutPoint>("\"abc:0\"").is_err());
+    }
+}
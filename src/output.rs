@@ -0,0 +1,109 @@
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Rows of already-formatted cells, rendered in any output format.
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+    /// Summary rows shown only in table output, so JSON and CSV stay one record per row.
+    footer: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Table {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+            footer: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn push_footer(&mut self, row: Vec<String>) {
+        self.footer.push(row);
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Table => self.render_table(),
+            Format::Json => self.render_json(),
+            Format::Csv => self.render_csv(),
+        }
+    }
+
+    fn render_table(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.chars().count()).collect();
+        for row in self.rows.iter().chain(&self.footer) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let format_row = |cells: &[String]| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        let headers: Vec<String> = self.headers.iter().map(|header| header.to_string()).collect();
+        let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+        let mut lines = vec![format_row(&headers), format_row(&rule)];
+        lines.extend(self.rows.iter().map(|row| format_row(row)));
+        if !self.footer.is_empty() {
+            lines.push(format_row(&rule));
+            lines.extend(self.footer.iter().map(|row| format_row(row)));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    fn render_json(&self) -> String {
+        let records: Vec<Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let record: Map<String, Value> = self
+                    .headers
+                    .iter()
+                    .zip(row)
+                    .map(|(header, cell)| (header.to_string(), Value::String(cell.clone())))
+                    .collect();
+                Value::Object(record)
+            })
+            .collect();
+        serde_json::to_string_pretty(&records).expect("string records serialize to JSON") + "\n"
+    }
+
+    fn render_csv(&self) -> String {
+        let mut out = String::new();
+        let headers: Vec<String> = self.headers.iter().map(|header| header.to_string()).collect();
+        for row in std::iter::once(&headers).chain(&self.rows) {
+            let cells: Vec<String> = row.iter().map(|cell| csv_escape(cell)).collect();
+            out.push_str(&cells.join(","));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::error::AccountingError;
+use crate::fair_value::Remeasurement;
+use crate::ledger::{AccountRole, AccountType, EntryKind, JournalEntry};
+use crate::BitcoinAccountingApp;
+
+/// A named fiscal period covering `start..=end`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FiscalPeriod {
+    pub name: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl FiscalPeriod {
+    pub fn new(name: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        FiscalPeriod {
+            name: name.to_string(),
+            start,
+            end,
+        }
+    }
+
+    pub fn contains(&self, date: DateTime<Utc>) -> bool {
+        date >= self.start && date <= self.end
+    }
+}
+
+/// A closed and locked fiscal period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodClose {
+    pub period: FiscalPeriod,
+    /// When the close was recorded.
+    pub closed_at: DateTime<Utc>,
+    /// Debit-positive balance of every account at the period end, before closing entries.
+    pub balances: BTreeMap<String, Decimal>,
+    /// Revenue and gains less expenses closed to retained earnings.
+    pub net_income: Decimal,
+    /// Journal entry closing revenue and expense accounts, if any had a balance.
+    pub entry_id: Option<u64>,
+}
+
+impl BitcoinAccountingApp {
+    pub fn period_closes(&self) -> &[PeriodClose] {
+        &self.period_closes
+    }
+
+    /// The closed period locking `date`, if any: the one it falls in or, for a date
+    /// before or between closed periods, the next one closed after it. Everything up to
+    /// the end of the latest closed period is locked.
+    pub fn locked_period(&self, date: DateTime<Utc>) -> Option<&FiscalPeriod> {
+        // Periods are closed in order
+        self.period_closes
+            .iter()
+            .map(|close| &close.period)
+            .find(|period| date <= period.end)
+    }
+
+    pub(crate) fn check_unlocked(&self, date: DateTime<Utc>) -> Result<(), AccountingError> {
+        match self.locked_period(date) {
+            Some(period) => Err(AccountingError::PeriodLocked {
+                date,
+                period: period.name.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Closes `period`: remeasures holdings to fair value at its end, snapshots the
+    /// trial balance, closes revenue and expense accounts to retained earnings and
+    /// locks it.
+    ///
+    /// Periods are closed in order, so `period` must start after the end of the last
+    /// one closed. The remeasurement and closing entries are both built and validated
+    /// before either is stored, and are stored with the lock in one write. Once locked,
+    /// transactions, rates and remeasurements dated inside it or any time before it are
+    /// rejected; late activity is booked with `add_prior_period_adjustment`.
+    pub fn close_period(&mut self, period: FiscalPeriod) -> Result<(PeriodClose, Remeasurement), AccountingError> {
+        if period.start > period.end {
+            return Err(AccountingError::InvalidPeriod(period.name));
+        }
+        if let Some(close) = self
+            .period_closes
+            .iter()
+            .find(|close| close.period.name == period.name || close.period.end >= period.start)
+        {
+            return Err(AccountingError::PeriodOverlap {
+                period: period.name,
+                closed: close.period.name.clone(),
+            });
+        }
+
+        let (remeasurement, remeasurement_entry) = self.prepare_remeasurement(period.end)?;
+        let mut balances: BTreeMap<String, Decimal> = self
+            .trial_balance(period.end)
+            .into_iter()
+            .map(|(account, balance)| (account.code, balance))
+            .collect();
+        // The remeasurement is not posted yet, but the snapshot and closing entry include it
+        for line in remeasurement_entry.iter().flat_map(|entry| &entry.lines) {
+            *balances.entry(line.account.clone()).or_default() += line.debit - line.credit;
+        }
+
+        let chart = self.ledger.chart();
+        let mut entry = JournalEntry::new(period.end, format!("Close period {}", period.name), None);
+        entry.kind = EntryKind::Closing;
+        let mut net_income = Decimal::ZERO;
+        for account in chart
+            .accounts()
+            .iter()
+            .filter(|account| matches!(account.account_type, AccountType::Revenue | AccountType::Expense))
+        {
+            let balance = balances[&account.code];
+            entry.push_credit(&account.code, balance);
+            net_income -= balance;
+        }
+        entry.push_credit(chart.code_for(AccountRole::RetainedEarnings), net_income);
+        let entry = if entry.lines.is_empty() {
+            None
+        } else {
+            self.ledger.validate(&entry)?;
+            entry.id = self.ledger.next_id() + u64::from(remeasurement_entry.is_some());
+            Some(entry)
+        };
+
+        let close = PeriodClose {
+            period,
+            closed_at: Utc::now(),
+            balances,
+            net_income,
+            entry_id: entry.as_ref().map(|entry| entry.id),
+        };
+        let entries: Vec<JournalEntry> = remeasurement_entry.into_iter().chain(entry).collect();
+        self.storage.record_period_close(&close, &remeasurement, &entries)?;
+        for entry in entries {
+            self.ledger.post(entry)?;
+        }
+        self.remeasurements.push(remeasurement.clone());
+        self.period_closes.push(close.clone());
+        Ok((close, remeasurement))
+    }
+}
@@ -0,0 +1,249 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How a rate is chosen for an instant that may not have an exact observation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatePolicy {
+    /// Only an observation at exactly the requested instant is used.
+    Exact,
+    /// The latest observation at or before the requested instant.
+    NearestPrior,
+    /// Linear interpolation between the surrounding observations.
+    Interpolate,
+    /// The last observation of the calendar day, in the given timezone, containing the instant.
+    DailyClose(FixedOffset),
+}
+
+impl fmt::Display for RatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RatePolicy::Exact => f.write_str("exact"),
+            RatePolicy::NearestPrior => f.write_str("nearest-prior"),
+            RatePolicy::Interpolate => f.write_str("interpolate"),
+            RatePolicy::DailyClose(offset) => write!(f, "daily-close@{}", offset),
+        }
+    }
+}
+
+impl FromStr for RatePolicy {
+    type Err = String;
+
+    /// Parses the `Display` form, e.g. `nearest-prior` or `daily-close@-05:00`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(RatePolicy::Exact),
+            "nearest-prior" => Ok(RatePolicy::NearestPrior),
+            "interpolate" => Ok(RatePolicy::Interpolate),
+            other => {
+                let offset = other
+                    .strip_prefix("daily-close@")
+                    .ok_or_else(|| format!("unknown rate policy '{}'", other))?;
+                offset
+                    .parse::<FixedOffset>()
+                    .map(RatePolicy::DailyClose)
+                    .map_err(|err| format!("invalid UTC offset '{}': {}", offset, err))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateError {
+    /// No usable rate exists for each of the listed instants.
+    MissingRates(Vec<DateTime<Utc>>),
+}
+
+impl fmt::Display for RateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateError::MissingRates(timestamps) => {
+                write!(f, "no usable exchange rate for ")?;
+                for (index, timestamp) in timestamps.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", timestamp.to_rfc3339())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RateError {}
+
+/// Ordered BTC/fiat rate history.
+#[derive(Debug, Clone)]
+pub struct RateStore {
+    rates: BTreeMap<DateTime<Utc>, Decimal>,
+    policy: RatePolicy,
+    /// Maximum distance between the requested instant and the observation used.
+    max_staleness: Option<Duration>,
+}
+
+impl RateStore {
+    pub fn new(policy: RatePolicy, max_staleness: Option<Duration>) -> Self {
+        RateStore {
+            rates: BTreeMap::new(),
+            policy,
+            max_staleness,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: RatePolicy, max_staleness: Option<Duration>) {
+        self.policy = policy;
+        self.max_staleness = max_staleness;
+    }
+
+    /// Records `rate` at `date`, returning the observation it replaced.
+    pub fn insert(&mut self, date: DateTime<Utc>, rate: Decimal) -> Option<Decimal> {
+        self.rates.insert(date, rate)
+    }
+
+    pub fn remove(&mut self, date: DateTime<Utc>) -> Option<Decimal> {
+        self.rates.remove(&date)
+    }
+
+    /// The date of the latest observation.
+    pub fn latest(&self) -> Option<DateTime<Utc>> {
+        self.rates.keys().next_back().copied()
+    }
+
+    /// The observation recorded at exactly `date`, whatever the policy.
+    pub fn get(&self, date: DateTime<Utc>) -> Option<Decimal> {
+        self.rates.get(&date).copied()
+    }
+
+    pub fn lookup(&self, at: DateTime<Utc>) -> Option<Decimal> {
+        match self.policy {
+            RatePolicy::Exact => self.rates.get(&at).copied(),
+            RatePolicy::NearestPrior => {
+                let (&date, &rate) = self.rates.range(..=at).next_back()?;
+                self.fresh(at - date).then_some(rate)
+            }
+            RatePolicy::Interpolate => {
+                let (&before, &before_rate) = self.rates.range(..=at).next_back()?;
+                if before == at {
+                    return Some(before_rate);
+                }
+                let (&after, &after_rate) = self.rates.range(at..).next()?;
+                if !self.fresh(at - before) || !self.fresh(after - at) {
+                    return None;
+                }
+                let elapsed = Decimal::from((at - before).num_milliseconds());
+                let span = Decimal::from((after - before).num_milliseconds());
+                Some((before_rate + (after_rate - before_rate) * elapsed / span).round_dp(8))
+            }
+            RatePolicy::DailyClose(offset) => {
+                let local_date = at.with_timezone(&offset).date_naive();
+                let day_end = local_date
+                    .succ_opt()?
+                    .and_time(NaiveTime::MIN)
+                    .and_local_timezone(offset)
+                    .single()?
+                    .with_timezone(&Utc);
+                let (&date, &rate) = self.rates.range(..day_end).next_back()?;
+                self.fresh(day_end - date).then_some(rate)
+            }
+        }
+    }
+
+    fn fresh(&self, age: Duration) -> bool {
+        self.max_staleness.is_none_or(|limit| age <= limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    /// Observations of 40k at noon on the 1st and 50k at noon on the 3rd.
+    fn store(policy: RatePolicy, max_staleness: Option<Duration>) -> RateStore {
+        let mut store = RateStore::new(policy, max_staleness);
+        store.insert(at(1, 12), dec!(40000));
+        store.insert(at(3, 12), dec!(50000));
+        store
+    }
+
+    #[test]
+    fn exact_needs_an_observation_at_the_instant() {
+        let store = store(RatePolicy::Exact, None);
+        assert_eq!(store.lookup(at(1, 12)), Some(dec!(40000)));
+        assert_eq!(store.lookup(at(2, 12)), None);
+    }
+
+    #[test]
+    fn nearest_prior_takes_the_latest_earlier_observation() {
+        let store = store(RatePolicy::NearestPrior, None);
+        assert_eq!(store.lookup(at(1, 11)), None);
+        assert_eq!(store.lookup(at(2, 12)), Some(dec!(40000)));
+        assert_eq!(store.lookup(at(4, 0)), Some(dec!(50000)));
+    }
+
+    #[test]
+    fn interpolate_is_linear_between_observations() {
+        let store = store(RatePolicy::Interpolate, None);
+        assert_eq!(store.lookup(at(2, 12)), Some(dec!(45000)));
+        assert_eq!(store.lookup(at(2, 0)), Some(dec!(42500)));
+        assert_eq!(store.lookup(at(3, 12)), Some(dec!(50000)));
+        assert_eq!(store.lookup(at(4, 0)), None);
+    }
+
+    #[test]
+    fn daily_close_takes_the_last_observation_of_the_local_day() {
+        let mut store = store(RatePolicy::DailyClose(FixedOffset::east_opt(0).unwrap()), None);
+        store.insert(at(1, 23), dec!(41000));
+        assert_eq!(store.lookup(at(1, 0)), Some(dec!(41000)));
+        assert_eq!(store.lookup(at(2, 12)), Some(dec!(41000)));
+
+        // At UTC-05:00, 03:00 UTC on the 1st is still the 31st, with no observations yet
+        let new_york = RatePolicy::DailyClose(FixedOffset::west_opt(5 * 3600).unwrap());
+        store.set_policy(new_york, None);
+        assert_eq!(store.lookup(at(1, 3)), None);
+        assert_eq!(store.lookup(at(2, 3)), Some(dec!(41000)));
+        assert_eq!(store.lookup(at(2, 12)), Some(dec!(41000)));
+        assert_eq!(store.lookup(at(3, 6)), Some(dec!(50000)));
+    }
+
+    #[test]
+    fn stale_observations_are_not_used() {
+        let nearest_prior = store(RatePolicy::NearestPrior, Some(Duration::hours(24)));
+        assert_eq!(nearest_prior.lookup(at(2, 12)), Some(dec!(40000)));
+        assert_eq!(nearest_prior.lookup(at(2, 13)), None);
+
+        let interpolate = store(RatePolicy::Interpolate, Some(Duration::hours(24)));
+        assert_eq!(interpolate.lookup(at(2, 12)), Some(dec!(45000)));
+        assert_eq!(interpolate.lookup(at(2, 13)), None);
+    }
+
+    #[test]
+    fn latest_is_the_last_observation() {
+        let mut store = store(RatePolicy::Exact, None);
+        assert_eq!(store.latest(), Some(at(3, 12)));
+        assert_eq!(store.insert(at(3, 12), dec!(51000)), Some(dec!(50000)));
+        assert_eq!(store.remove(at(3, 12)), Some(dec!(51000)));
+        assert_eq!(store.latest(), Some(at(1, 12)));
+    }
+
+    #[test]
+    fn policy_round_trips_through_display() {
+        for policy in [
+            RatePolicy::Exact,
+            RatePolicy::NearestPrior,
+            RatePolicy::Interpolate,
+            RatePolicy::DailyClose(FixedOffset::west_opt(5 * 3600).unwrap()),
+        ] {
+            assert_eq!(policy.to_string().parse::<RatePolicy>(), Ok(policy));
+        }
+        assert!("daily-close@nowhere".parse::<RatePolicy>().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::amount::Amount;
+use crate::error::AccountingError;
+use crate::reversal::{ReversalPlan, ReversalReason};
+use crate::{BitcoinAccountingApp, Transaction};
+
+/// Where a transaction stands in the book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransactionStatus {
+    /// Booked without confirmations; a confirmed transaction spending any of the same
+    /// outputs replaces it.
+    Pending,
+    Confirmed,
+    /// Taken out of the book.
+    Reversed(ReversalReason),
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionStatus::Pending => f.write_str("pending"),
+            TransactionStatus::Confirmed => f.write_str("confirmed"),
+            TransactionStatus::Reversed(reason) => write!(f, "reversed: {}", reason),
+        }
+    }
+}
+
+/// A pending transaction voided by a confirmed one spending the same outputs, such as
+/// a replace-by-fee bump or a double spend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replacement {
+    pub replaced: String,
+    pub replacement: String,
+    pub fee: Amount,
+    pub replacement_fee: Amount,
+}
+
+impl Replacement {
+    /// Replacement fee less the replaced fee, in satoshis; negative if it paid less.
+    pub fn fee_difference(&self) -> i64 {
+        self.replacement_fee.to_sat() as i64 - self.fee.to_sat() as i64
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Status of the transaction with `txid`, recorded or reversed, if the book knows it.
+    pub fn transaction_status(&self, txid: &str) -> Option<TransactionStatus> {
+        if let Some(transaction) = self.transactions.iter().find(|transaction| transaction.txid == txid) {
+            return Some(if transaction.confirmations() == 0 {
+                TransactionStatus::Pending
+            } else {
+                TransactionStatus::Confirmed
+            });
+        }
+        self.reversals
+            .iter()
+            .rev()
+            .find(|reversal| reversal.transaction.txid == txid)
+            .map(|reversal| TransactionStatus::Reversed(reversal.reason.clone()))
+    }
+
+    /// Every replacement, in the order booked.
+    pub fn replacements(&self) -> Vec<Replacement> {
+        self.reversals
+            .iter()
+            .filter_map(|reversal| match &reversal.reason {
+                ReversalReason::Replaced { by, replacement_fee } => Some(Replacement {
+                    replaced: reversal.transaction.txid.clone(),
+                    replacement: by.clone(),
+                    fee: reversal.transaction.fee,
+                    replacement_fee: *replacement_fee,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Recorded transactions spending any output that `transaction` spends.
+    pub fn conflicts_with(&self, transaction: &Transaction) -> Vec<&Transaction> {
+        self.transactions
+            .iter()
+            .filter(|recorded| recorded.txid != transaction.txid)
+            .filter(|recorded| {
+                recorded
+                    .inputs
+                    .iter()
+                    .any(|spent| transaction.inputs.iter().any(|input| input.outpoint() == spent.outpoint()))
+            })
+            .collect()
+    }
+
+    /// Plans voiding the pending transactions `transaction` conflicts with, provided it
+    /// is confirmed, to be stored together with its posting. A conflict with a confirmed
+    /// transaction is a `DoubleSpend`, and an unconfirmed replacement waits with
+    /// `UnconfirmedReplacement` until it confirms.
+    pub(crate) fn plan_replacement(&mut self, transaction: &Transaction) -> Result<Option<ReversalPlan>, AccountingError> {
+        let conflicting = self.conflicts_with(transaction);
+        if conflicting.is_empty() {
+            return Ok(None);
+        }
+        let txids: Vec<String> = conflicting.iter().map(|recorded| recorded.txid.clone()).collect();
+        if conflicting.iter().any(|recorded| recorded.confirmations() > 0) {
+            return Err(AccountingError::DoubleSpend {
+                txid: transaction.txid.clone(),
+                conflicting: txids,
+            });
+        }
+        if transaction.confirmations() == 0 {
+            return Err(AccountingError::UnconfirmedReplacement {
+                txid: transaction.txid.clone(),
+                replaces: txids,
+            });
+        }
+        let reason = ReversalReason::Replaced {
+            by: transaction.txid.clone(),
+            replacement_fee: transaction.fee,
+        };
+        Ok(Some(self.plan_reversal(&txids, &reason, None)?))
+    }
+}
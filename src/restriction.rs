@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// When a timelocked output becomes spendable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockTime {
+    Height(u32),
+    Time(DateTime<Utc>),
+}
+
+/// A contractual or on-chain limit on selling a held UTXO, disclosed under ASU 2023-08.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Restriction {
+    /// Locked by script (CLTV) or nLockTime.
+    Timelocked { until: LockTime },
+    /// Pledged to `counterparty`, open-ended if `until` is absent.
+    PledgedAsCollateral {
+        counterparty: String,
+        until: Option<DateTime<Utc>>,
+    },
+    /// Contractual lock-up that ends on a date.
+    LockUp { until: DateTime<Utc> },
+}
+
+impl Restriction {
+    /// Whether the restriction still applies at `date`. Height timelocks always do, since
+    /// the book does not know which block was current then.
+    pub fn is_active_at(&self, date: DateTime<Utc>) -> bool {
+        match self {
+            Restriction::Timelocked { until: LockTime::Height(_) } => true,
+            Restriction::Timelocked { until: LockTime::Time(until) } | Restriction::LockUp { until } => *until > date,
+            Restriction::PledgedAsCollateral { until, .. } => until.is_none_or(|until| until > date),
+        }
+    }
+
+    /// End of the restriction as written in a disclosure, if it has one.
+    pub fn ends(&self) -> Option<String> {
+        match self {
+            Restriction::Timelocked { until: LockTime::Height(height) } => Some(format!("block {}", height)),
+            Restriction::Timelocked { until: LockTime::Time(until) } | Restriction::LockUp { until } => Some(until.to_rfc3339()),
+            Restriction::PledgedAsCollateral { until, .. } => until.map(|until| until.to_rfc3339()),
+        }
+    }
+}
+
+impl fmt::Display for Restriction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Restriction::Timelocked { .. } => write!(f, "timelocked")?,
+            Restriction::PledgedAsCollateral { counterparty, .. } => write!(f, "pledged as collateral to {}", counterparty)?,
+            Restriction::LockUp { .. } => write!(f, "lock-up")?,
+        }
+        match self.ends() {
+            Some(ends) => write!(f, " until {}", ends),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(month: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, month, 1, 0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn restrictions_lapse_at_their_end() {
+        let lock_up = Restriction::LockUp { until: at(6) };
+        assert!(lock_up.is_active_at(at(5)));
+        assert!(!lock_up.is_active_at(at(6)));
+
+        let open_pledge = Restriction::PledgedAsCollateral {
+            counterparty: "Acme Lending".to_string(),
+            until: None,
+        };
+        assert!(open_pledge.is_active_at(at(12)));
+        assert!(Restriction::Timelocked { until: LockTime::Height(900_000) }.is_active_at(at(12)));
+        assert!(!Restriction::Timelocked { until: LockTime::Time(at(3)) }.is_active_at(at(4)));
+    }
+
+    #[test]
+    fn restrictions_describe_their_end() {
+        let pledge = Restriction::PledgedAsCollateral {
+            counterparty: "Acme Lending".to_string(),
+            until: Some(at(6)),
+        };
+        assert_eq!(pledge.to_string(), "pledged as collateral to Acme Lending until 2024-06-01T00:00:00+00:00");
+        assert_eq!(Restriction::Timelocked { until: LockTime::Height(900_000) }.to_string(), "timelocked until block 900000");
+        let open_pledge = Restriction::PledgedAsCollateral {
+            counterparty: "Acme Lending".to_string(),
+            until: None,
+        };
+        assert_eq!(open_pledge.to_string(), "pledged as collateral to Acme Lending");
+    }
+}
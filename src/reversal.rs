@@ -0,0 +1,327 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::amount::Amount;
+use crate::chain::BlockRef;
+use crate::error::AccountingError;
+use crate::ledger::{AccountRole, EntryKind, JournalEntry, JournalLine};
+use crate::lots::LotReplay;
+use crate::outpoint::OutPoint;
+use crate::storage::ReversalPosting;
+use crate::{BitcoinAccountingApp, Transaction, UTXO};
+
+/// Why a transaction was taken out of the book.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ReversalReason {
+    /// Mined in `block`, which left the best chain.
+    Orphaned { block: BlockRef },
+    /// Never confirmed, and replaced by the confirmed transaction `by`, which spends
+    /// some of the same outputs for `replacement_fee`.
+    Replaced { by: String, replacement_fee: Amount },
+    /// Spent an output of `txid`, which was reversed with it.
+    DependsOn { txid: String },
+    /// Booked in error and reversed on request; `by` names who asked and `reason` why.
+    Corrected { by: String, reason: String },
+}
+
+impl fmt::Display for ReversalReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReversalReason::Orphaned { block } => write!(f, "orphaned in block {}", block),
+            ReversalReason::Replaced { by, .. } => write!(f, "replaced by {}", by),
+            ReversalReason::DependsOn { txid } => write!(f, "spent an output of reversed {}", txid),
+            ReversalReason::Corrected { by, reason } => write!(f, "corrected by {}: {}", by, reason),
+        }
+    }
+}
+
+/// A transaction taken out of the book and the entries that cancelled its postings.
+///
+/// History is kept: the original entries stay in the ledger alongside their reversals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reversal {
+    pub transaction: Transaction,
+    pub reason: ReversalReason,
+    /// When the reversal was recorded.
+    pub recorded_at: DateTime<Utc>,
+    /// The correction date its reversing entries are dated, from which the book no
+    /// longer holds the transaction. `None` when they are dated like the entries they
+    /// reverse, for a transaction that never happened, such as an orphaned one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub posted_on: Option<DateTime<Utc>>,
+    /// Ids of the transaction's entries that were reversed.
+    pub reversed_entries: Vec<u64>,
+    /// Ids of the reversing entries, in the same order.
+    pub entry_ids: Vec<u64>,
+}
+
+/// Everything taking transactions out of the book changes, prepared before it is stored.
+pub(crate) struct ReversalPlan {
+    pub reversals: Vec<Reversal>,
+    pub entries: Vec<JournalEntry>,
+    pub dropped: Vec<OutPoint>,
+    pub restored: Vec<(OutPoint, UTXO)>,
+    /// The lots replayed once they are gone.
+    pub replay: LotReplay,
+    /// The recorded transactions that remain.
+    pub kept: Vec<Transaction>,
+}
+
+impl ReversalPlan {
+    pub(crate) fn posting(&self) -> ReversalPosting<'_> {
+        ReversalPosting {
+            reversals: &self.reversals,
+            entries: &self.entries,
+            dropped: &self.dropped,
+            restored: &self.restored,
+            lots: self.replay.book.open_lots(),
+        }
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Transactions taken out of the book, in the order reversed.
+    pub fn reversals(&self) -> &[Reversal] {
+        &self.reversals
+    }
+
+    /// Takes the transaction `txid`, booked in error, out of the book, recording `by`
+    /// and `reason` on the reversal.
+    ///
+    /// Its entries stay in the ledger and are cancelled by reversing entries dated
+    /// `posted_on`, which must fall in an open period; the transaction itself may be in a
+    /// closed one. UTXOs it spent are restored and the lots are rebuilt as if it had never
+    /// been booked, with later disposals restated on `posted_on` where the basis they
+    /// relieve changes. Holdings and lots as of any earlier date still include it, so
+    /// statements for periods before the correction do not change. A transaction whose
+    /// outputs were spent by another recorded one is refused until that one is reversed.
+    pub fn reverse_transaction(&mut self, txid: &str, posted_on: DateTime<Utc>, by: &str, reason: &str) -> Result<Reversal, AccountingError> {
+        if !self.transactions.iter().any(|transaction| transaction.txid == txid) {
+            return Err(AccountingError::UnknownTransaction(txid.to_string()));
+        }
+        let spenders: Vec<String> = self
+            .transactions
+            .iter()
+            .filter(|transaction| transaction.inputs.iter().any(|input| input.txid == txid))
+            .map(|transaction| transaction.txid.clone())
+            .collect();
+        if !spenders.is_empty() {
+            return Err(AccountingError::SpentByRecorded {
+                txid: txid.to_string(),
+                spenders,
+            });
+        }
+        self.check_unlocked(posted_on)?;
+        let reason = ReversalReason::Corrected {
+            by: by.to_string(),
+            reason: reason.to_string(),
+        };
+        let mut reversals = self.reverse_transactions(&[txid.to_string()], &reason, Some(posted_on))?;
+        Ok(reversals.remove(0))
+    }
+
+    /// Takes `txids` and every recorded transaction spending their outputs out of the
+    /// book, latest first, leaving it untouched if anything fails. `txids` are reversed
+    /// for `reason` and the rest as `DependsOn` the transaction whose output they spent.
+    ///
+    /// Each entry posted for them and not yet reversed gets a reversing entry dated
+    /// `posted_on`, or else on its own date, in which case neither it nor the
+    /// transaction may fall in a closed period. Owned outputs they spent are restored
+    /// to the UTXO set and lots are rebuilt without them. A remaining transaction whose
+    /// disposal now relieves a different basis is restated by an entry moving the
+    /// difference between Digital Assets and realized gain or loss, dated `posted_on` or
+    /// the disposal's own date if later, which must then be open too.
+    pub(crate) fn reverse_transactions(
+        &mut self,
+        txids: &[String],
+        reason: &ReversalReason,
+        posted_on: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Reversal>, AccountingError> {
+        let mut plan = self.plan_reversal(txids, reason, posted_on)?;
+        self.storage.record_reversals(&plan.posting())?;
+        self.transactions = std::mem::take(&mut plan.kept);
+        let reversals = plan.reversals.clone();
+        self.apply_reversal(plan)?;
+        Ok(reversals)
+    }
+
+    /// Builds and validates everything `reverse_transactions` changes, with entries
+    /// numbered as the next ones posted, without changing the book.
+    pub(crate) fn plan_reversal(
+        &mut self,
+        txids: &[String],
+        reason: &ReversalReason,
+        posted_on: Option<DateTime<Utc>>,
+    ) -> Result<ReversalPlan, AccountingError> {
+        let before = self.replay_lots()?;
+        let mut removing: HashSet<String> = txids.iter().cloned().collect();
+        // Each dependent, keyed to the reversed transaction whose output it spent
+        let mut parents: HashMap<String, String> = HashMap::new();
+        loop {
+            let spenders: Vec<(String, String)> = self
+                .transactions
+                .iter()
+                .filter(|transaction| !removing.contains(&transaction.txid))
+                .filter_map(|transaction| {
+                    let input = transaction.inputs.iter().find(|input| removing.contains(&input.txid))?;
+                    Some((transaction.txid.clone(), input.txid.clone()))
+                })
+                .collect();
+            if spenders.is_empty() {
+                break;
+            }
+            removing.extend(spenders.iter().map(|(spender, _)| spender.clone()));
+            parents.extend(spenders);
+        }
+        let (removed, kept): (Vec<Transaction>, Vec<Transaction>) = self
+            .transactions
+            .iter()
+            .cloned()
+            .partition(|transaction| removing.contains(&transaction.txid));
+
+        let already_reversed: HashSet<u64> = self
+            .reversals
+            .iter()
+            .flat_map(|reversal| reversal.reversed_entries.iter().copied())
+            .collect();
+        let recorded_at = Utc::now();
+        let mut next_id = self.ledger.next_id();
+        let mut reversals = Vec::new();
+        let mut entries = Vec::new();
+        for transaction in removed.iter().rev() {
+            if posted_on.is_none() {
+                self.check_unlocked(transaction.timestamp)?;
+            }
+            let reason = match parents.get(&transaction.txid) {
+                Some(parent) => ReversalReason::DependsOn { txid: parent.clone() },
+                None => reason.clone(),
+            };
+            let mut reversal = Reversal {
+                transaction: transaction.clone(),
+                reason,
+                recorded_at,
+                posted_on,
+                reversed_entries: Vec::new(),
+                entry_ids: Vec::new(),
+            };
+            let posted = self.ledger.entries().iter().filter(|entry| {
+                entry.txid.as_ref() == Some(&transaction.txid) && entry.kind != EntryKind::Reversal && !already_reversed.contains(&entry.id)
+            });
+            for original in posted {
+                let date = posted_on.unwrap_or(original.date);
+                self.check_unlocked(date)?;
+                let mut entry = JournalEntry::new(
+                    date,
+                    format!("Reversal of entry {}: {}", original.id, original.description),
+                    original.txid.clone(),
+                );
+                entry.kind = EntryKind::Reversal;
+                entry.lines = original
+                    .lines
+                    .iter()
+                    .map(|line| JournalLine {
+                        account: line.account.clone(),
+                        debit: line.credit,
+                        credit: line.debit,
+                    })
+                    .collect();
+                self.ledger.validate(&entry)?;
+                entry.id = next_id;
+                next_id += 1;
+                reversal.reversed_entries.push(original.id);
+                reversal.entry_ids.push(entry.id);
+                entries.push(entry);
+            }
+            reversals.push(reversal);
+        }
+
+        let dropped: Vec<OutPoint> = removed
+            .iter()
+            .flat_map(|transaction| &transaction.outputs)
+            .map(UTXO::outpoint)
+            .filter(|outpoint| self.utxo_set.contains_key(outpoint))
+            .collect();
+        // Only outputs of transactions still in the book were ever in the UTXO set
+        let restored: Vec<(OutPoint, UTXO)> = removed
+            .iter()
+            .flat_map(|transaction| &transaction.inputs)
+            .filter_map(|input| {
+                let funding = kept.iter().find(|transaction| transaction.txid == input.txid)?;
+                funding.outputs.iter().find(|output| output.vout == input.vout)
+            })
+            .filter(|output| self.wallets.is_owned(&output.address))
+            .map(|output| (output.outpoint(), output.clone()))
+            .collect();
+
+        // Replay the lots without the removed transactions, then put them back
+        let recorded = std::mem::replace(&mut self.transactions, kept);
+        let result = self.replay_lots().map_err(AccountingError::from).and_then(|replay| {
+            entries.extend(self.restatements(&before, &replay, &reversals, posted_on, next_id)?);
+            Ok(replay)
+        });
+        let kept = std::mem::replace(&mut self.transactions, recorded);
+        Ok(ReversalPlan {
+            reversals,
+            entries,
+            dropped,
+            restored,
+            replay: result?,
+            kept,
+        })
+    }
+
+    /// Posts a stored plan's entries and moves its UTXOs. The recorded transactions are
+    /// left to the caller, which may be booking another one alongside.
+    pub(crate) fn apply_reversal(&mut self, plan: ReversalPlan) -> Result<(), AccountingError> {
+        for entry in plan.entries {
+            self.ledger.post(entry)?;
+        }
+        for outpoint in &plan.dropped {
+            self.utxo_set.remove(outpoint);
+        }
+        self.utxo_set.extend(plan.restored);
+        self.reversals.extend(plan.reversals);
+        Ok(())
+    }
+
+    /// Entries restating each recorded transaction whose disposal relieves a different
+    /// cost basis in `after` than in `before`, numbered from `next_id`.
+    fn restatements(
+        &self,
+        before: &LotReplay,
+        after: &LotReplay,
+        reversals: &[Reversal],
+        posted_on: Option<DateTime<Utc>>,
+        mut next_id: u64,
+    ) -> Result<Vec<JournalEntry>, AccountingError> {
+        let reversed: Vec<&str> = reversals.iter().map(|reversal| reversal.transaction.txid.as_str()).collect();
+        let chart = self.ledger.chart();
+        let mut entries = Vec::new();
+        for transaction in &self.transactions {
+            let difference = after.relieved(&transaction.txid) - before.relieved(&transaction.txid);
+            if difference.is_zero() {
+                continue;
+            }
+            // A disposal after the correction date is restated on its own date
+            let date = posted_on.map_or(transaction.timestamp, |posted_on| posted_on.max(transaction.timestamp));
+            self.check_unlocked(date)?;
+            let mut entry = JournalEntry::new(
+                date,
+                format!("Restated relief of {} after reversing {}", transaction.txid, reversed.join(", ")),
+                Some(transaction.txid.clone()),
+            );
+            entry.kind = EntryKind::Restatement;
+            // More basis relieved means less gain
+            entry.push_debit(chart.code_for(AccountRole::RealizedGainLoss), difference);
+            entry.push_credit(chart.code_for(AccountRole::DigitalAssets), difference);
+            self.ledger.validate(&entry)?;
+            entry.id = next_id;
+            next_id += 1;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
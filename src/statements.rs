@@ -0,0 +1,255 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::error::AccountingError;
+use crate::ledger::{AccountRole, AccountType};
+use crate::period::FiscalPeriod;
+use crate::rates::RateError;
+use crate::{BitcoinAccountingApp, Transaction};
+
+/// One row of a financial statement with its current and comparative amounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementLine {
+    /// Ledger account the row comes from; totals and derived rows have none.
+    pub account: Option<String>,
+    pub label: String,
+    pub current: Decimal,
+    pub prior: Decimal,
+}
+
+impl StatementLine {
+    fn new(account: Option<&str>, label: &str, current: Decimal, prior: Decimal) -> Self {
+        StatementLine {
+            account: account.map(str::to_string),
+            label: label.to_string(),
+            current,
+            prior,
+        }
+    }
+
+    fn total(label: &str, lines: &[StatementLine]) -> Self {
+        StatementLine::new(
+            None,
+            label,
+            lines.iter().map(|line| line.current).sum(),
+            lines.iter().map(|line| line.prior).sum(),
+        )
+    }
+}
+
+/// Statement of financial position with digital assets at fair value (ASU 2023-08).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceSheet {
+    pub as_of: DateTime<Utc>,
+    pub prior_as_of: DateTime<Utc>,
+    pub assets: Vec<StatementLine>,
+    pub liabilities: Vec<StatementLine>,
+    pub equity: Vec<StatementLine>,
+    pub total_assets: StatementLine,
+    pub total_liabilities_and_equity: StatementLine,
+}
+
+/// Statement of operations for a period, with a comparative prior period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomeStatement {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub prior_start: DateTime<Utc>,
+    pub prior_end: DateTime<Utc>,
+    pub revenue: Vec<StatementLine>,
+    /// Realized and unrealized gains and losses on digital assets.
+    pub gains: Vec<StatementLine>,
+    pub expenses: Vec<StatementLine>,
+    pub net_income: StatementLine,
+}
+
+/// Flips a debit-positive balance for credit-normal accounts without producing `-0`.
+fn credit_normal(balance: Decimal) -> Decimal {
+    if balance.is_zero() {
+        Decimal::ZERO
+    } else {
+        -balance
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Balance sheet as of `as_of`, compared with `prior_as_of`.
+    ///
+    /// Digital assets are shown at fair value on each date even if no remeasurement was
+    /// posted there; the unposted difference is carried in current earnings alongside
+    /// income not yet closed to retained earnings.
+    pub fn balance_sheet(&self, as_of: DateTime<Utc>, prior_as_of: DateTime<Utc>) -> Result<BalanceSheet, AccountingError> {
+        let chart = self.ledger.chart();
+        let digital_assets = chart.code_for(AccountRole::DigitalAssets);
+        let adjustment = (self.unposted_fair_value_adjustment(as_of)?, self.unposted_fair_value_adjustment(prior_as_of)?);
+
+        let mut assets = Vec::new();
+        let mut liabilities = Vec::new();
+        let mut equity = Vec::new();
+        let mut earnings = (adjustment.0, adjustment.1);
+        for account in chart.accounts() {
+            let current = self.ledger.balance(&account.code, as_of);
+            let prior = self.ledger.balance(&account.code, prior_as_of);
+            match account.account_type {
+                AccountType::Asset if account.code == digital_assets => assets.push(StatementLine::new(
+                    Some(&account.code),
+                    &format!("{} (at fair value)", account.name),
+                    current + adjustment.0,
+                    prior + adjustment.1,
+                )),
+                AccountType::Asset => assets.push(StatementLine::new(Some(&account.code), &account.name, current, prior)),
+                AccountType::Liability => liabilities.push(StatementLine::new(
+                    Some(&account.code),
+                    &account.name,
+                    credit_normal(current),
+                    credit_normal(prior),
+                )),
+                AccountType::Equity => equity.push(StatementLine::new(
+                    Some(&account.code),
+                    &account.name,
+                    credit_normal(current),
+                    credit_normal(prior),
+                )),
+                AccountType::Revenue | AccountType::Expense => {
+                    earnings.0 -= current;
+                    earnings.1 -= prior;
+                }
+            }
+        }
+        equity.push(StatementLine::new(None, "Net income not yet closed", earnings.0, earnings.1));
+
+        let total_assets = StatementLine::total("Total assets", &assets);
+        let claims: Vec<StatementLine> = liabilities.iter().chain(&equity).cloned().collect();
+        let total_liabilities_and_equity = StatementLine::total("Total liabilities and equity", &claims);
+        Ok(BalanceSheet {
+            as_of,
+            prior_as_of,
+            assets,
+            liabilities,
+            equity,
+            total_assets,
+            total_liabilities_and_equity,
+        })
+    }
+
+    /// Income statement for `current`, compared with `prior`.
+    ///
+    /// Unrealized gain or loss includes the change in fair value not yet posted by a
+    /// remeasurement, so the statement agrees with the balance sheet at fair value.
+    pub fn income_statement(&self, current: &FiscalPeriod, prior: &FiscalPeriod) -> Result<IncomeStatement, AccountingError> {
+        let (start, end) = (current.start, current.end);
+        let (prior_start, prior_end) = (prior.start, prior.end);
+        let chart = self.ledger.chart();
+        let gain_accounts = [
+            chart.code_for(AccountRole::RealizedGainLoss),
+            chart.code_for(AccountRole::UnrealizedGainLoss),
+        ];
+        let unrealized = chart.code_for(AccountRole::UnrealizedGainLoss);
+        let unposted = (
+            self.unposted_fair_value_adjustment(end)? - self.unposted_fair_value_adjustment(before(start))?,
+            self.unposted_fair_value_adjustment(prior_end)? - self.unposted_fair_value_adjustment(before(prior_start))?,
+        );
+        // Closing entries would cancel the period's income, so activity is measured before them
+        let activity = |code: &str, from: DateTime<Utc>, to: DateTime<Utc>| {
+            self.ledger.pre_closing_balance(code, to) - self.ledger.pre_closing_balance(code, before(from))
+        };
+
+        let mut revenue = Vec::new();
+        let mut gains = Vec::new();
+        let mut expenses = Vec::new();
+        for account in chart.accounts() {
+            let current = activity(&account.code, start, end);
+            let prior = activity(&account.code, prior_start, prior_end);
+            match account.account_type {
+                AccountType::Revenue if account.code == unrealized => gains.push(StatementLine::new(
+                    Some(&account.code),
+                    &account.name,
+                    credit_normal(current) + unposted.0,
+                    credit_normal(prior) + unposted.1,
+                )),
+                AccountType::Revenue if gain_accounts.contains(&account.code.as_str()) => gains.push(StatementLine::new(
+                    Some(&account.code),
+                    &account.name,
+                    credit_normal(current),
+                    credit_normal(prior),
+                )),
+                AccountType::Revenue => revenue.push(StatementLine::new(
+                    Some(&account.code),
+                    &account.name,
+                    credit_normal(current),
+                    credit_normal(prior),
+                )),
+                AccountType::Expense => expenses.push(StatementLine::new(Some(&account.code), &account.name, current, prior)),
+                AccountType::Asset | AccountType::Liability | AccountType::Equity => {}
+            }
+        }
+
+        let net = |column: fn(&StatementLine) -> Decimal| {
+            revenue.iter().chain(&gains).map(column).sum::<Decimal>() - expenses.iter().map(column).sum::<Decimal>()
+        };
+        let net_income = StatementLine::new(None, "Net income", net(|line| line.current), net(|line| line.prior));
+        Ok(IncomeStatement {
+            start,
+            end,
+            prior_start,
+            prior_end,
+            revenue,
+            gains,
+            expenses,
+            net_income,
+        })
+    }
+
+    /// BTC held at `date`: owned outputs created and not yet spent by then, by the
+    /// transactions in the book as of that date.
+    pub fn holdings_at(&self, date: DateTime<Utc>) -> Amount {
+        let mut sats: i128 = 0;
+        let transactions: Vec<&Transaction> = self.transactions_as_of(date).collect();
+        let recorded = |txid: &str| transactions.iter().any(|transaction| transaction.txid == txid);
+        for opening in self.opening_balances.iter().filter(|opening| opening.utxo.timestamp <= date) {
+            sats += i128::from(opening.utxo.amount.to_sat());
+        }
+        for transaction in &transactions {
+            let posted = transaction.timestamp <= date;
+            for output in transaction.outputs.iter().filter(|output| self.wallets.is_owned(&output.address)) {
+                if posted {
+                    sats += i128::from(output.amount.to_sat());
+                }
+            }
+            for input in transaction.inputs.iter().filter(|input| self.wallets.is_owned(&input.address)) {
+                if posted {
+                    sats -= i128::from(input.amount.to_sat());
+                }
+                // Coins funded outside the book were held from their own timestamp
+                let opening = self.opening_balances.iter().any(|opening| opening.utxo.txid == input.txid);
+                if !recorded(&input.txid) && !opening && input.timestamp <= date {
+                    sats += i128::from(input.amount.to_sat());
+                }
+            }
+        }
+        Amount::from_sat(u64::try_from(sats).unwrap_or_default())
+    }
+
+    /// Fair value of the holdings at `date`; needs no rate when nothing is held.
+    pub(crate) fn fair_value_at(&self, date: DateTime<Utc>) -> Result<Decimal, RateError> {
+        let quantity = self.holdings_at(date);
+        if quantity.is_zero() {
+            return Ok(Decimal::ZERO);
+        }
+        let rate = self.exchange_rates.lookup(date).ok_or_else(|| RateError::MissingRates(vec![date]))?;
+        Ok(quantity.to_btc() * rate)
+    }
+
+    /// Fair value of holdings at `date` less the Digital Assets carrying amount then.
+    pub(crate) fn unposted_fair_value_adjustment(&self, date: DateTime<Utc>) -> Result<Decimal, RateError> {
+        let digital_assets = self.ledger.chart().code_for(AccountRole::DigitalAssets);
+        Ok(self.fair_value_at(date)? - self.ledger.balance(digital_assets, date))
+    }
+}
+
+/// The last instant before `date`, for balances that must exclude it.
+pub(crate) fn before(date: DateTime<Utc>) -> DateTime<Utc> {
+    date.checked_sub_signed(chrono::Duration::nanoseconds(1)).unwrap_or(date)
+}
@@ -0,0 +1,199 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::fair_value::Remeasurement;
+use crate::ledger::JournalEntry;
+use crate::lots::Lot;
+use crate::opening::OpeningBalance;
+use crate::outpoint::OutPoint;
+use crate::period::PeriodClose;
+use crate::restriction::Restriction;
+use crate::reversal::Reversal;
+use crate::{Transaction, UTXO};
+
+mod sqlite;
+
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    /// A stored value could not be decoded.
+    Corrupt(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(err) => write!(f, "sqlite: {}", err),
+            StorageError::Corrupt(message) => write!(f, "corrupt book: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        StorageError::Sqlite(err)
+    }
+}
+
+/// Everything needed to reopen a book and continue posting.
+#[derive(Debug, Clone, Default)]
+pub struct StoredBook {
+    /// In posting order.
+    pub transactions: Vec<Transaction>,
+    /// In the order added.
+    pub opening_balances: Vec<OpeningBalance>,
+    pub utxos: Vec<(OutPoint, UTXO)>,
+    /// In id order.
+    pub journal_entries: Vec<JournalEntry>,
+    pub rates: Vec<(DateTime<Utc>, Decimal)>,
+    /// `(wallet, address)` pairs.
+    pub wallet_addresses: Vec<(String, String)>,
+    pub remeasurements: Vec<Remeasurement>,
+    /// In the order closed.
+    pub period_closes: Vec<PeriodClose>,
+    /// In the order reversed.
+    pub reversals: Vec<Reversal>,
+    pub settings: HashMap<String, String>,
+}
+
+/// Changes made by one successful `add_transaction`, applied atomically.
+pub struct Posting<'a> {
+    pub transaction: &'a Transaction,
+    pub entries: &'a [JournalEntry],
+    pub spent: &'a [OutPoint],
+    pub created: &'a [(OutPoint, UTXO)],
+    /// Open lots after the posting, kept by stores that expose them for reporting.
+    pub lots: &'a [Lot],
+}
+
+/// Changes made by taking transactions out of the book, applied atomically.
+pub struct ReversalPosting<'a> {
+    /// Each carries the transaction to remove from the recorded history.
+    pub reversals: &'a [Reversal],
+    pub entries: &'a [JournalEntry],
+    /// Outputs of the removed transactions that leave the UTXO set.
+    pub dropped: &'a [OutPoint],
+    /// Outputs they spent that return to it.
+    pub restored: &'a [(OutPoint, UTXO)],
+    pub lots: &'a [Lot],
+}
+
+/// Durable backing store for a `BitcoinAccountingApp` book.
+///
+/// Every write either fully succeeds or leaves the store unchanged.
+pub trait Storage {
+    fn load(&self) -> Result<StoredBook, StorageError>;
+    fn record_posting(&mut self, posting: &Posting) -> Result<(), StorageError>;
+    /// Stores an opening balance with its entry, adds its UTXO and replaces the open lots.
+    fn record_opening_balance(&mut self, opening: &OpeningBalance, entry: Option<&JournalEntry>, lots: &[Lot]) -> Result<(), StorageError>;
+    fn record_remeasurement(&mut self, remeasurement: &Remeasurement, entry: Option<&JournalEntry>) -> Result<(), StorageError>;
+    /// Stores the period-end remeasurement, then the close and the entries of both.
+    fn record_period_close(&mut self, close: &PeriodClose, remeasurement: &Remeasurement, entries: &[JournalEntry]) -> Result<(), StorageError>;
+    fn record_reversals(&mut self, posting: &ReversalPosting) -> Result<(), StorageError>;
+    /// Stores the reversals of the transactions a replacement voids, then the replacement.
+    fn record_replacement(&mut self, reversals: &ReversalPosting, posting: &Posting) -> Result<(), StorageError>;
+    /// Stores the block and output confirmations of a recorded transaction.
+    fn record_confirmations(&mut self, transaction: &Transaction) -> Result<(), StorageError>;
+    fn insert_rate(&mut self, date: DateTime<Utc>, rate: Decimal) -> Result<(), StorageError>;
+    fn insert_wallet_address(&mut self, wallet: &str, address: &str) -> Result<(), StorageError>;
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), StorageError>;
+    fn set_restrictions(&mut self, outpoint: &OutPoint, restrictions: &[Restriction]) -> Result<(), StorageError>;
+}
+
+/// Keeps the book in memory only; used by default and in tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    book: StoredBook,
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self) -> Result<StoredBook, StorageError> {
+        Ok(self.book.clone())
+    }
+
+    fn record_posting(&mut self, posting: &Posting) -> Result<(), StorageError> {
+        self.book.transactions.push(posting.transaction.clone());
+        self.book.journal_entries.extend_from_slice(posting.entries);
+        self.book.utxos.retain(|(outpoint, _)| !posting.spent.contains(outpoint));
+        self.book.utxos.extend_from_slice(posting.created);
+        Ok(())
+    }
+
+    fn record_opening_balance(&mut self, opening: &OpeningBalance, entry: Option<&JournalEntry>, _lots: &[Lot]) -> Result<(), StorageError> {
+        self.book.journal_entries.extend(entry.cloned());
+        self.book.utxos.push((opening.utxo.outpoint(), opening.utxo.clone()));
+        self.book.opening_balances.push(opening.clone());
+        Ok(())
+    }
+
+    fn record_remeasurement(&mut self, remeasurement: &Remeasurement, entry: Option<&JournalEntry>) -> Result<(), StorageError> {
+        self.book.journal_entries.extend(entry.cloned());
+        self.book.remeasurements.push(remeasurement.clone());
+        Ok(())
+    }
+
+    fn record_period_close(&mut self, close: &PeriodClose, remeasurement: &Remeasurement, entries: &[JournalEntry]) -> Result<(), StorageError> {
+        self.book.journal_entries.extend_from_slice(entries);
+        self.book.remeasurements.push(remeasurement.clone());
+        self.book.period_closes.push(close.clone());
+        Ok(())
+    }
+
+    fn record_reversals(&mut self, posting: &ReversalPosting) -> Result<(), StorageError> {
+        for reversal in posting.reversals {
+            self.book.transactions.retain(|transaction| transaction.txid != reversal.transaction.txid);
+        }
+        self.book.journal_entries.extend_from_slice(posting.entries);
+        self.book.utxos.retain(|(outpoint, _)| !posting.dropped.contains(outpoint));
+        self.book.utxos.extend_from_slice(posting.restored);
+        self.book.reversals.extend_from_slice(posting.reversals);
+        Ok(())
+    }
+
+    fn record_replacement(&mut self, reversals: &ReversalPosting, posting: &Posting) -> Result<(), StorageError> {
+        self.record_reversals(reversals)?;
+        self.record_posting(posting)
+    }
+
+    fn record_confirmations(&mut self, transaction: &Transaction) -> Result<(), StorageError> {
+        if let Some(recorded) = self.book.transactions.iter_mut().find(|recorded| recorded.txid == transaction.txid) {
+            recorded.clone_from(transaction);
+        }
+        for output in &transaction.outputs {
+            if let Some((_, utxo)) = self.book.utxos.iter_mut().find(|(outpoint, _)| *outpoint == output.outpoint()) {
+                utxo.confirmations = output.confirmations;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_rate(&mut self, date: DateTime<Utc>, rate: Decimal) -> Result<(), StorageError> {
+        self.book.rates.retain(|(existing, _)| *existing != date);
+        self.book.rates.push((date, rate));
+        Ok(())
+    }
+
+    fn insert_wallet_address(&mut self, wallet: &str, address: &str) -> Result<(), StorageError> {
+        self.book.wallet_addresses.retain(|(_, existing)| existing != address);
+        self.book.wallet_addresses.push((wallet.to_string(), address.to_string()));
+        Ok(())
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), StorageError> {
+        self.book.settings.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn set_restrictions(&mut self, outpoint: &OutPoint, restrictions: &[Restriction]) -> Result<(), StorageError> {
+        if let Some((_, utxo)) = self.book.utxos.iter_mut().find(|(existing, _)| existing == outpoint) {
+            utxo.restrictions = restrictions.to_vec();
+        }
+        Ok(())
+    }
+}
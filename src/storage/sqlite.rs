@@ -0,0 +1,780 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, Row};
+use rust_decimal::Decimal;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{Posting, ReversalPosting, Storage, StorageError, StoredBook};
+use crate::amount::Amount;
+use crate::chain::BlockRef;
+use crate::fair_value::Remeasurement;
+use crate::ledger::{JournalEntry, JournalLine};
+use crate::lots::Lot;
+use crate::opening::OpeningBalance;
+use crate::outpoint::OutPoint;
+use crate::period::{FiscalPeriod, PeriodClose};
+use crate::restriction::Restriction;
+use crate::reversal::Reversal;
+use crate::{Transaction, UTXO};
+
+/// Schema migrations, applied in order. The book's `user_version` is the number applied.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE transactions (
+        txid TEXT PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        fee TEXT NOT NULL
+    );
+    CREATE TABLE transaction_utxos (
+        txid TEXT NOT NULL REFERENCES transactions(txid),
+        role TEXT NOT NULL CHECK (role IN ('input', 'output')),
+        position INTEGER NOT NULL,
+        utxo_txid TEXT NOT NULL,
+        vout INTEGER NOT NULL,
+        amount TEXT NOT NULL,
+        address TEXT NOT NULL,
+        confirmations INTEGER NOT NULL,
+        spendable INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        PRIMARY KEY (txid, role, position)
+    );
+    CREATE TABLE utxos (
+        outpoint TEXT PRIMARY KEY,
+        txid TEXT NOT NULL,
+        vout INTEGER NOT NULL,
+        amount TEXT NOT NULL,
+        address TEXT NOT NULL,
+        confirmations INTEGER NOT NULL,
+        spendable INTEGER NOT NULL,
+        timestamp TEXT NOT NULL
+    );
+    CREATE TABLE journal_entries (
+        id INTEGER PRIMARY KEY,
+        date TEXT NOT NULL,
+        description TEXT NOT NULL,
+        txid TEXT
+    );
+    CREATE TABLE journal_lines (
+        entry_id INTEGER NOT NULL REFERENCES journal_entries(id),
+        position INTEGER NOT NULL,
+        account TEXT NOT NULL,
+        debit TEXT NOT NULL,
+        credit TEXT NOT NULL,
+        PRIMARY KEY (entry_id, position)
+    );
+    CREATE TABLE exchange_rates (
+        date TEXT PRIMARY KEY,
+        rate TEXT NOT NULL
+    );
+    CREATE TABLE lots (
+        position INTEGER PRIMARY KEY,
+        outpoint TEXT NOT NULL,
+        acquired_at TEXT NOT NULL,
+        quantity TEXT NOT NULL,
+        cost_basis TEXT NOT NULL
+    );
+    CREATE TABLE wallet_addresses (
+        address TEXT PRIMARY KEY,
+        wallet TEXT NOT NULL
+    );
+    CREATE TABLE remeasurements (
+        reporting_date TEXT NOT NULL,
+        quantity TEXT NOT NULL,
+        rate TEXT NOT NULL,
+        fair_value TEXT NOT NULL,
+        carrying_amount TEXT NOT NULL,
+        adjustment TEXT NOT NULL,
+        entry_id INTEGER REFERENCES journal_entries(id)
+    );
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+"#, r#"
+    ALTER TABLE transaction_utxos ADD COLUMN restrictions TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE utxos ADD COLUMN restrictions TEXT NOT NULL DEFAULT '[]';
+"#, r#"
+    ALTER TABLE journal_entries ADD COLUMN kind TEXT NOT NULL DEFAULT 'regular';
+    CREATE TABLE period_closes (
+        name TEXT PRIMARY KEY,
+        start_date TEXT NOT NULL,
+        end_date TEXT NOT NULL,
+        closed_at TEXT NOT NULL,
+        balances TEXT NOT NULL,
+        net_income TEXT NOT NULL,
+        entry_id INTEGER REFERENCES journal_entries(id)
+    );
+"#, r#"
+    ALTER TABLE transactions ADD COLUMN block_hash TEXT;
+    ALTER TABLE transactions ADD COLUMN block_height INTEGER;
+    CREATE TABLE reversals (
+        id INTEGER PRIMARY KEY,
+        txid TEXT NOT NULL,
+        transaction_json TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        recorded_at TEXT NOT NULL,
+        reversed_entries TEXT NOT NULL,
+        entry_ids TEXT NOT NULL
+    );
+"#, r#"
+    CREATE TABLE opening_balances (
+        outpoint TEXT PRIMARY KEY,
+        utxo_json TEXT NOT NULL,
+        cost_basis TEXT NOT NULL,
+        entry_id INTEGER REFERENCES journal_entries(id)
+    );
+"#, r#"
+    ALTER TABLE reversals ADD COLUMN posted_on TEXT;
+"#, r#"
+    ALTER TABLE transactions ADD COLUMN acquisition_fee TEXT NOT NULL DEFAULT '0';
+"#];
+
+/// Book stored in a single SQLite database file.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens (creating if needed) the book at `path` and brings its schema up to date.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self, StorageError> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut conn)?;
+        Ok(SqliteStorage { conn })
+    }
+
+    pub fn schema_version(&self) -> Result<usize, StorageError> {
+        Ok(self.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        return Err(StorageError::Corrupt(format!(
+            "schema version {} is newer than the supported version {}",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+fn encode_time(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn decode_time(value: &str) -> Result<DateTime<Utc>, StorageError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|err| StorageError::Corrupt(format!("invalid timestamp '{}': {}", value, err)))
+}
+
+fn decode_decimal(value: &str) -> Result<Decimal, StorageError> {
+    Decimal::from_str(value).map_err(|err| StorageError::Corrupt(format!("invalid decimal '{}': {}", value, err)))
+}
+
+/// Reads a stored `txid:vout` key. Books written before txids were checked may hold
+/// txids that are not hex, so only the shape is checked here.
+fn decode_outpoint(value: &str) -> Result<OutPoint, StorageError> {
+    value
+        .rsplit_once(':')
+        .and_then(|(txid, vout)| {
+            Some(OutPoint {
+                txid: txid.to_string(),
+                vout: vout.parse().ok()?,
+            })
+        })
+        .ok_or_else(|| StorageError::Corrupt(format!("invalid outpoint '{}'", value)))
+}
+
+fn decode_amount(value: &str) -> Result<Amount, StorageError> {
+    Amount::from_str(value).map_err(|err| StorageError::Corrupt(format!("invalid amount '{}': {}", value, err)))
+}
+
+/// Column order: txid, vout, amount, address, confirmations, spendable, timestamp, restrictions.
+const UTXO_COLUMNS: &str = "txid, vout, amount, address, confirmations, spendable, timestamp, restrictions";
+
+type RawUtxo = (String, u32, String, String, u64, bool, String, String);
+
+fn read_raw_utxo(row: &Row, offset: usize) -> rusqlite::Result<RawUtxo> {
+    Ok((
+        row.get(offset)?,
+        row.get(offset + 1)?,
+        row.get(offset + 2)?,
+        row.get(offset + 3)?,
+        row.get(offset + 4)?,
+        row.get(offset + 5)?,
+        row.get(offset + 6)?,
+        row.get(offset + 7)?,
+    ))
+}
+
+fn decode_utxo(raw: RawUtxo) -> Result<UTXO, StorageError> {
+    let (txid, vout, amount, address, confirmations, spendable, timestamp, restrictions) = raw;
+    Ok(UTXO {
+        txid,
+        vout,
+        amount: decode_amount(&amount)?,
+        address,
+        confirmations,
+        spendable,
+        timestamp: decode_time(&timestamp)?,
+        restrictions: serde_json::from_str(&restrictions)
+            .map_err(|err| StorageError::Corrupt(format!("invalid restrictions '{}': {}", restrictions, err)))?,
+    })
+}
+
+fn encode_restrictions(restrictions: &[Restriction]) -> String {
+    serde_json::to_string(restrictions).expect("restrictions serialize to JSON")
+}
+
+impl SqliteStorage {
+    fn load_transactions(&self) -> Result<Vec<Transaction>, StorageError> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT txid, timestamp, fee, block_hash, block_height, acquisition_fee FROM transactions ORDER BY rowid")?;
+        let headers = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<u32>>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut io = self.conn.prepare(&format!(
+            "SELECT role, utxo_{} FROM transaction_utxos WHERE txid = ?1 ORDER BY role, position",
+            UTXO_COLUMNS
+        ))?;
+        let mut transactions = Vec::with_capacity(headers.len());
+        for (txid, timestamp, fee, block_hash, block_height, acquisition_fee) in headers {
+            let mut inputs = Vec::new();
+            let mut outputs = Vec::new();
+            let rows = io
+                .query_map([&txid], |row| Ok((row.get::<_, String>(0)?, read_raw_utxo(row, 1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for (role, raw) in rows {
+                let utxo = decode_utxo(raw)?;
+                if role == "input" {
+                    inputs.push(utxo);
+                } else {
+                    outputs.push(utxo);
+                }
+            }
+            transactions.push(Transaction {
+                txid,
+                timestamp: decode_time(&timestamp)?,
+                inputs,
+                outputs,
+                fee: decode_amount(&fee)?,
+                block: block_hash.zip(block_height).map(|(hash, height)| BlockRef { hash, height }),
+                acquisition_fee: decode_decimal(&acquisition_fee)?,
+            });
+        }
+        Ok(transactions)
+    }
+
+    fn load_journal_entries(&self) -> Result<Vec<JournalEntry>, StorageError> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT id, date, description, txid, kind FROM journal_entries ORDER BY id")?;
+        let headers = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut lines = self
+            .conn
+            .prepare("SELECT account, debit, credit FROM journal_lines WHERE entry_id = ?1 ORDER BY position")?;
+        let mut entries = Vec::with_capacity(headers.len());
+        for (id, date, description, txid, kind) in headers {
+            let raw_lines = lines
+                .query_map([id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut entry = JournalEntry::new(decode_time(&date)?, description, txid);
+            entry.id = id;
+            entry.kind = kind.parse().map_err(StorageError::Corrupt)?;
+            for (account, debit, credit) in raw_lines {
+                entry.lines.push(JournalLine {
+                    account,
+                    debit: decode_decimal(&debit)?,
+                    credit: decode_decimal(&credit)?,
+                });
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+fn insert_journal_entry(tx: &rusqlite::Transaction, entry: &JournalEntry) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO journal_entries (id, date, description, txid, kind) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![entry.id, encode_time(entry.date), entry.description, entry.txid, entry.kind.to_string()],
+    )?;
+    for (position, line) in entry.lines.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO journal_lines (entry_id, position, account, debit, credit) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![entry.id, position, line.account, line.debit.to_string(), line.credit.to_string()],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert_utxo(tx: &rusqlite::Transaction, outpoint: &OutPoint, utxo: &UTXO) -> Result<(), StorageError> {
+    tx.execute(
+        &format!("INSERT OR REPLACE INTO utxos (outpoint, {}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)", UTXO_COLUMNS),
+        params![
+            outpoint.to_string(),
+            utxo.txid,
+            utxo.vout,
+            utxo.amount.to_string(),
+            utxo.address,
+            utxo.confirmations,
+            utxo.spendable,
+            encode_time(utxo.timestamp),
+            encode_restrictions(&utxo.restrictions)
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_remeasurement(tx: &rusqlite::Transaction, remeasurement: &Remeasurement) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO remeasurements (reporting_date, quantity, rate, fair_value, carrying_amount, adjustment, entry_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            encode_time(remeasurement.reporting_date),
+            remeasurement.quantity.to_string(),
+            remeasurement.rate.to_string(),
+            remeasurement.fair_value.to_string(),
+            remeasurement.carrying_amount.to_string(),
+            remeasurement.adjustment.to_string(),
+            remeasurement.entry_id
+        ],
+    )?;
+    Ok(())
+}
+
+/// Lots are rebuilt from transactions on load; this table is a reporting copy.
+fn replace_lots(tx: &rusqlite::Transaction, lots: &[Lot]) -> Result<(), StorageError> {
+    tx.execute("DELETE FROM lots", [])?;
+    for (position, lot) in lots.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO lots (position, outpoint, acquired_at, quantity, cost_basis) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                position,
+                lot.outpoint.to_string(),
+                encode_time(lot.acquired_at),
+                lot.quantity.to_string(),
+                lot.cost_basis.to_string()
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn insert_posting(tx: &rusqlite::Transaction, posting: &Posting) -> Result<(), StorageError> {
+    let transaction = posting.transaction;
+    tx.execute(
+        "INSERT INTO transactions (txid, timestamp, fee, block_hash, block_height, acquisition_fee) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            transaction.txid,
+            encode_time(transaction.timestamp),
+            transaction.fee.to_string(),
+            transaction.block.as_ref().map(|block| &block.hash),
+            transaction.block.as_ref().map(|block| block.height),
+            transaction.acquisition_fee.to_string()
+        ],
+    )?;
+    let io = transaction
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(position, utxo)| ("input", position, utxo))
+        .chain(transaction.outputs.iter().enumerate().map(|(position, utxo)| ("output", position, utxo)));
+    for (role, position, utxo) in io {
+        tx.execute(
+            &format!(
+                "INSERT INTO transaction_utxos (txid, role, position, utxo_{}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                UTXO_COLUMNS
+            ),
+            params![
+                transaction.txid,
+                role,
+                position,
+                utxo.txid,
+                utxo.vout,
+                utxo.amount.to_string(),
+                utxo.address,
+                utxo.confirmations,
+                utxo.spendable,
+                encode_time(utxo.timestamp),
+                encode_restrictions(&utxo.restrictions)
+            ],
+        )?;
+    }
+
+    for entry in posting.entries {
+        insert_journal_entry(tx, entry)?;
+    }
+
+    for outpoint in posting.spent {
+        tx.execute("DELETE FROM utxos WHERE outpoint = ?1", [outpoint.to_string()])?;
+    }
+    for (outpoint, utxo) in posting.created {
+        insert_utxo(tx, outpoint, utxo)?;
+    }
+    replace_lots(tx, posting.lots)?;
+    Ok(())
+}
+
+fn insert_reversals(tx: &rusqlite::Transaction, posting: &ReversalPosting) -> Result<(), StorageError> {
+    // The transaction moves out of the recorded history so it can be booked again
+    for reversal in posting.reversals {
+        let txid = &reversal.transaction.txid;
+        tx.execute("DELETE FROM transaction_utxos WHERE txid = ?1", [txid])?;
+        tx.execute("DELETE FROM transactions WHERE txid = ?1", [txid])?;
+        tx.execute(
+            "INSERT INTO reversals (txid, transaction_json, reason, recorded_at, reversed_entries, entry_ids, posted_on)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                txid,
+                encode_json(&reversal.transaction),
+                encode_json(&reversal.reason),
+                encode_time(reversal.recorded_at),
+                encode_json(&reversal.reversed_entries),
+                encode_json(&reversal.entry_ids),
+                reversal.posted_on.map(encode_time)
+            ],
+        )?;
+    }
+    for entry in posting.entries {
+        insert_journal_entry(tx, entry)?;
+    }
+    for outpoint in posting.dropped {
+        tx.execute("DELETE FROM utxos WHERE outpoint = ?1", [outpoint.to_string()])?;
+    }
+    for (outpoint, utxo) in posting.restored {
+        insert_utxo(tx, outpoint, utxo)?;
+    }
+    replace_lots(tx, posting.lots)?;
+    Ok(())
+}
+
+fn encode_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("stored records serialize to JSON")
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(value: &str, what: &str) -> Result<T, StorageError> {
+    serde_json::from_str(value).map_err(|err| StorageError::Corrupt(format!("invalid {} '{}': {}", what, value, err)))
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> Result<StoredBook, StorageError> {
+        let mut book = StoredBook {
+            transactions: self.load_transactions()?,
+            journal_entries: self.load_journal_entries()?,
+            ..StoredBook::default()
+        };
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT utxo_json, cost_basis, entry_id FROM opening_balances ORDER BY rowid")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<u64>>(2)?))
+        })?;
+        for row in rows {
+            let (utxo, cost_basis, entry_id) = row?;
+            book.opening_balances.push(OpeningBalance {
+                utxo: decode_json(&utxo, "opening balance")?,
+                cost_basis: decode_decimal(&cost_basis)?,
+                entry_id,
+            });
+        }
+
+        let mut statement = self.conn.prepare(&format!("SELECT outpoint, {} FROM utxos ORDER BY rowid", UTXO_COLUMNS))?;
+        for row in statement.query_map([], |row| Ok((row.get::<_, String>(0)?, read_raw_utxo(row, 1)?)))? {
+            let (outpoint, raw) = row?;
+            let outpoint = decode_outpoint(&outpoint)?;
+            let mut utxo = decode_utxo(raw)?;
+            // Older books could hold an output txid that differs from its transaction's
+            utxo.txid.clone_from(&outpoint.txid);
+            book.utxos.push((outpoint, utxo));
+        }
+
+        let mut statement = self.conn.prepare("SELECT date, rate FROM exchange_rates ORDER BY date")?;
+        for row in statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (date, rate) = row?;
+            book.rates.push((decode_time(&date)?, decode_decimal(&rate)?));
+        }
+
+        let mut statement = self.conn.prepare("SELECT wallet, address FROM wallet_addresses ORDER BY rowid")?;
+        book.wallet_addresses = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut statement = self.conn.prepare(
+            "SELECT reporting_date, quantity, rate, fair_value, carrying_amount, adjustment, entry_id
+             FROM remeasurements ORDER BY rowid",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                [
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ],
+                row.get::<_, Option<u64>>(6)?,
+            ))
+        })?;
+        for row in rows {
+            let (reporting_date, amounts, entry_id) = row?;
+            book.remeasurements.push(Remeasurement {
+                reporting_date: decode_time(&reporting_date)?,
+                quantity: decode_amount(&amounts[0])?,
+                rate: decode_decimal(&amounts[1])?,
+                fair_value: decode_decimal(&amounts[2])?,
+                carrying_amount: decode_decimal(&amounts[3])?,
+                adjustment: decode_decimal(&amounts[4])?,
+                entry_id,
+            });
+        }
+
+        let mut statement = self
+            .conn
+            .prepare("SELECT name, start_date, end_date, closed_at, balances, net_income, entry_id FROM period_closes ORDER BY rowid")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                [
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ],
+                row.get::<_, Option<u64>>(6)?,
+            ))
+        })?;
+        for row in rows {
+            let ([name, start, end, closed_at, balances, net_income], entry_id) = row?;
+            book.period_closes.push(PeriodClose {
+                period: FiscalPeriod::new(&name, decode_time(&start)?, decode_time(&end)?),
+                closed_at: decode_time(&closed_at)?,
+                balances: serde_json::from_str(&balances)
+                    .map_err(|err| StorageError::Corrupt(format!("invalid balances for period {}: {}", name, err)))?,
+                net_income: decode_decimal(&net_income)?,
+                entry_id,
+            });
+        }
+
+        let mut statement = self.conn.prepare(
+            "SELECT transaction_json, reason, recorded_at, reversed_entries, entry_ids, posted_on FROM reversals ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                [
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ],
+                row.get::<_, Option<String>>(5)?,
+            ))
+        })?;
+        for row in rows {
+            let ([transaction, reason, recorded_at, reversed_entries, entry_ids], posted_on) = row?;
+            book.reversals.push(Reversal {
+                transaction: decode_json(&transaction, "reversed transaction")?,
+                reason: decode_json(&reason, "reversal reason")?,
+                recorded_at: decode_time(&recorded_at)?,
+                posted_on: posted_on.as_deref().map(decode_time).transpose()?,
+                reversed_entries: decode_json(&reversed_entries, "reversed entry ids")?,
+                entry_ids: decode_json(&entry_ids, "reversal entry ids")?,
+            });
+        }
+
+        let mut statement = self.conn.prepare("SELECT key, value FROM settings")?;
+        book.settings = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        Ok(book)
+    }
+
+    fn record_posting(&mut self, posting: &Posting) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        insert_posting(&tx, posting)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_opening_balance(&mut self, opening: &OpeningBalance, entry: Option<&JournalEntry>, lots: &[Lot]) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        if let Some(entry) = entry {
+            insert_journal_entry(&tx, entry)?;
+        }
+        let outpoint = opening.utxo.outpoint();
+        tx.execute(
+            "INSERT INTO opening_balances (outpoint, utxo_json, cost_basis, entry_id) VALUES (?1, ?2, ?3, ?4)",
+            params![outpoint.to_string(), encode_json(&opening.utxo), opening.cost_basis.to_string(), opening.entry_id],
+        )?;
+        insert_utxo(&tx, &outpoint, &opening.utxo)?;
+        replace_lots(&tx, lots)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_remeasurement(&mut self, remeasurement: &Remeasurement, entry: Option<&JournalEntry>) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        if let Some(entry) = entry {
+            insert_journal_entry(&tx, entry)?;
+        }
+        insert_remeasurement(&tx, remeasurement)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_period_close(&mut self, close: &PeriodClose, remeasurement: &Remeasurement, entries: &[JournalEntry]) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        for entry in entries {
+            insert_journal_entry(&tx, entry)?;
+        }
+        insert_remeasurement(&tx, remeasurement)?;
+        tx.execute(
+            "INSERT INTO period_closes (name, start_date, end_date, closed_at, balances, net_income, entry_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                close.period.name,
+                encode_time(close.period.start),
+                encode_time(close.period.end),
+                encode_time(close.closed_at),
+                serde_json::to_string(&close.balances).expect("balances serialize to JSON"),
+                close.net_income.to_string(),
+                close.entry_id
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_reversals(&mut self, posting: &ReversalPosting) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        insert_reversals(&tx, posting)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_replacement(&mut self, reversals: &ReversalPosting, posting: &Posting) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        insert_reversals(&tx, reversals)?;
+        insert_posting(&tx, posting)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn record_confirmations(&mut self, transaction: &Transaction) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "UPDATE transactions SET block_hash = ?2, block_height = ?3 WHERE txid = ?1",
+            params![
+                transaction.txid,
+                transaction.block.as_ref().map(|block| &block.hash),
+                transaction.block.as_ref().map(|block| block.height)
+            ],
+        )?;
+        for output in &transaction.outputs {
+            tx.execute(
+                "UPDATE transaction_utxos SET confirmations = ?3 WHERE txid = ?1 AND role = 'output' AND vout = ?2",
+                params![transaction.txid, output.vout, output.confirmations],
+            )?;
+            tx.execute(
+                "UPDATE utxos SET confirmations = ?2 WHERE outpoint = ?1",
+                params![output.outpoint().to_string(), output.confirmations],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn insert_rate(&mut self, date: DateTime<Utc>, rate: Decimal) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO exchange_rates (date, rate) VALUES (?1, ?2)",
+            params![encode_time(date), rate.to_string()],
+        )?;
+        Ok(())
+    }
+
+    fn insert_wallet_address(&mut self, wallet: &str, address: &str) -> Result<(), StorageError> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO wallet_addresses (address, wallet) VALUES (?1, ?2)",
+            params![address, wallet],
+        )?;
+        Ok(())
+    }
+
+    fn set_restrictions(&mut self, outpoint: &OutPoint, restrictions: &[Restriction]) -> Result<(), StorageError> {
+        self.conn.execute(
+            "UPDATE utxos SET restrictions = ?2 WHERE outpoint = ?1",
+            params![outpoint.to_string(), encode_restrictions(restrictions)],
+        )?;
+        Ok(())
+    }
+
+    fn set_setting(&mut self, key: &str, value: &str) -> Result<(), StorageError> {
+        self.conn
+            .execute("INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)", params![key, value])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_bring_an_older_book_up_to_date() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        conn.execute("INSERT INTO transactions (txid, timestamp, fee) VALUES ('aa', '2024-01-01T00:00:00Z', '0')", [])
+            .unwrap();
+
+        let storage = SqliteStorage::from_connection(conn).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+        let kept: usize = storage.conn.query_row("SELECT COUNT(*) FROM transactions", [], |row| row.get(0)).unwrap();
+        assert_eq!(kept, 1);
+    }
+
+    #[test]
+    fn migrating_an_up_to_date_book_changes_nothing() {
+        let mut storage = SqliteStorage::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        migrate(&mut storage.conn).unwrap();
+        assert_eq!(storage.schema_version().unwrap(), MIGRATIONS.len());
+    }
+}
@@ -0,0 +1,19 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+
+pub mod acb;
+pub mod form8949;
+pub mod section104;
+
+fn cents(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// Two decimals, negatives in parentheses as tax forms show them.
+fn money(value: Decimal) -> String {
+    let value = cents(value);
+    if value.is_sign_negative() && !value.is_zero() {
+        format!("({:.2})", value.abs())
+    } else {
+        format!("{:.2}", value.abs())
+    }
+}
@@ -0,0 +1,231 @@
+//! Canadian adjusted cost base (ACB): a running weighted-average cost across all
+//! bitcoin held, with the superficial loss rule.
+
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::amount::Amount;
+use crate::period::FiscalPeriod;
+use crate::rates::RateError;
+use crate::BitcoinAccountingApp;
+
+/// Days either side of a disposal in which buying back makes a loss superficial.
+pub const SUPERFICIAL_LOSS_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AcbEventKind {
+    Acquisition,
+    Disposition,
+}
+
+/// One acquisition or disposition and the pool after it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcbEntry {
+    pub date: DateTime<Utc>,
+    pub txid: String,
+    pub kind: AcbEventKind,
+    pub units: Amount,
+    /// Cost of an acquisition or proceeds of a disposition.
+    pub amount: Decimal,
+    /// ACB of the units disposed; zero for acquisitions.
+    pub acb_disposed: Decimal,
+    /// Proceeds less ACB disposed, after adding back any superficial loss.
+    pub gain_loss: Decimal,
+    /// Loss denied because identical units were bought within 30 days either side
+    /// and still held; it is added to the ACB of the pool.
+    pub superficial_loss: Decimal,
+    pub units_held: Amount,
+    pub total_acb: Decimal,
+}
+
+impl AcbEntry {
+    pub fn acb_per_unit(&self) -> Decimal {
+        if self.units_held.is_zero() {
+            Decimal::ZERO
+        } else {
+            self.total_acb / self.units_held.to_btc()
+        }
+    }
+}
+
+/// ACB activity within a period, with the pool on either side of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcbComputation {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub opening_units: Amount,
+    pub opening_acb: Decimal,
+    pub entries: Vec<AcbEntry>,
+    pub closing_units: Amount,
+    pub closing_acb: Decimal,
+}
+
+impl AcbComputation {
+    fn dispositions(&self) -> impl Iterator<Item = &AcbEntry> {
+        self.entries.iter().filter(|entry| entry.kind == AcbEventKind::Disposition)
+    }
+
+    pub fn proceeds(&self) -> Decimal {
+        self.dispositions().map(|entry| entry.amount).sum()
+    }
+
+    pub fn acb_disposed(&self) -> Decimal {
+        self.dispositions().map(|entry| entry.acb_disposed).sum()
+    }
+
+    /// Net capital gain or loss after superficial losses are denied.
+    pub fn gain_loss(&self) -> Decimal {
+        self.dispositions().map(|entry| entry.gain_loss).sum()
+    }
+
+    pub fn superficial_losses(&self) -> Decimal {
+        self.dispositions().map(|entry| entry.superficial_loss).sum()
+    }
+}
+
+/// An acquisition, or all the lots relieved by one disposing transaction.
+struct Event {
+    date: DateTime<Utc>,
+    txid: String,
+    kind: AcbEventKind,
+    units: Amount,
+    amount: Decimal,
+}
+
+impl BitcoinAccountingApp {
+    /// ACB computation for dispositions dated within `period`.
+    ///
+    /// Acquisitions and disposals come from the same replay as the lot book, so transfers
+    /// between owned wallets are neither. Each acquisition adds its cost to the pool, and
+    /// each disposal takes `units × ACB per unit` out of it, whatever `CostBasisMethod`
+    /// the ledger uses. A loss is superficial in proportion to the smallest of the units
+    /// disposed, the units acquired in the 61 days around the disposal, and the units
+    /// held 30 days after it.
+    pub fn acb_computation(&self, period: &FiscalPeriod) -> Result<AcbComputation, RateError> {
+        let (start, end) = (period.start, period.end);
+        let replay = self.replay_lots()?;
+        let mut events: Vec<Event> = Vec::new();
+        for (txid, lot) in replay.opened.iter().chain(&replay.received) {
+            events.push(Event {
+                date: lot.acquired_at,
+                txid: txid.clone(),
+                kind: AcbEventKind::Acquisition,
+                units: lot.quantity,
+                amount: lot.cost_basis,
+            });
+        }
+        for realized in &replay.realized {
+            match events
+                .iter_mut()
+                .find(|event| event.kind == AcbEventKind::Disposition && event.txid == realized.txid)
+            {
+                Some(event) => {
+                    event.units += realized.quantity;
+                    event.amount += realized.proceeds;
+                }
+                None => events.push(Event {
+                    date: realized.disposed_at,
+                    txid: realized.txid.clone(),
+                    kind: AcbEventKind::Disposition,
+                    units: realized.quantity,
+                    amount: realized.proceeds,
+                }),
+            }
+        }
+        // Acquisitions come first at the same instant, so a same-time buy is in the pool
+        events.sort_by_key(|event| (event.date, event.kind == AcbEventKind::Disposition));
+
+        let held_at = |date: DateTime<Utc>| {
+            let mut sats: i128 = 0;
+            for event in events.iter().filter(|event| event.date <= date) {
+                match event.kind {
+                    AcbEventKind::Acquisition => sats += i128::from(event.units.to_sat()),
+                    AcbEventKind::Disposition => sats -= i128::from(event.units.to_sat()),
+                }
+            }
+            Amount::from_sat(u64::try_from(sats).unwrap_or_default())
+        };
+        let window = Duration::days(SUPERFICIAL_LOSS_DAYS);
+
+        let mut units_held = Amount::ZERO;
+        let mut total_acb = Decimal::ZERO;
+        let mut opening = None;
+        let mut entries = Vec::new();
+        for Event {
+            date,
+            txid,
+            kind,
+            units,
+            amount,
+        } in &events
+        {
+            if *date > end {
+                break;
+            }
+            if *date >= start && opening.is_none() {
+                opening = Some((units_held, total_acb));
+            }
+            let mut acb_disposed = Decimal::ZERO;
+            let mut gain_loss = Decimal::ZERO;
+            let mut superficial_loss = Decimal::ZERO;
+            match kind {
+                AcbEventKind::Acquisition => {
+                    units_held += *units;
+                    total_acb += *amount;
+                }
+                AcbEventKind::Disposition => {
+                    let disposed = (*units).min(units_held);
+                    acb_disposed = if disposed == units_held {
+                        total_acb
+                    } else {
+                        (total_acb * Decimal::from(disposed.to_sat()) / Decimal::from(units_held.to_sat())).round_dp(8)
+                    };
+                    units_held -= disposed;
+                    total_acb -= acb_disposed;
+                    gain_loss = *amount - acb_disposed;
+
+                    if gain_loss.is_sign_negative() && !units.is_zero() {
+                        let acquired: Amount = events
+                            .iter()
+                            .filter(|event| event.kind == AcbEventKind::Acquisition && event.date >= *date - window && event.date <= *date + window)
+                            .map(|event| event.units)
+                            .sum();
+                        let substituted = (*units).min(acquired).min(held_at(*date + window));
+                        if !substituted.is_zero() {
+                            superficial_loss = (-gain_loss * Decimal::from(substituted.to_sat()) / Decimal::from(units.to_sat())).round_dp(8);
+                            gain_loss += superficial_loss;
+                            total_acb += superficial_loss;
+                        }
+                    }
+                }
+            }
+            if *date >= start {
+                entries.push(AcbEntry {
+                    date: *date,
+                    txid: txid.clone(),
+                    kind: *kind,
+                    units: *units,
+                    amount: *amount,
+                    acb_disposed,
+                    gain_loss,
+                    superficial_loss,
+                    units_held,
+                    total_acb,
+                });
+            }
+        }
+        let (opening_units, opening_acb) = opening.unwrap_or((units_held, total_acb));
+
+        Ok(AcbComputation {
+            start,
+            end,
+            opening_units,
+            opening_acb,
+            entries,
+            closing_units: units_held,
+            closing_acb: total_acb,
+        })
+    }
+}
@@ -0,0 +1,278 @@
+//! IRS Form 8949 (Sales and Other Dispositions of Capital Assets) and the
+//! Schedule D lines its totals carry to.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+use super::{cents, money};
+use crate::lots::{HoldingTerm, RealizedLot};
+use crate::outpoint::OutPoint;
+use crate::period::FiscalPeriod;
+use crate::rates::RateError;
+use crate::calendar::ReportingCalendar;
+use crate::BitcoinAccountingApp;
+
+/// One disposal of one lot, as entered on a Form 8949 line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Form8949Row {
+    /// Column (a), e.g. "0.5 BTC".
+    pub description: String,
+    /// Column (b), the local date the lot was acquired.
+    pub date_acquired: NaiveDate,
+    /// Column (c), the local date of the disposing transaction.
+    pub date_sold: NaiveDate,
+    /// Column (d), rounded to cents.
+    pub proceeds: Decimal,
+    /// Column (e), rounded to cents.
+    pub cost_basis: Decimal,
+    /// Column (f); empty when there is no adjustment.
+    pub adjustment_code: String,
+    /// Column (g).
+    pub adjustment: Decimal,
+    /// Column (h): proceeds less cost basis plus adjustment.
+    pub gain_loss: Decimal,
+    pub term: HoldingTerm,
+    pub txid: String,
+    pub outpoint: OutPoint,
+}
+
+impl Form8949Row {
+    fn from_realized(realized: &RealizedLot, calendar: &ReportingCalendar) -> Self {
+        let proceeds = cents(realized.proceeds);
+        let cost_basis = cents(realized.cost_basis);
+        Form8949Row {
+            description: format!("{} BTC", realized.quantity),
+            date_acquired: calendar.local_date(realized.acquired_at),
+            date_sold: calendar.local_date(realized.disposed_at),
+            proceeds,
+            cost_basis,
+            adjustment_code: String::new(),
+            adjustment: Decimal::ZERO,
+            gain_loss: proceeds - cost_basis,
+            term: realized.term,
+            txid: realized.txid.clone(),
+            outpoint: realized.outpoint.clone(),
+        }
+    }
+}
+
+/// Column totals of one part of Form 8949, equal to the matching Schedule D line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Form8949Totals {
+    pub proceeds: Decimal,
+    pub cost_basis: Decimal,
+    pub adjustment: Decimal,
+    pub gain_loss: Decimal,
+}
+
+impl Form8949Totals {
+    fn of(rows: &[Form8949Row]) -> Self {
+        Form8949Totals {
+            proceeds: rows.iter().map(|row| row.proceeds).sum(),
+            cost_basis: rows.iter().map(|row| row.cost_basis).sum(),
+            adjustment: rows.iter().map(|row| row.adjustment).sum(),
+            gain_loss: rows.iter().map(|row| row.gain_loss).sum(),
+        }
+    }
+}
+
+/// Schedule D lines fed by Form 8949 boxes C and F.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleD {
+    /// Line 3: short-term totals from Form 8949 with box C checked.
+    pub line_3: Form8949Totals,
+    /// Line 10: long-term totals from Form 8949 with box F checked.
+    pub line_10: Form8949Totals,
+}
+
+/// Realized dispositions for a tax period, split into Part I and Part II.
+///
+/// Bitcoin sales are not reported on Form 1099-B, so Part I uses box C and Part II
+/// uses box F.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Form8949 {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// First and last local days of the period at the reporting UTC offset.
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    /// Part I: held one year or less.
+    pub short_term: Vec<Form8949Row>,
+    /// Part II: held more than one year.
+    pub long_term: Vec<Form8949Row>,
+}
+
+impl Form8949 {
+    /// Builds the form for `realized`, dating rows at `calendar`'s UTC offset.
+    pub fn new(calendar: &ReportingCalendar, start: DateTime<Utc>, end: DateTime<Utc>, realized: &[RealizedLot]) -> Self {
+        let (short_term, long_term) = realized
+            .iter()
+            .map(|realized| Form8949Row::from_realized(realized, calendar))
+            .partition(|row| row.term == HoldingTerm::ShortTerm);
+        Form8949 {
+            start,
+            end,
+            first_day: calendar.local_date(start),
+            last_day: calendar.local_date(end),
+            short_term,
+            long_term,
+        }
+    }
+
+    pub fn short_term_totals(&self) -> Form8949Totals {
+        Form8949Totals::of(&self.short_term)
+    }
+
+    pub fn long_term_totals(&self) -> Form8949Totals {
+        Form8949Totals::of(&self.long_term)
+    }
+
+    pub fn schedule_d(&self) -> ScheduleD {
+        ScheduleD {
+            line_3: self.short_term_totals(),
+            line_10: self.long_term_totals(),
+        }
+    }
+
+    /// Plain-text rendering following the form's parts, boxes, columns (a) to (h) and
+    /// line 2 totals, with dates as MM/DD/YYYY and losses in parentheses.
+    pub fn line_layout(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Form 8949  Sales and Other Dispositions of Capital Assets");
+        let _ = writeln!(out, "Period {} to {}", self.first_day, self.last_day);
+        let parts = [
+            (
+                "Part I   Short-Term. Transactions involving capital assets you held 1 year or less",
+                "Box C    Short-term transactions not reported to you on Form 1099-B",
+                &self.short_term,
+            ),
+            (
+                "Part II  Long-Term. Transactions involving capital assets you held more than 1 year",
+                "Box F    Long-term transactions not reported to you on Form 1099-B",
+                &self.long_term,
+            ),
+        ];
+        for (part, checkbox, rows) in parts {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "{}", part);
+            let _ = writeln!(out, "{}", checkbox);
+            let _ = writeln!(
+                out,
+                "{:<24} {:<10} {:<10} {:>16} {:>16} {:<4} {:>14} {:>16}",
+                "(a) Description", "(b) Acq.", "(c) Sold", "(d) Proceeds", "(e) Cost basis", "(f)", "(g) Adjustment", "(h) Gain/(loss)"
+            );
+            for row in rows.iter() {
+                let _ = writeln!(
+                    out,
+                    "{:<24} {:<10} {:<10} {:>16} {:>16} {:<4} {:>14} {:>16}",
+                    row.description,
+                    row.date_acquired.format("%m/%d/%Y"),
+                    row.date_sold.format("%m/%d/%Y"),
+                    money(row.proceeds),
+                    money(row.cost_basis),
+                    row.adjustment_code,
+                    money(row.adjustment),
+                    money(row.gain_loss),
+                );
+            }
+            let totals = Form8949Totals::of(rows);
+            let _ = writeln!(
+                out,
+                "{:<46} {:>16} {:>16} {:<4} {:>14} {:>16}",
+                "2  Totals",
+                money(totals.proceeds),
+                money(totals.cost_basis),
+                "",
+                money(totals.adjustment),
+                money(totals.gain_loss),
+            );
+        }
+        out
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// Form 8949 for dispositions dated within `period`.
+    pub fn form_8949(&self, period: &FiscalPeriod) -> Result<Form8949, RateError> {
+        Ok(Form8949::new(&self.calendar, period.start, period.end, &self.realized_gains_by_lot(period)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::calendar::FiscalYearStart;
+    use chrono::{FixedOffset, TimeZone};
+    use rust_decimal_macros::dec;
+
+    fn realized(acquired: (i32, u32, u32), disposed: (i32, u32, u32, u32), proceeds: Decimal, cost_basis: Decimal, term: HoldingTerm) -> RealizedLot {
+        let acquired_at = Utc.with_ymd_and_hms(acquired.0, acquired.1, acquired.2, 12, 0, 0).unwrap();
+        let disposed_at = Utc.with_ymd_and_hms(disposed.0, disposed.1, disposed.2, disposed.3, 0, 0).unwrap();
+        RealizedLot {
+            txid: "22".repeat(32),
+            disposed_at,
+            outpoint: OutPoint {
+                txid: "11".repeat(32),
+                vout: 0,
+            },
+            acquired_at,
+            term,
+            quantity: Amount::from_sat(10_000_000),
+            proceeds,
+            cost_basis,
+            gain_loss: proceeds - cost_basis,
+        }
+    }
+
+    fn form(realized: &[RealizedLot]) -> Form8949 {
+        let calendar = ReportingCalendar::new(FixedOffset::west_opt(5 * 3600).unwrap(), FiscalYearStart::JANUARY_1);
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 5, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 1, 1, 5, 0, 0).unwrap() - chrono::Duration::nanoseconds(1);
+        Form8949::new(&calendar, start, end, realized)
+    }
+
+    #[test]
+    fn totals_are_of_rounded_rows_and_carry_to_schedule_d() {
+        let form = form(&[
+            realized((2024, 1, 10), (2024, 3, 1, 12), dec!(1000.005), dec!(800.004), HoldingTerm::ShortTerm),
+            realized((2024, 2, 10), (2024, 4, 1, 12), dec!(500.125), dec!(700.333), HoldingTerm::ShortTerm),
+            realized((2022, 1, 10), (2024, 5, 1, 12), dec!(3000), dec!(1000), HoldingTerm::LongTerm),
+        ]);
+        assert_eq!(form.short_term.len(), 2);
+        assert_eq!((form.short_term[0].proceeds, form.short_term[0].cost_basis), (dec!(1000.01), dec!(800.00)));
+        assert_eq!(
+            form.short_term_totals(),
+            Form8949Totals {
+                proceeds: dec!(1500.14),
+                cost_basis: dec!(1500.33),
+                adjustment: dec!(0),
+                gain_loss: dec!(-0.19),
+            }
+        );
+        assert_eq!(form.long_term_totals().gain_loss, dec!(2000));
+
+        let schedule_d = form.schedule_d();
+        assert_eq!(schedule_d.line_3, form.short_term_totals());
+        assert_eq!(schedule_d.line_10, form.long_term_totals());
+    }
+
+    #[test]
+    fn rows_are_dated_by_local_day() {
+        // 02:00 UTC on 1 March is still 29 February at UTC-05:00
+        let form = form(&[realized((2024, 1, 10), (2024, 3, 1, 2), dec!(100), dec!(50), HoldingTerm::ShortTerm)]);
+        assert_eq!(form.short_term[0].date_sold, NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+        assert_eq!((form.first_day, form.last_day), (NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), NaiveDate::from_ymd_opt(2024, 12, 31).unwrap()));
+    }
+
+    #[test]
+    fn line_layout_shows_losses_in_parentheses() {
+        let layout = form(&[realized((2024, 1, 10), (2024, 4, 1, 12), dec!(500), dec!(700.5), HoldingTerm::ShortTerm)]).line_layout();
+        assert!(layout.contains("Period 2024-01-01 to 2024-12-31"));
+        assert!(layout.contains("01/10/2024 04/01/2024"));
+        assert!(layout.contains("(200.50)"));
+        assert!(layout.contains("Box F"));
+    }
+}
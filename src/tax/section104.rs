@@ -0,0 +1,297 @@
+//! UK capital gains on bitcoin under TCGA 1992: same-day matching, the 30-day
+//! bed-and-breakfast rule and the Section 104 pool.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::money;
+use crate::amount::Amount;
+use crate::period::FiscalPeriod;
+use crate::rates::RateError;
+use crate::BitcoinAccountingApp;
+
+/// Which identification rule matched part of a disposal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+pub enum MatchRule {
+    /// Acquired on the day of the disposal.
+    SameDay,
+    /// Acquired within the 30 days after the disposal.
+    BedAndBreakfast { acquired_on: NaiveDate },
+    /// Taken from the pool at its average cost.
+    Section104,
+}
+
+impl std::fmt::Display for MatchRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatchRule::SameDay => f.write_str("Same day"),
+            MatchRule::BedAndBreakfast { acquired_on } => write!(f, "Bed and breakfast (acquired {})", acquired_on.format("%d/%m/%Y")),
+            MatchRule::Section104 => f.write_str("Section 104 pool"),
+        }
+    }
+}
+
+/// Part of a disposal matched by one rule, with its share of the proceeds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CgtMatch {
+    pub rule: MatchRule,
+    pub quantity: Amount,
+    pub proceeds: Decimal,
+    pub allowable_cost: Decimal,
+    pub gain_loss: Decimal,
+}
+
+/// All disposals on one day, which HMRC treats as a single disposal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CgtDisposal {
+    pub date: NaiveDate,
+    pub txids: Vec<String>,
+    pub quantity: Amount,
+    pub proceeds: Decimal,
+    pub allowable_cost: Decimal,
+    pub gain_loss: Decimal,
+    pub matches: Vec<CgtMatch>,
+}
+
+/// Quantity and pooled allowable cost in the Section 104 holding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section104Pool {
+    pub quantity: Amount,
+    pub cost: Decimal,
+}
+
+/// HMRC capital gains computation for disposals within a period.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CgtComputation {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// First and last local days of the period at the reporting UTC offset.
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+    pub disposals: Vec<CgtDisposal>,
+    /// The pool after the last day in the period.
+    pub pool: Section104Pool,
+}
+
+impl CgtComputation {
+    pub fn disposal_proceeds(&self) -> Decimal {
+        self.disposals.iter().map(|disposal| disposal.proceeds).sum()
+    }
+
+    pub fn allowable_costs(&self) -> Decimal {
+        self.disposals.iter().map(|disposal| disposal.allowable_cost).sum()
+    }
+
+    /// Gains on disposals that made a gain, before losses.
+    pub fn gains(&self) -> Decimal {
+        self.disposals.iter().map(|disposal| disposal.gain_loss.max(Decimal::ZERO)).sum()
+    }
+
+    /// Losses on disposals that made a loss, as a positive amount.
+    pub fn losses(&self) -> Decimal {
+        self.disposals.iter().map(|disposal| (-disposal.gain_loss).max(Decimal::ZERO)).sum()
+    }
+
+    /// Plain-text computation: each disposal with its matching, proceeds less allowable
+    /// costs, the closing pool and the SA108 summary boxes. Dates are DD/MM/YYYY.
+    pub fn computation(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Capital gains computation: Bitcoin (BTC)");
+        let _ = writeln!(out, "Period {} to {}", self.first_day, self.last_day);
+        for (number, disposal) in self.disposals.iter().enumerate() {
+            let _ = writeln!(out);
+            let _ = writeln!(out, "Disposal {}: {} BTC on {}", number + 1, disposal.quantity, disposal.date.format("%d/%m/%Y"));
+            for matched in &disposal.matches {
+                let _ = writeln!(
+                    out,
+                    "  {:<40} {:>16} {:>16}",
+                    matched.rule.to_string(),
+                    format!("{} BTC", matched.quantity),
+                    money(matched.allowable_cost)
+                );
+            }
+            let _ = writeln!(out, "  {:<57} {:>16}", "Disposal proceeds", money(disposal.proceeds));
+            let _ = writeln!(out, "  {:<57} {:>16}", "Less allowable costs", money(disposal.allowable_cost));
+            let _ = writeln!(out, "  {:<57} {:>16}", "Gain/(loss)", money(disposal.gain_loss));
+        }
+        let _ = writeln!(out);
+        let _ = writeln!(
+            out,
+            "Section 104 pool at {}: {} BTC, pooled cost {}",
+            self.last_day.format("%d/%m/%Y"),
+            self.pool.quantity,
+            money(self.pool.cost)
+        );
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Summary");
+        let _ = writeln!(out, "  {:<57} {:>16}", "Number of disposals", self.disposals.len());
+        let _ = writeln!(out, "  {:<57} {:>16}", "Disposal proceeds", money(self.disposal_proceeds()));
+        let _ = writeln!(out, "  {:<57} {:>16}", "Allowable costs", money(self.allowable_costs()));
+        let _ = writeln!(out, "  {:<57} {:>16}", "Gains in the year, before losses", money(self.gains()));
+        let _ = writeln!(out, "  {:<57} {:>16}", "Losses in the year", money(self.losses()));
+        out
+    }
+}
+
+/// Acquisitions and disposals on one day, and what is left of each as rules match them.
+#[derive(Debug, Default)]
+struct Day {
+    acquired: Amount,
+    cost: Decimal,
+    disposed: Amount,
+    proceeds: Decimal,
+    txids: Vec<String>,
+    matches: Vec<(MatchRule, Amount, Decimal)>,
+}
+
+impl Day {
+    /// Takes up to `quantity` of the unmatched acquisitions, returning it with its cost.
+    fn take_acquired(&mut self, quantity: Amount) -> (Amount, Decimal) {
+        let taken = quantity.min(self.acquired);
+        let cost = prorate(self.cost, taken, self.acquired);
+        self.acquired -= taken;
+        self.cost -= cost;
+        (taken, cost)
+    }
+
+    fn unmatched_disposal(&self) -> Amount {
+        self.disposed - self.matches.iter().map(|(_, quantity, _)| *quantity).sum()
+    }
+}
+
+impl BitcoinAccountingApp {
+    /// UK capital gains computation for disposals on days within `period`.
+    ///
+    /// Acquisitions and disposals come from the same replay as the lot book, grouped by
+    /// local day at the reporting calendar's UTC offset. The period covers the local
+    /// days on which `period.start` and `period.end` fall and those between. Each day's
+    /// disposals are matched first with that day's acquisitions, then with acquisitions
+    /// in the following 30 days (earliest disposal first), and the rest against the
+    /// Section 104 pool at average cost. The whole history is
+    /// matched so the pool and later acquisitions are correct at the period edges.
+    pub fn uk_cgt_computation(&self, period: &FiscalPeriod) -> Result<CgtComputation, RateError> {
+        let (start, end) = (period.start, period.end);
+        let (first_day, last_day) = (self.calendar.local_date(start), self.calendar.local_date(end));
+        // Days from the first one excluded on are after the period
+        let end_day = last_day.succ_opt().unwrap_or(NaiveDate::MAX);
+        let replay = self.replay_lots()?;
+        let mut days: BTreeMap<NaiveDate, Day> = BTreeMap::new();
+        for (_, lot) in replay.opened.iter().chain(&replay.received) {
+            let day = days.entry(self.calendar.local_date(lot.acquired_at)).or_default();
+            day.acquired += lot.quantity;
+            day.cost += lot.cost_basis;
+        }
+        for realized in &replay.realized {
+            let day = days.entry(self.calendar.local_date(realized.disposed_at)).or_default();
+            day.disposed += realized.quantity;
+            day.proceeds += realized.proceeds;
+            if !day.txids.contains(&realized.txid) {
+                day.txids.push(realized.txid.clone());
+            }
+        }
+
+        for day in days.values_mut() {
+            let (quantity, cost) = day.take_acquired(day.disposed);
+            if !quantity.is_zero() {
+                day.matches.push((MatchRule::SameDay, quantity, cost));
+            }
+        }
+
+        let dates: Vec<NaiveDate> = days.keys().copied().collect();
+        for &disposed_on in &dates {
+            for &acquired_on in dates.iter().filter(|&&date| date > disposed_on && date <= disposed_on + Duration::days(30)) {
+                let needed = days[&disposed_on].unmatched_disposal();
+                if needed.is_zero() {
+                    break;
+                }
+                let (quantity, cost) = days.get_mut(&acquired_on).expect("date taken from the map").take_acquired(needed);
+                if !quantity.is_zero() {
+                    let rule = MatchRule::BedAndBreakfast { acquired_on };
+                    days.get_mut(&disposed_on).expect("date taken from the map").matches.push((rule, quantity, cost));
+                }
+            }
+        }
+
+        let mut pool = Section104Pool::default();
+        let mut closing_pool = pool;
+        for (&date, day) in days.iter_mut() {
+            pool.quantity += day.acquired;
+            pool.cost += day.cost;
+            let needed = day.unmatched_disposal();
+            if !needed.is_zero() {
+                let quantity = needed.min(pool.quantity);
+                let cost = prorate(pool.cost, quantity, pool.quantity);
+                pool.quantity -= quantity;
+                pool.cost -= cost;
+                day.matches.push((MatchRule::Section104, needed, cost));
+            }
+            if date < end_day {
+                closing_pool = pool;
+            }
+        }
+
+        let disposals = days
+            .into_iter()
+            .filter(|(date, day)| *date >= first_day && *date < end_day && !day.disposed.is_zero())
+            .map(|(date, day)| {
+                let count = day.matches.len();
+                let mut allocated = Decimal::ZERO;
+                let matches: Vec<CgtMatch> = day
+                    .matches
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (rule, quantity, allowable_cost))| {
+                        // The last match absorbs rounding so the parts add up to the day's proceeds
+                        let proceeds = if index + 1 == count {
+                            day.proceeds - allocated
+                        } else {
+                            prorate(day.proceeds, quantity, day.disposed)
+                        };
+                        allocated += proceeds;
+                        CgtMatch {
+                            rule,
+                            quantity,
+                            proceeds,
+                            allowable_cost,
+                            gain_loss: proceeds - allowable_cost,
+                        }
+                    })
+                    .collect();
+                let allowable_cost: Decimal = matches.iter().map(|matched| matched.allowable_cost).sum();
+                CgtDisposal {
+                    date,
+                    txids: day.txids,
+                    quantity: day.disposed,
+                    proceeds: day.proceeds,
+                    allowable_cost,
+                    gain_loss: day.proceeds - allowable_cost,
+                    matches,
+                }
+            })
+            .collect();
+
+        Ok(CgtComputation {
+            start,
+            end,
+            first_day,
+            last_day,
+            disposals,
+            pool: closing_pool,
+        })
+    }
+}
+
+/// `value` scaled by `part / whole` sats, exact when the part is the whole.
+fn prorate(value: Decimal, part: Amount, whole: Amount) -> Decimal {
+    if part == whole {
+        value
+    } else if whole.is_zero() {
+        Decimal::ZERO
+    } else {
+        (value * Decimal::from(part.to_sat()) / Decimal::from(whole.to_sat())).round_dp(8)
+    }
+}
@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::Transaction;
+
+/// How a transaction output relates to the entity's own wallets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputClass {
+    /// Paid to us by a transaction that spends none of our coins.
+    Receipt,
+    /// Returned to the wallet that funded the transaction.
+    Change,
+    /// Moved to another of our wallets.
+    InternalTransfer,
+    /// Paid out of the entity.
+    ExternalPayment,
+    /// Neither funded by nor paid to us.
+    Foreign,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputClassification {
+    pub vout: u32,
+    pub address: String,
+    pub class: OutputClass,
+}
+
+/// Addresses owned by the entity, grouped by wallet.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletRegistry {
+    wallets: BTreeMap<String, BTreeSet<String>>,
+    owners: HashMap<String, String>,
+}
+
+impl WalletRegistry {
+    /// Adds `address` to `wallet`, creating the wallet if needed. An address belongs to one wallet.
+    pub fn add_address(&mut self, wallet: &str, address: &str) {
+        if let Some(previous) = self.owners.insert(address.to_string(), wallet.to_string()) {
+            if let Some(addresses) = self.wallets.get_mut(&previous) {
+                addresses.remove(address);
+            }
+        }
+        self.wallets.entry(wallet.to_string()).or_default().insert(address.to_string());
+    }
+
+    pub fn wallet_of(&self, address: &str) -> Option<&str> {
+        self.owners.get(address).map(String::as_str)
+    }
+
+    pub fn is_owned(&self, address: &str) -> bool {
+        self.owners.contains_key(address)
+    }
+
+    pub fn wallets(&self) -> impl Iterator<Item = (&str, &BTreeSet<String>)> {
+        self.wallets.iter().map(|(name, addresses)| (name.as_str(), addresses))
+    }
+
+    /// Labels every output of `transaction` relative to the wallet funding it.
+    ///
+    /// The funding wallet is the wallet of the first owned input.
+    pub fn classify(&self, transaction: &Transaction) -> Vec<OutputClassification> {
+        let funding_wallet = transaction
+            .inputs
+            .iter()
+            .find_map(|input| self.wallet_of(&input.address));
+
+        transaction
+            .outputs
+            .iter()
+            .map(|output| {
+                let class = match (funding_wallet, self.wallet_of(&output.address)) {
+                    (None, Some(_)) => OutputClass::Receipt,
+                    (None, None) => OutputClass::Foreign,
+                    (Some(funding), Some(owner)) if funding == owner => OutputClass::Change,
+                    (Some(_), Some(_)) => OutputClass::InternalTransfer,
+                    (Some(_), None) => OutputClass::ExternalPayment,
+                };
+                OutputClassification {
+                    vout: output.vout,
+                    address: output.address.clone(),
+                    class,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::UTXO;
+    use chrono::Utc;
+    use rust_decimal::Decimal;
+
+    fn utxo(address: &str, vout: u32) -> UTXO {
+        UTXO {
+            txid: "11".repeat(32),
+            vout,
+            amount: Amount::from_sat(1000),
+            address: address.to_string(),
+            confirmations: 6,
+            spendable: true,
+            timestamp: Utc::now(),
+            restrictions: Vec::new(),
+        }
+    }
+
+    fn transaction(inputs: &[&str], outputs: &[&str]) -> Transaction {
+        Transaction {
+            txid: "22".repeat(32),
+            timestamp: Utc::now(),
+            inputs: inputs.iter().map(|address| utxo(address, 0)).collect(),
+            outputs: outputs.iter().enumerate().map(|(vout, address)| utxo(address, vout as u32)).collect(),
+            fee: Amount::ZERO,
+            block: None,
+            acquisition_fee: Decimal::ZERO,
+        }
+    }
+
+    fn registry() -> WalletRegistry {
+        let mut registry = WalletRegistry::default();
+        registry.add_address("hot", "hot-1");
+        registry.add_address("hot", "hot-2");
+        registry.add_address("cold", "cold-1");
+        registry
+    }
+
+    fn classes(registry: &WalletRegistry, transaction: &Transaction) -> Vec<OutputClass> {
+        registry.classify(transaction).into_iter().map(|output| output.class).collect()
+    }
+
+    #[test]
+    fn outputs_are_classified_against_the_funding_wallet() {
+        let registry = registry();
+        assert_eq!(
+            classes(&registry, &transaction(&["hot-1"], &["external", "hot-2", "cold-1"])),
+            vec![OutputClass::ExternalPayment, OutputClass::Change, OutputClass::InternalTransfer]
+        );
+        assert_eq!(
+            classes(&registry, &transaction(&["external"], &["cold-1", "elsewhere"])),
+            vec![OutputClass::Receipt, OutputClass::Foreign]
+        );
+        // The first owned input decides the funding wallet
+        assert_eq!(
+            classes(&registry, &transaction(&["external", "cold-1", "hot-1"], &["cold-1", "hot-1"])),
+            vec![OutputClass::Change, OutputClass::InternalTransfer]
+        );
+    }
+
+    #[test]
+    fn an_address_belongs_to_one_wallet() {
+        let mut registry = registry();
+        registry.add_address("cold", "hot-2");
+        assert_eq!(registry.wallet_of("hot-2"), Some("cold"));
+        let hot: Vec<&str> = registry
+            .wallets()
+            .find(|(name, _)| *name == "hot")
+            .map(|(_, addresses)| addresses.iter().map(String::as_str).collect())
+            .unwrap();
+        assert_eq!(hot, vec!["hot-1"]);
+        assert!(!registry.is_owned("external"));
+    }
+}
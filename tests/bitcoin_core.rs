@@ -0,0 +1,49 @@
+mod common;
+
+use bitcoin_accounting::import::bitcoin_core::CoreExport;
+use common::*;
+use rust_decimal_macros::dec;
+
+const LIST_TRANSACTIONS: &str = r#"[
+    {"address": "bc1q-hot", "category": "receive", "amount": 1.0, "vout": 0, "confirmations": 10,
+     "blockhash": "abababababababababababababababababababababababababababababababab", "blockheight": 820000,
+     "blocktime": 1704067200, "time": 1704067100,
+     "txid": "1111111111111111111111111111111111111111111111111111111111111111"}
+]"#;
+
+fn export(documents: &[&str]) -> CoreExport {
+    let mut export = CoreExport::new();
+    for document in documents {
+        export.add_document(document).unwrap();
+    }
+    export
+}
+
+#[test]
+fn importing_an_export_twice_books_it_once() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000))]);
+    let export = export(&[LIST_TRANSACTIONS]);
+
+    let first = app.import_bitcoin_core("hot", &export).unwrap();
+    assert_eq!(first.imported, vec![txid(0x11)]);
+    assert_eq!(app.wallets().wallet_of("bc1q-hot"), Some("hot"));
+    let entries = app.ledger().entries().len();
+
+    let second = app.import_bitcoin_core("hot", &export).unwrap();
+    assert!(second.imported.is_empty());
+    assert_eq!(second.skipped, vec![txid(0x11)]);
+    assert!(second.updated.is_empty());
+    assert_eq!(app.transactions().len(), 1);
+    assert_eq!(app.ledger().entries().len(), entries);
+}
+
+#[test]
+fn later_exports_update_confirmations() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000))]);
+    app.import_bitcoin_core("hot", &export(&[LIST_TRANSACTIONS])).unwrap();
+
+    let deeper = LIST_TRANSACTIONS.replace("\"confirmations\": 10", "\"confirmations\": 25");
+    let summary = app.import_bitcoin_core("hot", &export(&[&deeper])).unwrap();
+    assert_eq!(summary.updated, vec![txid(0x11)]);
+    assert_eq!(app.transactions()[0].confirmations(), 25);
+}
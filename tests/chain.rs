@@ -0,0 +1,109 @@
+mod common;
+
+use bitcoin_accounting::chain::BlockRef;
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::reversal::ReversalReason;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction};
+use common::*;
+use rust_decimal_macros::dec;
+
+fn block(n: u8, height: u32) -> BlockRef {
+    BlockRef { hash: txid(n), height }
+}
+
+fn mined(mut transaction: Transaction, block: BlockRef) -> Transaction {
+    transaction.block = Some(block);
+    transaction
+}
+
+/// Receives 1 BTC on Jan 1 in block `0xa1`, then pays 0.4 out on May 1 in block `0xa2`.
+fn book() -> (BitcoinAccountingApp, Transaction, Transaction) {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 5, 1), dec!(40000))]);
+    let received = mined(receipt(1, at(2024, 1, 1), OWNED[0], "1"), block(0xa1, 100));
+    let paid = mined(
+        transaction(2, at(2024, 5, 1), vec![output(&received, 0)], &[(EXTERNAL, "0.4"), (OWNED[1], "0.6")], "0"),
+        block(0xa2, 200),
+    );
+    app.add_transaction(received.clone()).unwrap();
+    app.add_transaction(paid.clone()).unwrap();
+    (app, received, paid)
+}
+
+#[test]
+fn orphaned_block_is_rolled_back() {
+    let (mut app, received, paid) = book();
+    let entries = app.ledger().entries().len();
+    let reversals = app.orphan_block(&txid(0xa2)).unwrap();
+
+    assert_eq!(reversals.len(), 1);
+    assert_eq!(reversals[0].transaction.txid, paid.txid);
+    assert_eq!(reversals[0].reason, ReversalReason::Orphaned { block: block(0xa2, 200) });
+    assert_eq!(reversals[0].reversed_entries.len(), reversals[0].entry_ids.len());
+    // The original entries stay, cancelled by reversals on their own dates
+    assert_eq!(app.ledger().entries().len(), entries + reversals[0].entry_ids.len());
+    let date = at(2024, 5, 1);
+    assert_eq!(balance(&app, "1500", date), dec!(30000));
+    assert_eq!(balance(&app, "1000", date), dec!(0));
+    assert_eq!(balance(&app, "4100", date), dec!(0));
+
+    assert!(app.transactions().iter().all(|transaction| transaction.txid != paid.txid));
+    assert!(app.get_utxo(&output(&received, 0).outpoint()).is_some());
+    assert!(app.get_utxo(&output(&paid, 1).outpoint()).is_none());
+    assert_eq!(app.reversals().len(), 1);
+    assert!(app.transactions_in_block(&txid(0xa2)).is_empty());
+
+    // Mined again in the new chain, it is booked afresh
+    app.add_transaction(mined(paid, block(0xb2, 200))).unwrap();
+    assert_eq!(balance(&app, "4100", date), dec!(-4000));
+}
+
+#[test]
+fn orphaning_a_block_takes_its_spenders_with_it() {
+    let (mut app, received, paid) = book();
+    let reversals = app.orphan_block(&txid(0xa1)).unwrap();
+    let reasons: Vec<(String, ReversalReason)> = reversals
+        .into_iter()
+        .map(|reversal| (reversal.transaction.txid, reversal.reason))
+        .collect();
+    assert!(reasons.contains(&(received.txid.clone(), ReversalReason::Orphaned { block: block(0xa1, 100) })));
+    assert!(reasons.contains(&(paid.txid.clone(), ReversalReason::DependsOn { txid: received.txid.clone() })));
+    assert!(app.transactions().is_empty());
+    assert_eq!(app.utxos_by_address(OWNED[0]).len() + app.utxos_by_address(OWNED[1]).len(), 0);
+    for code in ["1000", "1500", "4000", "4100"] {
+        assert_eq!(balance(&app, code, at(2024, 12, 31)), dec!(0), "{}", code);
+    }
+}
+
+#[test]
+fn unknown_block_changes_nothing() {
+    let (mut app, ..) = book();
+    let entries = app.ledger().entries().len();
+    let err = app.orphan_block(&txid(0xff)).unwrap_err();
+    assert!(matches!(err, AccountingError::UnknownBlock(ref hash) if *hash == txid(0xff)), "{}", err);
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert_eq!(app.transactions().len(), 2);
+}
+
+#[test]
+fn transactions_wait_for_min_confirmations() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000))]);
+    app.set_min_confirmations(6).unwrap();
+    let mut pending = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    pending.outputs[0].confirmations = 1;
+
+    let err = app.add_transaction(pending.clone()).unwrap_err();
+    assert!(
+        matches!(err, AccountingError::Unconfirmed { confirmations: 1, required: 6, .. }),
+        "{}",
+        err
+    );
+    assert!(app.transactions().is_empty());
+    assert!(app.ledger().entries().is_empty());
+
+    app.set_min_confirmations(0).unwrap();
+    pending.outputs[0].confirmations = 0;
+    app.add_transaction(pending.clone()).unwrap();
+    app.update_confirmations(&pending.txid, 6, Some(block(0xa1, 100))).unwrap();
+    assert_eq!(app.get_utxo(&output(&pending, 0).outpoint()).map(|utxo| utxo.confirmations), Some(6));
+    assert_eq!(app.transactions_in_block(&txid(0xa1)).len(), 1);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde_json::Value;
+
+/// A fresh working directory for one book, removed when dropped.
+struct Workspace(PathBuf);
+
+impl Workspace {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("bitcoin-accounting-cli-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Workspace(dir)
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.0.join(file)
+    }
+
+    fn write(&self, file: &str, contents: &str) -> PathBuf {
+        let path = self.path(file);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_bitcoin-accounting"))
+            .current_dir(&self.0)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Runs a command that must succeed, returning its standard output.
+    fn ok(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn json(&self, args: &[&str]) -> Value {
+        serde_json::from_str(&self.ok(&[args, &["--format", "json"]].concat())).unwrap()
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn file_arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// A book owning `OWNED` that bought 1 BTC at 30k in January and paid 0.4 of it out at
+/// 40k in February.
+fn book(name: &str) -> Workspace {
+    let workspace = Workspace::new(name);
+    workspace.ok(&["init", "--max-staleness", "none"]);
+    workspace.ok(&[&["add-address", "treasury"][..], &OWNED].concat());
+    let rates = workspace.write("rates.csv", "timestamp,rate\n2024-01-01T00:00:00Z,30000\n2024-02-01T00:00:00Z,40000\n");
+    workspace.ok(&["import", "rates", file_arg(&rates)]);
+
+    let bought = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    let paid = transaction(2, at(2024, 2, 1), vec![output(&bought, 0)], &[(EXTERNAL, "0.4"), (OWNED[1], "0.6")], "0");
+    let transactions = workspace.write("transactions.json", &serde_json::to_string(&[bought, paid]).unwrap());
+    workspace.ok(&["import", "transactions", file_arg(&transactions)]);
+    workspace
+}
+
+fn decimal(value: &Value) -> Decimal {
+    value.as_str().unwrap().parse().unwrap()
+}
+
+#[test]
+fn init_refuses_an_existing_book() {
+    let workspace = book("init");
+    let output = workspace.run(&["init"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    assert!(!workspace.run(&["--book", "missing.sqlite", "utxos"]).status.success());
+}
+
+#[test]
+fn utxos_and_gains_come_from_the_imported_book() {
+    let workspace = book("reports");
+    let utxos = workspace.json(&["utxos"]);
+    let utxos = utxos.as_array().unwrap();
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0]["outpoint"], format!("{}:1", txid(2)));
+    assert_eq!(utxos[0]["amount"], "0.6");
+
+    let gains = workspace.ok(&["report", "gains", "--format", "csv"]);
+    let mut rows = gains.lines();
+    let header: Vec<&str> = rows.next().unwrap().split(',').collect();
+    let row: Vec<&str> = rows.next().unwrap().split(',').collect();
+    let column = |name: &str| row[header.iter().position(|column| *column == name).unwrap()].parse::<Decimal>().unwrap();
+    assert_eq!((column("proceeds"), column("cost_basis"), column("gain_loss")), (dec!(16000), dec!(12000), dec!(4000)));
+    assert!(rows.next().is_none());
+}
+
+#[test]
+fn reports_end_at_the_latest_rate_by_default() {
+    let workspace = book("latest");
+    let fair_value = |sheet: &Value| {
+        let line = sheet.as_array().unwrap().iter().find(|line| line["account"] == "1500").unwrap();
+        decimal(&line["current"])
+    };
+    assert_eq!(fair_value(&workspace.json(&["report", "balance-sheet"])), dec!(24000));
+
+    let rates = workspace.write("later.csv", "timestamp,rate\n2024-03-01T00:00:00Z,50000\n");
+    workspace.ok(&["import", "rates", file_arg(&rates)]);
+    assert_eq!(fair_value(&workspace.json(&["report", "balance-sheet"])), dec!(30000));
+    assert_eq!(fair_value(&workspace.json(&["report", "balance-sheet", "--to", "2024-02-15"])), dec!(24000));
+}
+
+#[test]
+fn closed_period_rejects_later_imports_into_it() {
+    let workspace = book("close");
+    workspace.ok(&["close-period", "--from", "2024-01-01", "--date", "2024-01-31", "--name", "2024-01"]);
+    let periods = workspace.json(&["periods"]);
+    assert_eq!(periods[0]["period"], "2024-01");
+    assert_eq!(decimal(&periods[0]["net_income"]), dec!(30000));
+
+    let late = workspace.write("late.json", &serde_json::to_string(&[receipt(3, at(2024, 1, 15), OWNED[2], "1")]).unwrap());
+    let output = workspace.run(&["import", "transactions", file_arg(&late)]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Imported 0 of 1"), "{}", String::from_utf8_lossy(&output.stdout));
+}
@@ -0,0 +1,84 @@
+//! Builders shared by the integration tests.
+#![allow(dead_code)]
+
+use bitcoin_accounting::amount::Amount;
+use bitcoin_accounting::rates::RatePolicy;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction, UTXO};
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+
+/// Addresses registered to the `treasury` wallet by `app`.
+pub const OWNED: [&str; 3] = ["owned-1", "owned-2", "owned-3"];
+pub const EXTERNAL: &str = "external";
+
+/// A 64-digit hex txid made of `n` repeated.
+pub fn txid(n: u8) -> String {
+    format!("{:02x}", n).repeat(32)
+}
+
+pub fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, 0, 0, 0).unwrap()
+}
+
+pub fn btc(amount: &str) -> Amount {
+    amount.parse().unwrap()
+}
+
+pub fn utxo(txid: &str, vout: u32, amount: &str, address: &str, timestamp: DateTime<Utc>) -> UTXO {
+    UTXO {
+        txid: txid.to_string(),
+        vout,
+        amount: btc(amount),
+        address: address.to_string(),
+        confirmations: 6,
+        spendable: true,
+        timestamp,
+        restrictions: Vec::new(),
+    }
+}
+
+/// Transaction `n` on `date` spending `inputs` to `(address, amount)` outputs.
+pub fn transaction(n: u8, date: DateTime<Utc>, inputs: Vec<UTXO>, outputs: &[(&str, &str)], fee: &str) -> Transaction {
+    let txid = txid(n);
+    Transaction {
+        outputs: outputs
+            .iter()
+            .enumerate()
+            .map(|(vout, (address, amount))| utxo(&txid, vout as u32, amount, address, date))
+            .collect(),
+        txid,
+        timestamp: date,
+        inputs,
+        fee: btc(fee),
+        block: None,
+        acquisition_fee: Decimal::ZERO,
+    }
+}
+
+/// Coins received from outside the entity, listed without inputs.
+pub fn receipt(n: u8, date: DateTime<Utc>, address: &str, amount: &str) -> Transaction {
+    transaction(n, date, Vec::new(), &[(address, amount)], "0")
+}
+
+/// Output `vout` of `transaction`, as an input spending it.
+pub fn output(transaction: &Transaction, vout: u32) -> UTXO {
+    transaction.outputs[vout as usize].clone()
+}
+
+/// An in-memory book owning `OWNED`, with nearest-prior rates that never go stale.
+pub fn app(rates: &[(DateTime<Utc>, Decimal)]) -> BitcoinAccountingApp {
+    let mut app = BitcoinAccountingApp::new();
+    for address in OWNED {
+        app.register_address("treasury", address).unwrap();
+    }
+    app.set_rate_policy(RatePolicy::NearestPrior, None).unwrap();
+    for (date, rate) in rates {
+        app.add_exchange_rate(*date, *rate).unwrap();
+    }
+    app
+}
+
+/// Debit-positive balance of the account with `code` at `as_of`.
+pub fn balance(app: &BitcoinAccountingApp, code: &str, as_of: DateTime<Utc>) -> Decimal {
+    app.ledger().balance(code, as_of)
+}
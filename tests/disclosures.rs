@@ -0,0 +1,91 @@
+mod common;
+
+use bitcoin_accounting::amount::Amount;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::restriction::Restriction;
+use bitcoin_accounting::BitcoinAccountingApp;
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Brings in 0.5 BTC held since 2023, receives 1 BTC in January, then pays 0.4 out in
+/// May with a 0.0001 fee and remeasures at the end of March.
+fn book() -> BitcoinAccountingApp {
+    let mut app = app(&[
+        (at(2023, 12, 1), dec!(20000)),
+        (at(2024, 1, 1), dec!(30000)),
+        (at(2024, 3, 31), dec!(35000)),
+        (at(2024, 5, 1), dec!(40000)),
+        (at(2024, 6, 30), dec!(45000)),
+    ]);
+    app.add_opening_balance(utxo(&txid(9), 0, "0.5", OWNED[2], at(2023, 12, 1)), None).unwrap();
+    let received = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+    app.remeasure_fair_value(at(2024, 3, 31)).unwrap();
+    app.add_transaction(transaction(2, at(2024, 5, 1), vec![output(&received, 0)], &[(EXTERNAL, "0.4"), (OWNED[1], "0.5999")], "0.0001"))
+        .unwrap();
+    app
+}
+
+#[test]
+fn roll_forward_ties_out_to_the_balance_sheet() {
+    let app = book();
+    let q1 = FiscalPeriod::new("Q1", at(2024, 1, 1), at(2024, 3, 31));
+    let q2 = FiscalPeriod::new("Q2", at(2024, 4, 1), at(2024, 6, 30));
+    let first = app.roll_forward(&q1).unwrap();
+    let second = app.roll_forward(&q2).unwrap();
+
+    assert_eq!((first.opening_quantity, first.opening_fair_value), (btc("0.5"), dec!(10000)));
+    assert_eq!((first.acquired_quantity, first.acquisitions), (btc("1"), dec!(30000)));
+    assert_eq!(first.unrealized_gain_loss, dec!(12500));
+    assert_eq!((first.closing_quantity, first.closing_fair_value), (btc("1.5"), dec!(52500)));
+
+    assert_eq!((second.opening_quantity, second.opening_fair_value), (first.closing_quantity, first.closing_fair_value));
+    assert_eq!(second.disposed_quantity, btc("0.4001"));
+    // FIFO relieves the opening balance, held at 20k, before January's coins
+    assert_eq!((second.disposals_cost_basis, second.disposal_proceeds), (dec!(8002), dec!(16004)));
+    assert_eq!(second.realized_gain_loss, dec!(8002));
+    assert_eq!(second.closing_quantity, btc("1.0999"));
+
+    for roll_forward in [&first, &second] {
+        assert_eq!(roll_forward.unreconciled(), dec!(0));
+        let sheet = app.balance_sheet(roll_forward.end, roll_forward.start).unwrap();
+        let digital_assets = sheet.assets.iter().find(|line| line.account.as_deref() == Some("1500")).unwrap();
+        assert_eq!(digital_assets.current, roll_forward.closing_fair_value);
+    }
+}
+
+#[test]
+fn holdings_disclosure_gives_each_restricted_utxo_its_own_basis() {
+    let mut app = book();
+    let opening = utxo(&txid(9), 0, "0.5", OWNED[2], at(2023, 12, 1)).outpoint();
+    let change = utxo(&txid(2), 1, "0.5999", OWNED[1], at(2024, 5, 1)).outpoint();
+    app.add_restriction(&opening, Restriction::LockUp { until: at(2024, 12, 31) }).unwrap();
+    app.add_restriction(&change, Restriction::LockUp { until: at(2024, 6, 1) }).unwrap();
+    app.add_restriction(
+        &change,
+        Restriction::PledgedAsCollateral {
+            counterparty: "Acme Lending".to_string(),
+            until: None,
+        },
+    )
+    .unwrap();
+
+    let disclosure = app.holdings_disclosure(at(2024, 6, 30)).unwrap();
+    assert_eq!((disclosure.units, disclosure.rate, disclosure.fair_value), (btc("1.0999"), dec!(45000), dec!(49495.5)));
+    assert_eq!(disclosure.cost_basis, dec!(31998));
+    // The lapsed lock-up is left out, and every held UTXO is restricted
+    assert_eq!(disclosure.restricted.len(), 2);
+    let units: Amount = disclosure.restricted.iter().map(|holding| holding.units).sum();
+    let cost_basis: Decimal = disclosure.restricted.iter().map(|holding| holding.cost_basis).sum();
+    assert_eq!((units, cost_basis), (disclosure.units, disclosure.cost_basis));
+    for holding in &disclosure.restricted {
+        assert_eq!(holding.fair_value, holding.units.to_btc() * dec!(45000));
+    }
+
+    // Before the payment only the opening balance was restricted, at its full basis
+    let disclosure = app.holdings_disclosure(at(2024, 3, 31)).unwrap();
+    assert_eq!((disclosure.units, disclosure.cost_basis), (btc("1.5"), dec!(40000)));
+    assert_eq!(disclosure.restricted.len(), 1);
+    assert_eq!((disclosure.restricted[0].outpoint.clone(), disclosure.restricted[0].cost_basis), (opening, dec!(10000)));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use bitcoin_accounting::ledger::AccountRole;
+use common::*;
+use rust_decimal_macros::dec;
+
+#[test]
+fn remeasurement_carries_forward_as_the_next_carrying_amount() {
+    let mut app = app(&[
+        (at(2024, 1, 1), dec!(30000)),
+        (at(2024, 3, 31), dec!(40000)),
+        (at(2024, 5, 1), dec!(38000)),
+        (at(2024, 6, 30), dec!(35000)),
+    ]);
+    let bought = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(bought.clone()).unwrap();
+
+    let first = app.remeasure_fair_value(at(2024, 3, 31)).unwrap();
+    assert_eq!((first.carrying_amount, first.fair_value, first.adjustment), (dec!(30000), dec!(40000), dec!(10000)));
+    assert!(first.entry_id.is_some());
+
+    // Half is sold between remeasurements, relieving its cost basis of 15k
+    app.add_transaction(transaction(2, at(2024, 5, 1), vec![output(&bought, 0)], &[(EXTERNAL, "0.5"), (OWNED[1], "0.5")], "0"))
+        .unwrap();
+    let second = app.remeasure_fair_value(at(2024, 6, 30)).unwrap();
+    assert_eq!(second.quantity, btc("0.5"));
+    assert_eq!((second.carrying_amount, second.fair_value, second.adjustment), (dec!(25000), dec!(17500), dec!(-7500)));
+
+    let chart = app.ledger().chart();
+    let as_of = at(2024, 6, 30);
+    assert_eq!(balance(&app, chart.code_for(AccountRole::DigitalAssets), as_of), dec!(17500));
+    assert_eq!(balance(&app, chart.code_for(AccountRole::UnrealizedGainLoss), as_of), dec!(-2500));
+    assert_eq!(app.remeasurements().len(), 2);
+}
+
+#[test]
+fn remeasurement_at_carrying_amount_posts_nothing() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 1), OWNED[0], "1")).unwrap();
+    let entries = app.ledger().entries().len();
+
+    let remeasurement = app.remeasure_fair_value(at(2024, 3, 31)).unwrap();
+    assert_eq!(remeasurement.adjustment, dec!(0));
+    assert_eq!(remeasurement.entry_id, None);
+    assert_eq!(app.ledger().entries().len(), entries);
+}
+
+#[test]
+fn remeasurement_in_arrears_ignores_later_activity() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 3, 31), dec!(40000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 1), OWNED[0], "1")).unwrap();
+    app.add_transaction(receipt(2, at(2024, 4, 2), OWNED[1], "1")).unwrap();
+
+    let remeasurement = app.remeasure_fair_value(at(2024, 3, 31)).unwrap();
+    assert_eq!(remeasurement.quantity, btc("1"));
+    assert_eq!(remeasurement.adjustment, dec!(10000));
+}
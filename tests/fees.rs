@@ -0,0 +1,125 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::fees::FeePolicy;
+use bitcoin_accounting::ledger::AccountRole;
+use bitcoin_accounting::lots::CostBasisMethod;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction};
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// A book under `policy` holding 1 BTC bought at 30k, with BTC at 40k from February.
+fn funded(policy: FeePolicy) -> (BitcoinAccountingApp, Transaction) {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 2, 1), dec!(40000))]);
+    app.set_fee_policy(policy).unwrap();
+    let bought = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(bought.clone()).unwrap();
+    (app, bought)
+}
+
+/// Fee expense, realized gain and Digital Assets after everything posted.
+fn effects(app: &BitcoinAccountingApp) -> (Decimal, Decimal, Decimal) {
+    let chart = app.ledger().chart();
+    let as_of = at(2024, 12, 31);
+    (
+        balance(app, chart.code_for(AccountRole::FeeExpense), as_of),
+        -balance(app, chart.code_for(AccountRole::RealizedGainLoss), as_of),
+        balance(app, chart.code_for(AccountRole::DigitalAssets), as_of),
+    )
+}
+
+fn payment(bought: &Transaction) -> Transaction {
+    transaction(2, at(2024, 2, 1), vec![output(bought, 0)], &[(EXTERNAL, "0.4"), (OWNED[1], "0.5999")], "0.0001")
+}
+
+#[test]
+fn expense_policy_expenses_disposal_fees() {
+    let (mut app, bought) = funded(FeePolicy::Expense);
+    app.add_transaction(payment(&bought)).unwrap();
+    // The coins spent on the fee are disposed of at 4, against a basis of 3
+    assert_eq!(effects(&app), (dec!(4), dec!(4001), dec!(17997)));
+}
+
+#[test]
+fn capitalize_policy_deducts_disposal_fees_from_proceeds() {
+    let (mut app, bought) = funded(FeePolicy::Capitalize);
+    app.add_transaction(payment(&bought)).unwrap();
+    assert_eq!(effects(&app), (dec!(0), dec!(3997), dec!(17997)));
+
+    // 0.4001 BTC disposed of, worth 16004, less the fee of 4
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let proceeds: Decimal = app.realized_gains_by_lot(&year).unwrap().iter().map(|lot| lot.proceeds).sum();
+    assert_eq!(proceeds, dec!(16000));
+}
+
+#[test]
+fn transfer_fees_are_expensed_under_either_policy() {
+    for policy in [FeePolicy::Expense, FeePolicy::Capitalize] {
+        let (mut app, bought) = funded(policy);
+        app.add_transaction(transaction(2, at(2024, 2, 1), vec![output(&bought, 0)], &[(OWNED[1], "0.9999")], "0.0001"))
+            .unwrap();
+        assert_eq!(effects(&app), (dec!(4), dec!(1), dec!(29997)), "{}", policy);
+    }
+}
+
+#[test]
+fn fees_the_entity_did_not_pay_are_not_booked() {
+    for policy in [FeePolicy::Expense, FeePolicy::Capitalize] {
+        let (mut app, bought) = funded(policy);
+        // Received from outside: the sender paid the fee
+        let sender = utxo(&txid(8), 0, "0.5001", EXTERNAL, at(2024, 1, 15));
+        app.add_transaction(transaction(2, at(2024, 2, 1), vec![sender], &[(OWNED[1], "0.5")], "0.0001"))
+            .unwrap();
+        assert_eq!(effects(&app), (dec!(0), dec!(0), dec!(50000)), "{}", policy);
+
+        // A joint transaction with another party's inputs leaves the fee to them
+        let other = utxo(&txid(9), 0, "1", EXTERNAL, at(2024, 1, 15));
+        let joint = transaction(3, at(2024, 2, 1), vec![output(&bought, 0), other], &[(OWNED[2], "1"), (EXTERNAL, "0.9999")], "0.0001");
+        app.add_transaction(joint).unwrap();
+        assert_eq!(effects(&app).0, dec!(0), "{}", policy);
+    }
+}
+
+#[test]
+fn acquisition_fees_follow_the_policy() {
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    for (policy, expensed, basis, gain) in [
+        (FeePolicy::Expense, dec!(150), dec!(20000), dec!(0)),
+        (FeePolicy::Capitalize, dec!(0), dec!(20150), dec!(-90)),
+    ] {
+        let (mut app, _) = funded(policy);
+        app.set_cost_basis_method(CostBasisMethod::SpecificId).unwrap();
+        // 0.5 BTC bought at 40k with a 150 commission paid in cash
+        let mut bought = transaction(2, at(2024, 2, 1), Vec::new(), &[(OWNED[1], "0.3"), (OWNED[2], "0.2")], "0");
+        bought.acquisition_fee = dec!(150);
+        app.add_transaction(bought).unwrap();
+
+        let chart = app.ledger().chart();
+        assert_eq!(effects(&app), (expensed, dec!(0), dec!(30000) + basis), "{}", policy);
+        assert_eq!(balance(&app, chart.code_for(AccountRole::Cash), at(2024, 12, 31)), dec!(-150), "{}", policy);
+        let lots: Vec<Decimal> = app
+            .open_lots()
+            .unwrap()
+            .iter()
+            .filter(|lot| lot.outpoint.txid == txid(2))
+            .map(|lot| lot.cost_basis)
+            .collect();
+        assert_eq!(lots.iter().sum::<Decimal>(), basis, "{}", policy);
+
+        // Selling the 0.3 at cost realizes a loss of the capitalized commission on it
+        let held = app.utxos_by_address(OWNED[1])[0].clone();
+        app.add_transaction(transaction(3, at(2024, 2, 1), vec![held], &[(EXTERNAL, "0.3")], "0")).unwrap();
+        assert_eq!(app.calculate_realized_gains_losses(&year).unwrap().total(), gain, "{}", policy);
+    }
+}
+
+#[test]
+fn negative_acquisition_fees_are_rejected() {
+    let (mut app, _) = funded(FeePolicy::Capitalize);
+    let mut bought = receipt(2, at(2024, 2, 1), OWNED[1], "0.5");
+    bought.acquisition_fee = dec!(-1);
+    let err = app.add_transaction(bought).unwrap_err();
+    assert!(matches!(err, AccountingError::NegativeAcquisitionFee(ref id) if *id == txid(2)), "{}", err);
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use bitcoin_accounting::ledger::AccountRole;
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[test]
+fn every_posted_entry_balances() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 2, 1), dec!(40000))]);
+    let bought = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(bought.clone()).unwrap();
+    let sale = transaction(2, at(2024, 2, 1), vec![output(&bought, 0)], &[(EXTERNAL, "0.5"), (OWNED[1], "0.4999")], "0.0001");
+    app.add_transaction(sale.clone()).unwrap();
+    app.add_transaction(transaction(3, at(2024, 2, 1), vec![output(&sale, 1)], &[(OWNED[2], "0.4998")], "0.0001"))
+        .unwrap();
+
+    app.ledger().verify().unwrap();
+    for entry in app.ledger().entries() {
+        assert_eq!(entry.total_debits(), entry.total_credits(), "{}", entry.description);
+    }
+    let as_of = at(2024, 12, 31);
+    let total: Decimal = app
+        .ledger()
+        .chart()
+        .accounts()
+        .iter()
+        .map(|account| balance(&app, &account.code, as_of))
+        .sum();
+    assert_eq!(total, Decimal::ZERO);
+
+    // Bought for 30k, sold half for 20k, and spent two fees worth 4 each, costing 3
+    let chart = app.ledger().chart();
+    assert_eq!(balance(&app, chart.code_for(AccountRole::DigitalAssets), as_of), dec!(14994));
+    assert_eq!(balance(&app, chart.code_for(AccountRole::RealizedGainLoss), as_of), dec!(-5002));
+    assert_eq!(balance(&app, chart.code_for(AccountRole::FeeExpense), as_of), dec!(8));
+}
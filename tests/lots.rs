@@ -0,0 +1,99 @@
+mod common;
+
+use bitcoin_accounting::amount::Amount;
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::lots::CostBasisMethod;
+use bitcoin_accounting::period::FiscalPeriod;
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Buys 1 BTC at 30k and 1 BTC at 50k, then sells the second one for 40k.
+fn realized_gain(method: CostBasisMethod) -> (Decimal, Decimal) {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 1, 2), dec!(50000)), (at(2024, 1, 3), dec!(40000))]);
+    app.set_cost_basis_method(method).unwrap();
+    app.add_transaction(receipt(1, at(2024, 1, 1), OWNED[0], "1")).unwrap();
+    let second = receipt(2, at(2024, 1, 2), OWNED[1], "1");
+    app.add_transaction(second.clone()).unwrap();
+    app.add_transaction(transaction(3, at(2024, 1, 3), vec![output(&second, 0)], &[(EXTERNAL, "1")], "0"))
+        .unwrap();
+
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let realized = app.realized_gains_by_lot(&year).unwrap();
+    let basis = realized.iter().map(|lot| lot.cost_basis).sum();
+    (basis, app.calculate_realized_gains_losses(&year).unwrap().total())
+}
+
+#[test]
+fn realized_gain_follows_the_cost_basis_method() {
+    assert_eq!(realized_gain(CostBasisMethod::Fifo), (dec!(30000), dec!(10000)));
+    assert_eq!(realized_gain(CostBasisMethod::Hifo), (dec!(50000), dec!(-10000)));
+    assert_eq!(realized_gain(CostBasisMethod::SpecificId), (dec!(50000), dec!(-10000)));
+    assert_eq!(realized_gain(CostBasisMethod::AverageCost), (dec!(40000), dec!(0)));
+}
+
+#[test]
+fn open_lots_stay_on_held_utxos() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 1, 2), dec!(50000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 1), OWNED[0], "1")).unwrap();
+    let second = receipt(2, at(2024, 1, 2), OWNED[1], "1");
+    app.add_transaction(second.clone()).unwrap();
+    // FIFO relieves the first receipt's lot, though the second receipt's coins are spent
+    app.add_transaction(transaction(3, at(2024, 1, 2), vec![output(&second, 0)], &[(EXTERNAL, "0.4"), (OWNED[2], "0.6")], "0"))
+        .unwrap();
+
+    let lots = app.open_lots().unwrap();
+    for lot in &lots {
+        assert!(app.get_utxo(&lot.outpoint).is_some(), "lot on spent {}", lot.outpoint);
+    }
+    for utxo in app.utxo_set().values() {
+        let carried: Amount = lots.iter().filter(|lot| lot.outpoint == utxo.outpoint()).map(|lot| lot.quantity).sum();
+        assert_eq!(carried, utxo.amount);
+    }
+}
+
+#[test]
+fn posted_disposals_keep_their_relief() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 2, 1), dec!(50000)), (at(2024, 3, 1), dec!(40000))]);
+    app.set_cost_basis_method(CostBasisMethod::Lifo).unwrap();
+    let first = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(first.clone()).unwrap();
+    app.add_transaction(transaction(2, at(2024, 3, 1), vec![output(&first, 0)], &[(EXTERNAL, "1")], "0"))
+        .unwrap();
+    let entries = app.ledger().entries().len();
+
+    // Under LIFO a receipt dated before the sale would have been relieved instead
+    let err = app.add_transaction(receipt(3, at(2024, 2, 1), OWNED[1], "1")).unwrap_err();
+    assert!(matches!(err, AccountingError::DisposalChangesPosted(ref id) if *id == txid(2)), "{}", err);
+    let err = app.add_opening_balance(utxo(&txid(9), 0, "1", OWNED[2], at(2024, 2, 1)), None).unwrap_err();
+    assert!(matches!(err, AccountingError::DisposalChangesPosted(_)), "{}", err);
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert!(app.transactions().iter().all(|transaction| transaction.txid != txid(3)));
+    assert!(app.opening_balances().is_empty());
+
+    // The ledger and the lots still agree on the sale
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    assert_eq!(app.calculate_realized_gains_losses(&year).unwrap().total(), dec!(10000));
+    assert_eq!(balance(&app, "4100", at(2024, 12, 31)), dec!(-10000));
+
+    // Activity after the sale leaves it alone
+    app.add_transaction(receipt(3, at(2024, 3, 2), OWNED[1], "1")).unwrap();
+}
+
+#[test]
+fn cost_basis_method_is_fixed_once_it_matters() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 1, 2), dec!(50000)), (at(2024, 1, 3), dec!(40000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 1), OWNED[0], "1")).unwrap();
+    let second = receipt(2, at(2024, 1, 2), OWNED[1], "1");
+    app.add_transaction(second.clone()).unwrap();
+    // With nothing disposed of yet, any method relieves the same
+    app.set_cost_basis_method(CostBasisMethod::Hifo).unwrap();
+    app.set_cost_basis_method(CostBasisMethod::Fifo).unwrap();
+    app.add_transaction(transaction(3, at(2024, 1, 3), vec![output(&second, 0)], &[(EXTERNAL, "0.5"), (OWNED[2], "0.5")], "0"))
+        .unwrap();
+
+    let err = app.set_cost_basis_method(CostBasisMethod::Hifo).unwrap_err();
+    assert!(matches!(err, AccountingError::DisposalChangesPosted(ref id) if *id == txid(3)), "{}", err);
+    assert_eq!(app.cost_basis_method(), CostBasisMethod::Fifo);
+    assert_eq!(app.open_lots().unwrap().iter().map(|lot| lot.cost_basis).sum::<Decimal>(), dec!(65000));
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::ledger::AccountRole;
+use bitcoin_accounting::period::FiscalPeriod;
+use common::*;
+use rust_decimal_macros::dec;
+
+#[test]
+fn opening_balance_can_be_spent_at_its_carried_basis() {
+    let mut app = app(&[(at(2023, 6, 1), dec!(25000)), (at(2024, 1, 1), dec!(40000))]);
+    let held = utxo(&txid(9), 3, "1", OWNED[0], at(2023, 6, 1));
+    let opening = app.add_opening_balance(held.clone(), Some(dec!(10000))).unwrap();
+    assert_eq!(opening.cost_basis, dec!(10000));
+    assert!(app.get_utxo(&held.outpoint()).is_some());
+
+    let chart = app.ledger().chart();
+    let digital_assets = chart.code_for(AccountRole::DigitalAssets).to_string();
+    let retained_earnings = chart.code_for(AccountRole::RetainedEarnings).to_string();
+    assert_eq!(balance(&app, &digital_assets, at(2023, 6, 1)), dec!(10000));
+    assert_eq!(balance(&app, &retained_earnings, at(2023, 6, 1)), dec!(-10000));
+
+    app.add_transaction(transaction(1, at(2024, 1, 1), vec![held], &[(EXTERNAL, "1")], "0")).unwrap();
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let realized = app.realized_gains_by_lot(&year).unwrap();
+    assert_eq!(realized.len(), 1);
+    assert_eq!((realized[0].cost_basis, realized[0].acquired_at), (dec!(10000), at(2023, 6, 1)));
+    assert_eq!(balance(&app, &digital_assets, at(2024, 1, 1)), dec!(0));
+}
+
+#[test]
+fn opening_balance_defaults_to_fair_value() {
+    let mut app = app(&[(at(2023, 6, 1), dec!(25000))]);
+    let opening = app.add_opening_balance(utxo(&txid(9), 0, "0.5", OWNED[0], at(2023, 6, 1)), None).unwrap();
+    assert_eq!(opening.cost_basis, dec!(12500));
+}
+
+#[test]
+fn opening_balances_must_be_new_owned_coins() {
+    let mut app = app(&[(at(2023, 6, 1), dec!(25000))]);
+    let held = utxo(&txid(9), 0, "1", OWNED[0], at(2023, 6, 1));
+    app.add_opening_balance(held.clone(), None).unwrap();
+
+    let err = app.add_opening_balance(held.clone(), None).unwrap_err();
+    assert!(matches!(err, AccountingError::OpeningBalanceExists(outpoint) if outpoint == held.outpoint()));
+    let err = app.add_opening_balance(utxo(&txid(8), 0, "1", EXTERNAL, at(2023, 6, 1)), None).unwrap_err();
+    assert!(matches!(err, AccountingError::UnownedAddress(address) if address == EXTERNAL));
+
+    let recorded = receipt(1, at(2023, 6, 1), OWNED[1], "1");
+    app.add_transaction(recorded.clone()).unwrap();
+    let err = app.add_opening_balance(utxo(&txid(1), 5, "1", OWNED[1], at(2023, 6, 1)), None).unwrap_err();
+    assert!(matches!(err, AccountingError::DuplicateTransaction(_)));
+    // Nor may a transaction reuse an opening balance's txid
+    let err = app.add_transaction(receipt(9, at(2023, 6, 2), OWNED[2], "1")).unwrap_err();
+    assert!(matches!(err, AccountingError::DuplicateTransaction(_)));
+    assert_eq!(app.opening_balances().len(), 1);
+}
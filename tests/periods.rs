@@ -0,0 +1,121 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::ledger::EntryKind;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::rates::RatePolicy;
+use bitcoin_accounting::BitcoinAccountingApp;
+use common::*;
+use rust_decimal_macros::dec;
+
+fn q1() -> FiscalPeriod {
+    FiscalPeriod::new("Q1", at(2024, 1, 1), at(2024, 3, 31))
+}
+
+/// Receives 1 BTC on Jan 1 at 30000, worth 35000 by Mar 31.
+fn book() -> BitcoinAccountingApp {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 3, 31), dec!(35000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 1), OWNED[0], "1")).unwrap();
+    app
+}
+
+#[test]
+fn closing_remeasures_and_closes_income_to_retained_earnings() {
+    let mut app = book();
+    let (close, remeasurement) = app.close_period(q1()).unwrap();
+    assert_eq!(remeasurement.reporting_date, at(2024, 3, 31));
+    // The snapshot is taken before closing but includes the remeasurement
+    assert_eq!(close.balances["4000"], dec!(-30000));
+    assert_eq!(close.balances["4200"], dec!(-5000));
+    assert_eq!(close.balances["1500"], dec!(35000));
+    assert_eq!(close.net_income, dec!(35000));
+
+    let end = at(2024, 3, 31);
+    assert_eq!(balance(&app, "4000", end), dec!(0));
+    assert_eq!(balance(&app, "4200", end), dec!(0));
+    assert_eq!(balance(&app, "3000", end), dec!(-35000));
+    assert_eq!(balance(&app, "1500", end), dec!(35000));
+    let closing = app.ledger().entries().last().unwrap();
+    assert_eq!((Some(closing.id), closing.kind), (close.entry_id, EntryKind::Closing));
+    assert_eq!(app.period_closes().len(), 1);
+    assert_eq!(app.locked_period(at(2024, 2, 1)), Some(&q1()));
+    assert_eq!(app.locked_period(at(2024, 4, 1)), None);
+    // Dates before the first closed period are locked with it
+    assert_eq!(app.locked_period(at(2023, 6, 1)), Some(&q1()));
+}
+
+#[test]
+fn failed_close_changes_nothing() {
+    let mut app = book();
+    app.set_rate_policy(RatePolicy::Exact, None).unwrap();
+    let entries = app.ledger().entries().len();
+    let period = FiscalPeriod::new("Q1", at(2024, 1, 1), at(2024, 3, 30));
+    assert!(app.close_period(period).is_err());
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert!(app.period_closes().is_empty());
+    assert!(app.remeasurements().is_empty());
+    assert_eq!(app.locked_period(at(2024, 2, 1)), None);
+}
+
+#[test]
+fn closed_periods_reject_postings() {
+    let mut app = book();
+    app.close_period(q1()).unwrap();
+    let entries = app.ledger().entries().len();
+
+    let err = app.add_transaction(receipt(2, at(2024, 2, 1), OWNED[1], "0.5")).unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { ref period, .. } if period == "Q1"), "{}", err);
+    let err = app.remeasure_fair_value(at(2024, 3, 1)).unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { .. }), "{}", err);
+    let err = app.add_exchange_rate(at(2024, 1, 1), dec!(31000)).unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { .. }), "{}", err);
+    // Nor can activity slip in before the first closed period
+    let err = app.add_transaction(receipt(3, at(2023, 12, 1), OWNED[2], "0.5")).unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { ref period, .. } if period == "Q1"), "{}", err);
+    assert_eq!(app.ledger().entries().len(), entries);
+
+    app.add_transaction(receipt(2, at(2024, 4, 1), OWNED[1], "0.5")).unwrap();
+}
+
+#[test]
+fn periods_close_in_order_without_overlap() {
+    let mut app = book();
+    let backwards = FiscalPeriod::new("Backwards", at(2024, 3, 31), at(2024, 1, 1));
+    assert!(matches!(app.close_period(backwards), Err(AccountingError::InvalidPeriod(name)) if name == "Backwards"));
+
+    app.close_period(q1()).unwrap();
+    let again = app.close_period(q1()).unwrap_err();
+    assert!(matches!(again, AccountingError::PeriodOverlap { ref closed, .. } if closed == "Q1"), "{}", again);
+    let overlapping = FiscalPeriod::new("March-April", at(2024, 3, 1), at(2024, 4, 30));
+    assert!(matches!(app.close_period(overlapping), Err(AccountingError::PeriodOverlap { .. })));
+    let reused = FiscalPeriod::new("Q1", at(2024, 4, 1), at(2024, 6, 30));
+    assert!(matches!(app.close_period(reused), Err(AccountingError::PeriodOverlap { .. })));
+
+    app.close_period(FiscalPeriod::new("Q2", at(2024, 4, 1), at(2024, 6, 30))).unwrap();
+    assert_eq!(app.period_closes().len(), 2);
+}
+
+#[test]
+fn late_activity_is_booked_as_a_prior_period_adjustment() {
+    let mut app = book();
+    app.close_period(q1()).unwrap();
+    let late = receipt(2, at(2024, 2, 1), OWNED[1], "0.5");
+
+    let err = app.add_prior_period_adjustment(late.clone(), at(2024, 3, 15)).unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { .. }), "{}", err);
+    let current = receipt(3, at(2024, 4, 15), OWNED[2], "0.1");
+    let err = app.add_prior_period_adjustment(current, at(2024, 4, 15)).unwrap_err();
+    assert!(matches!(err, AccountingError::NotInClosedPeriod(ref id) if *id == txid(3)), "{}", err);
+
+    app.add_prior_period_adjustment(late, at(2024, 4, 1)).unwrap();
+    let adjustment = app.ledger().entries().last().unwrap();
+    assert_eq!((adjustment.date, adjustment.kind), (at(2024, 4, 1), EntryKind::PriorPeriodAdjustment));
+    // The revenue is taken straight to retained earnings, leaving the closed period as reported
+    assert_eq!(balance(&app, "4000", at(2024, 4, 1)), dec!(0));
+    assert_eq!(balance(&app, "3000", at(2024, 4, 1)), dec!(-50000));
+    assert_eq!(balance(&app, "3000", at(2024, 3, 31)), dec!(-35000));
+    assert_eq!(balance(&app, "1500", at(2024, 4, 1)), dec!(50000));
+    // Lots keep the transaction's own date
+    let lots = app.open_lots().unwrap();
+    assert!(lots.iter().any(|lot| lot.outpoint.txid == txid(2) && lot.acquired_at == at(2024, 2, 1)));
+}
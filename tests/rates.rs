@@ -0,0 +1,65 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::rates::{RateError, RatePolicy};
+use chrono::Duration;
+use common::*;
+use rust_decimal_macros::dec;
+
+#[test]
+fn missing_rate_leaves_the_transaction_unposted() {
+    let mut app = app(&[]);
+    app.set_rate_policy(RatePolicy::NearestPrior, Some(Duration::days(1))).unwrap();
+    app.add_exchange_rate(at(2024, 1, 1), dec!(40000)).unwrap();
+
+    let err = app.add_transaction(receipt(1, at(2024, 1, 5), OWNED[0], "1")).unwrap_err();
+    assert!(matches!(
+        err,
+        AccountingError::Rate(RateError::MissingRates(ref missing)) if *missing == vec![at(2024, 1, 5)]
+    ));
+    assert!(app.transactions().is_empty());
+    assert!(app.ledger().entries().is_empty());
+}
+
+#[test]
+fn rate_that_would_change_a_posted_entry_is_rejected() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 3), OWNED[0], "1")).unwrap();
+
+    let err = app.add_exchange_rate(at(2024, 1, 2), dec!(45000)).unwrap_err();
+    assert!(matches!(
+        err,
+        AccountingError::RateChangesPosted { date, posted } if date == at(2024, 1, 2) && posted == at(2024, 1, 3)
+    ));
+    assert_eq!(app.rate_at(at(2024, 1, 3)), Some(dec!(40000)));
+
+    // Later rates and restatements of the same value change nothing posted
+    app.add_exchange_rate(at(2024, 1, 4), dec!(45000)).unwrap();
+    app.add_exchange_rate(at(2024, 1, 1), dec!(40000)).unwrap();
+}
+
+#[test]
+fn rate_policy_that_would_change_a_posted_entry_is_rejected() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000)), (at(2024, 1, 5), dec!(50000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 3), OWNED[0], "1")).unwrap();
+
+    for (policy, staleness) in [(RatePolicy::Interpolate, None), (RatePolicy::Exact, None), (RatePolicy::NearestPrior, Some(Duration::days(1)))] {
+        let err = app.set_rate_policy(policy, staleness).unwrap_err();
+        assert!(matches!(err, AccountingError::RatePolicyChangesPosted { posted } if posted == at(2024, 1, 3)), "{}", err);
+    }
+    assert_eq!(app.rate_at(at(2024, 1, 3)), Some(dec!(40000)));
+
+    // A staleness limit the posted rate still meets changes nothing
+    app.set_rate_policy(RatePolicy::NearestPrior, Some(Duration::days(2))).unwrap();
+}
+
+#[test]
+fn rate_in_a_closed_period_is_locked() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000))]);
+    app.add_transaction(receipt(1, at(2024, 1, 3), OWNED[0], "1")).unwrap();
+    app.close_period(FiscalPeriod::new("2024-01", at(2024, 1, 1), at(2024, 1, 31))).unwrap();
+
+    let err = app.add_exchange_rate(at(2024, 1, 2), dec!(45000)).unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { ref period, .. } if period == "2024-01"));
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::rates::RatePolicy;
+use bitcoin_accounting::replacement::TransactionStatus;
+use bitcoin_accounting::reversal::ReversalReason;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction};
+use common::*;
+use rust_decimal_macros::dec;
+
+/// Transaction `n` paying 0.4 of `received` out on `date`, with `confirmations`.
+fn payment(n: u8, day: u32, received: &Transaction, change: &str, fee: &str, confirmations: u64) -> Transaction {
+    let mut payment = transaction(n, at(2024, 5, day), vec![output(received, 0)], &[(EXTERNAL, "0.4"), (OWNED[1], change)], fee);
+    for output in &mut payment.outputs {
+        output.confirmations = confirmations;
+    }
+    payment
+}
+
+/// Receives 1 BTC on Jan 1, then books a pending payment of 0.4 on May 1 with a 0.0001 fee.
+fn book() -> (BitcoinAccountingApp, Transaction, Transaction) {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 5, 1), dec!(40000))]);
+    app.set_min_confirmations(0).unwrap();
+    let received = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+    let pending = payment(2, 1, &received, "0.5999", "0.0001", 0);
+    app.add_transaction(pending.clone()).unwrap();
+    (app, received, pending)
+}
+
+#[test]
+fn confirmed_conflict_replaces_a_pending_transaction() {
+    let (mut app, received, pending) = book();
+    assert_eq!(app.transaction_status(&pending.txid), Some(TransactionStatus::Pending));
+    assert_eq!(balance(&app, "6000", at(2024, 5, 31)), dec!(4));
+
+    let bumped = payment(3, 2, &received, "0.5995", "0.0005", 1);
+    app.add_transaction(bumped.clone()).unwrap();
+
+    let replacements = app.replacements();
+    assert_eq!(replacements.len(), 1);
+    assert_eq!((replacements[0].replaced.as_str(), replacements[0].replacement.as_str()), (pending.txid.as_str(), bumped.txid.as_str()));
+    assert_eq!((replacements[0].fee, replacements[0].replacement_fee), (btc("0.0001"), btc("0.0005")));
+    assert_eq!(replacements[0].fee_difference(), 40000);
+    assert_eq!(
+        app.transaction_status(&pending.txid),
+        Some(TransactionStatus::Reversed(ReversalReason::Replaced {
+            by: bumped.txid.clone(),
+            replacement_fee: btc("0.0005"),
+        }))
+    );
+    assert_eq!(app.transaction_status(&bumped.txid), Some(TransactionStatus::Confirmed));
+
+    // Only the replacement's payment and fee remain
+    assert!(app.get_utxo(&output(&pending, 1).outpoint()).is_none());
+    assert!(app.get_utxo(&output(&bumped, 1).outpoint()).is_some());
+    assert_eq!(balance(&app, "6000", at(2024, 5, 31)), dec!(20));
+    assert_eq!(balance(&app, "1000", at(2024, 5, 31)), dec!(16000));
+    assert_eq!(balance(&app, "1500", at(2024, 5, 31)), dec!(17985));
+}
+
+#[test]
+fn unconfirmed_replacement_waits() {
+    let (mut app, received, pending) = book();
+    let entries = app.ledger().entries().len();
+    let bumped = payment(3, 2, &received, "0.5995", "0.0005", 0);
+    let err = app.add_transaction(bumped.clone()).unwrap_err();
+    assert!(
+        matches!(err, AccountingError::UnconfirmedReplacement { ref replaces, .. } if *replaces == vec![pending.txid.clone()]),
+        "{}",
+        err
+    );
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert_eq!(app.transaction_status(&pending.txid), Some(TransactionStatus::Pending));
+    assert_eq!(app.transaction_status(&bumped.txid), None);
+    assert!(app.replacements().is_empty());
+}
+
+#[test]
+fn conflict_with_a_confirmed_transaction_is_a_double_spend() {
+    let (mut app, received, pending) = book();
+    let bumped = payment(3, 2, &received, "0.5995", "0.0005", 1);
+    app.add_transaction(bumped.clone()).unwrap();
+    let entries = app.ledger().entries().len();
+
+    let err = app.add_transaction(payment(4, 3, &received, "0.599", "0.001", 1)).unwrap_err();
+    assert!(
+        matches!(err, AccountingError::DoubleSpend { ref conflicting, .. } if *conflicting == vec![bumped.txid.clone()]),
+        "{}",
+        err
+    );
+    // Reintroducing the replaced transaction is refused the same way
+    assert!(matches!(app.add_transaction(pending), Err(AccountingError::DoubleSpend { .. })));
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert_eq!(app.replacements().len(), 1);
+}
+
+#[test]
+fn failed_replacement_leaves_the_pending_transaction_booked() {
+    let (mut app, received, pending) = book();
+    app.set_rate_policy(RatePolicy::Exact, None).unwrap();
+    let entries = app.ledger().entries().len();
+    // No rate on May 2, so the replacement cannot post
+    let bumped = payment(3, 2, &received, "0.5995", "0.0005", 1);
+    let err = app.add_transaction(bumped.clone()).unwrap_err();
+    assert!(matches!(err, AccountingError::Rate(_)), "{}", err);
+
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert_eq!(app.transaction_status(&pending.txid), Some(TransactionStatus::Pending));
+    assert_eq!(app.transaction_status(&bumped.txid), None);
+    assert!(app.reversals().is_empty());
+    assert!(app.get_utxo(&output(&pending, 1).outpoint()).is_some());
+    assert!(app.get_utxo(&output(&received, 0).outpoint()).is_none());
+
+    app.add_exchange_rate(at(2024, 5, 2), dec!(40000)).unwrap();
+    app.add_transaction(bumped.clone()).unwrap();
+    assert_eq!(app.transaction_status(&bumped.txid), Some(TransactionStatus::Confirmed));
+    assert_eq!(app.replacements().len(), 1);
+}
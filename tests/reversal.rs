@@ -0,0 +1,162 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::ledger::EntryKind;
+use bitcoin_accounting::lots::CostBasisMethod;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::replacement::TransactionStatus;
+use bitcoin_accounting::reversal::ReversalReason;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction};
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+struct Book {
+    app: BitcoinAccountingApp,
+    first: Transaction,
+    second: Transaction,
+    sale: Transaction,
+}
+
+/// Under FIFO, receives 1 BTC at 30k and 1 BTC at 35k, sells 0.5 out of the second
+/// receipt on Mar 1 (relieving the first lot) and all of the first on Apr 1.
+fn book() -> Book {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 2, 1), dec!(35000)), (at(2024, 3, 1), dec!(40000))]);
+    app.set_cost_basis_method(CostBasisMethod::Fifo).unwrap();
+    let first = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    let second = receipt(2, at(2024, 2, 1), OWNED[1], "1");
+    let sale = transaction(3, at(2024, 3, 1), vec![output(&second, 0)], &[(EXTERNAL, "0.5"), (OWNED[2], "0.5")], "0");
+    for transaction in [&first, &second, &sale] {
+        app.add_transaction(transaction.clone()).unwrap();
+    }
+    app.add_transaction(transaction(4, at(2024, 4, 1), vec![output(&first, 0)], &[(EXTERNAL, "1")], "0"))
+        .unwrap();
+    Book { app, first, second, sale }
+}
+
+fn open_basis(app: &BitcoinAccountingApp) -> Decimal {
+    app.open_lots().unwrap().iter().map(|lot| lot.cost_basis).sum()
+}
+
+#[test]
+fn reversal_restores_spent_outputs_and_restates_later_relief() {
+    let Book { mut app, second, sale, .. } = book();
+    // 0.5 of the first lot on Mar 1, then its other half and 0.5 of the second on Apr 1
+    assert_eq!(balance(&app, "4100", at(2024, 4, 1)), dec!(-12500));
+
+    let reversal = app.reverse_transaction(&sale.txid, at(2024, 5, 1), "auditor", "booked twice").unwrap();
+    assert_eq!(
+        reversal.reason,
+        ReversalReason::Corrected {
+            by: "auditor".to_string(),
+            reason: "booked twice".to_string(),
+        }
+    );
+    assert_eq!(reversal.reversed_entries.len(), reversal.entry_ids.len());
+    assert!(app
+        .ledger()
+        .entries()
+        .iter()
+        .filter(|entry| reversal.entry_ids.contains(&entry.id))
+        .all(|entry| entry.kind == EntryKind::Reversal && entry.date == at(2024, 5, 1)));
+    assert_eq!(app.reversals().len(), 1);
+    assert!(matches!(app.transaction_status(&sale.txid), Some(TransactionStatus::Reversed(_))));
+
+    assert!(app.get_utxo(&output(&second, 0).outpoint()).is_some());
+    assert!(app.get_utxo(&output(&sale, 1).outpoint()).is_none());
+
+    // The Apr 1 sale now relieves the whole first lot, 2500 less than before
+    let restatement = app.ledger().entries().last().unwrap();
+    assert_eq!((restatement.date, restatement.total_debits()), (at(2024, 5, 1), dec!(2500)));
+    // The book ties out to lots replayed as if the sale had never been booked
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let after = at(2024, 5, 1);
+    assert_eq!(balance(&app, "1500", after), dec!(35000));
+    assert_eq!(balance(&app, "1500", after), open_basis(&app));
+    assert_eq!(balance(&app, "4100", after), dec!(-10000));
+    assert_eq!(-balance(&app, "4100", after), app.calculate_realized_gains_losses(&year).unwrap().total());
+    assert_eq!(balance(&app, "1000", after), dec!(40000));
+    // History is kept: the sale's original gain still stands on its own date
+    assert_eq!(balance(&app, "4100", at(2024, 3, 1)), dec!(-5000));
+}
+
+#[test]
+fn spent_outputs_must_be_reversed_first() {
+    let Book { mut app, first, second, sale } = book();
+    let entries = app.ledger().entries().len();
+    let err = app.reverse_transaction(&second.txid, at(2024, 5, 1), "auditor", "duplicate").unwrap_err();
+    assert!(
+        matches!(err, AccountingError::SpentByRecorded { ref spenders, .. } if *spenders == vec![sale.txid.clone()]),
+        "{}",
+        err
+    );
+    assert!(matches!(
+        app.reverse_transaction(&first.txid, at(2024, 5, 1), "auditor", "duplicate"),
+        Err(AccountingError::SpentByRecorded { .. })
+    ));
+    assert!(matches!(
+        app.reverse_transaction(&txid(9), at(2024, 5, 1), "auditor", "duplicate"),
+        Err(AccountingError::UnknownTransaction(_))
+    ));
+    assert_eq!(app.ledger().entries().len(), entries);
+    assert!(app.reversals().is_empty());
+
+    app.reverse_transaction(&sale.txid, at(2024, 5, 1), "auditor", "duplicate").unwrap();
+    app.reverse_transaction(&second.txid, at(2024, 5, 1), "auditor", "duplicate").unwrap();
+    assert_eq!(app.reversals().len(), 2);
+    assert_eq!(balance(&app, "4000", at(2024, 5, 1)), dec!(-30000));
+}
+
+#[test]
+fn reversals_post_in_an_open_period() {
+    let Book { mut app, sale, .. } = book();
+    app.close_period(FiscalPeriod::new("Q1", at(2024, 1, 1), at(2024, 3, 31))).unwrap();
+    let err = app.reverse_transaction(&sale.txid, at(2024, 3, 15), "auditor", "duplicate").unwrap_err();
+    assert!(matches!(err, AccountingError::PeriodLocked { ref period, .. } if period == "Q1"), "{}", err);
+    assert!(app.reversals().is_empty());
+
+    // The sale itself may be in the closed period, which is left as reported
+    let closed: Vec<Decimal> = ["1500", "3000"].iter().map(|code| balance(&app, code, at(2024, 3, 31))).collect();
+    let reversal = app.reverse_transaction(&sale.txid, at(2024, 4, 15), "auditor", "duplicate").unwrap();
+    assert!(app
+        .ledger()
+        .entries()
+        .iter()
+        .filter(|entry| reversal.entry_ids.contains(&entry.id))
+        .all(|entry| entry.date == at(2024, 4, 15)));
+    let unchanged: Vec<Decimal> = ["1500", "3000"].iter().map(|code| balance(&app, code, at(2024, 3, 31))).collect();
+    assert_eq!(unchanged, closed);
+}
+
+#[test]
+fn reversal_leaves_earlier_periods_as_reported() {
+    let mut app = app(&[(at(2024, 3, 1), dec!(30000))]);
+    let received = receipt(1, at(2024, 3, 1), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+    let (fy2023, fy2024) = (
+        FiscalPeriod::new("FY2023", at(2023, 1, 1), at(2023, 12, 31)),
+        FiscalPeriod::new("FY2024", at(2024, 1, 1), at(2024, 12, 31)),
+    );
+    let reported = |app: &BitcoinAccountingApp| {
+        let roll_forward = app.roll_forward(&fy2024).unwrap();
+        (
+            app.income_statement(&fy2024, &fy2023).unwrap().net_income.current,
+            app.balance_sheet(at(2024, 12, 31), at(2023, 12, 31)).unwrap().total_assets.current,
+            (roll_forward.acquisitions, roll_forward.closing_quantity, roll_forward.closing_fair_value),
+        )
+    };
+    let before = reported(&app);
+    assert_eq!(before, (dec!(30000), dec!(30000), (dec!(30000), btc("1"), dec!(30000))));
+
+    app.reverse_transaction(&received.txid, at(2025, 2, 1), "auditor", "not ours").unwrap();
+    assert_eq!(reported(&app), before);
+    assert_eq!(app.holdings_at(at(2024, 12, 31)), btc("1"));
+    assert!(app.open_lots().unwrap().is_empty());
+
+    // The next year carries the correction out of the opening balance
+    let fy2025 = FiscalPeriod::new("FY2025", at(2025, 1, 1), at(2025, 12, 31));
+    let roll_forward = app.roll_forward(&fy2025).unwrap();
+    assert_eq!((roll_forward.opening_fair_value, roll_forward.corrections), (dec!(30000), dec!(30000)));
+    assert_eq!(roll_forward.closing_fair_value, dec!(0));
+    assert_eq!(roll_forward.unreconciled(), dec!(0));
+}
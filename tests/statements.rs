@@ -0,0 +1,72 @@
+mod common;
+
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::statements::StatementLine;
+use bitcoin_accounting::BitcoinAccountingApp;
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Receives 1 BTC in January, then pays 0.4 of it out in May with a 0.0001 fee.
+fn book() -> BitcoinAccountingApp {
+    let mut app = app(&[
+        (at(2024, 1, 1), dec!(30000)),
+        (at(2024, 3, 31), dec!(35000)),
+        (at(2024, 5, 1), dec!(40000)),
+        (at(2024, 6, 30), dec!(45000)),
+    ]);
+    let received = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+    app.add_transaction(transaction(2, at(2024, 5, 1), vec![output(&received, 0)], &[(EXTERNAL, "0.4"), (OWNED[1], "0.5999")], "0.0001"))
+        .unwrap();
+    app
+}
+
+fn columns(lines: &[StatementLine], code: &str) -> (Decimal, Decimal) {
+    let line = lines.iter().find(|line| line.account.as_deref() == Some(code)).unwrap();
+    (line.current, line.prior)
+}
+
+#[test]
+fn balance_sheet_carries_digital_assets_at_fair_value() {
+    let app = book();
+    let sheet = app.balance_sheet(at(2024, 6, 30), at(2024, 3, 31)).unwrap();
+    assert_eq!(columns(&sheet.assets, "1500"), (dec!(26995.5), dec!(35000)));
+    assert_eq!(columns(&sheet.assets, "1000"), (dec!(16000), dec!(0)));
+    assert_eq!((sheet.total_assets.current, sheet.total_assets.prior), (dec!(42995.5), dec!(35000)));
+    assert_eq!(sheet.total_liabilities_and_equity.current, sheet.total_assets.current);
+    assert_eq!(sheet.total_liabilities_and_equity.prior, sheet.total_assets.prior);
+}
+
+#[test]
+fn income_statement_compares_with_the_prior_period() {
+    let app = book();
+    let q1 = FiscalPeriod::new("Q1", at(2024, 1, 1), at(2024, 3, 31));
+    let q2 = FiscalPeriod::new("Q2", at(2024, 4, 1), at(2024, 6, 30));
+    let statement = app.income_statement(&q2, &q1).unwrap();
+    assert_eq!(columns(&statement.revenue, "4000"), (dec!(0), dec!(30000)));
+    // 4000 on the payment and 1 on the coins spent on the fee
+    assert_eq!(columns(&statement.gains, "4100"), (dec!(4001), dec!(0)));
+    assert_eq!(columns(&statement.gains, "4200"), (dec!(3998.5), dec!(5000)));
+    assert_eq!(columns(&statement.expenses, "6000"), (dec!(4), dec!(0)));
+    assert_eq!((statement.net_income.current, statement.net_income.prior), (dec!(7995.5), dec!(35000)));
+
+    // Net income is the change in equity, as nothing else moves it
+    let sheet = app.balance_sheet(at(2024, 6, 30), at(2024, 3, 31)).unwrap();
+    let equity = sheet.total_liabilities_and_equity;
+    assert_eq!(equity.current - equity.prior, statement.net_income.current);
+}
+
+#[test]
+fn closing_moves_income_to_retained_earnings_without_changing_the_statements() {
+    let mut app = book();
+    let q1 = FiscalPeriod::new("Q1", at(2024, 1, 1), at(2024, 3, 31));
+    let before = app.income_statement(&q1, &q1).unwrap().net_income.current;
+    app.close_period(q1.clone()).unwrap();
+    assert_eq!(app.income_statement(&q1, &q1).unwrap().net_income.current, before);
+
+    let sheet = app.balance_sheet(at(2024, 3, 31), at(2023, 12, 31)).unwrap();
+    assert_eq!(columns(&sheet.equity, "3000").0, dec!(35000));
+    let unclosed = sheet.equity.iter().find(|line| line.account.is_none()).unwrap();
+    assert_eq!(unclosed.current, dec!(0));
+}
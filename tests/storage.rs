@@ -0,0 +1,101 @@
+mod common;
+
+use std::path::PathBuf;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::fees::FeePolicy;
+use bitcoin_accounting::lots::CostBasisMethod;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::rates::RatePolicy;
+use bitcoin_accounting::storage::{SqliteStorage, StorageError};
+use bitcoin_accounting::BitcoinAccountingApp;
+use common::*;
+use rust_decimal_macros::dec;
+
+/// A fresh path for a book file, removed when dropped.
+struct BookFile(PathBuf);
+
+impl BookFile {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("bitcoin-accounting-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        BookFile(path)
+    }
+
+    fn open(&self) -> BitcoinAccountingApp {
+        BitcoinAccountingApp::open(Box::new(SqliteStorage::open(&self.0).unwrap())).unwrap()
+    }
+}
+
+impl Drop for BookFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn snapshot(app: &BitcoinAccountingApp) -> serde_json::Value {
+    let mut utxos: Vec<_> = app.utxo_set().values().collect();
+    utxos.sort_by_key(|utxo| utxo.outpoint());
+    serde_json::json!({
+        "transactions": app.transactions(),
+        "entries": app.ledger().entries(),
+        "utxos": utxos,
+        "lots": app.open_lots().unwrap(),
+        "opening": app.opening_balances(),
+        "remeasurements": app.remeasurements(),
+        "closes": app.period_closes(),
+        "wallets": app.wallets(),
+    })
+}
+
+#[test]
+fn reopened_book_continues_where_it_left_off() {
+    let file = BookFile::new("reopen");
+    let mut app = file.open();
+    for address in OWNED {
+        app.register_address("treasury", address).unwrap();
+    }
+    app.set_rate_policy(RatePolicy::Interpolate, None).unwrap();
+    app.set_cost_basis_method(CostBasisMethod::Hifo).unwrap();
+    app.set_fee_policy(FeePolicy::Capitalize).unwrap();
+    app.add_exchange_rate(at(2023, 12, 1), dec!(25000)).unwrap();
+    app.add_exchange_rate(at(2024, 1, 1), dec!(30000)).unwrap();
+    app.add_exchange_rate(at(2024, 3, 1), dec!(40000)).unwrap();
+    app.add_opening_balance(utxo(&txid(9), 0, "0.5", OWNED[2], at(2023, 12, 1)), None).unwrap();
+    let mut bought = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    bought.acquisition_fee = dec!(25);
+    app.add_transaction(bought.clone()).unwrap();
+    app.add_transaction(transaction(2, at(2024, 2, 1), vec![output(&bought, 0)], &[(EXTERNAL, "0.3"), (OWNED[1], "0.6999")], "0.0001"))
+        .unwrap();
+    app.close_period(FiscalPeriod::new("2024-01", at(2024, 1, 1), at(2024, 1, 31))).unwrap();
+    let before = snapshot(&app);
+    drop(app);
+
+    let mut app = file.open();
+    assert_eq!(snapshot(&app), before);
+    assert_eq!(app.cost_basis_method(), CostBasisMethod::Hifo);
+    assert_eq!(app.fee_policy(), FeePolicy::Capitalize);
+    assert_eq!(app.rate_at(at(2024, 1, 31)), Some(dec!(35000)));
+    assert!(matches!(
+        app.add_transaction(receipt(3, at(2024, 1, 15), OWNED[0], "1")),
+        Err(AccountingError::PeriodLocked { .. })
+    ));
+
+    app.add_transaction(receipt(3, at(2024, 3, 1), OWNED[0], "1")).unwrap();
+    let after = snapshot(&app);
+    drop(app);
+    assert_eq!(snapshot(&file.open()), after);
+}
+
+#[test]
+fn newer_schema_is_refused() {
+    let file = BookFile::new("schema");
+    let storage = SqliteStorage::open(&file.0).unwrap();
+    let version = storage.schema_version().unwrap();
+    drop(storage);
+
+    let conn = rusqlite::Connection::open(&file.0).unwrap();
+    conn.pragma_update(None, "user_version", version + 1).unwrap();
+    drop(conn);
+    assert!(matches!(SqliteStorage::open(&file.0), Err(StorageError::Corrupt(_))));
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use bitcoin_accounting::calendar::{FiscalYearStart, ReportingCalendar};
+use bitcoin_accounting::lots::HoldingTerm;
+use bitcoin_accounting::period::FiscalPeriod;
+use bitcoin_accounting::tax::acb::AcbEventKind;
+use bitcoin_accounting::tax::section104::MatchRule;
+use chrono::{FixedOffset, TimeZone, Utc};
+use common::*;
+use rust_decimal_macros::dec;
+
+#[test]
+fn form_8949_agrees_with_realized_gains() {
+    let mut app = app(&[(at(2022, 6, 1), dec!(20000)), (at(2024, 1, 1), dec!(30000)), (at(2024, 5, 1), dec!(60000))]);
+    let old = receipt(1, at(2022, 6, 1), OWNED[0], "1");
+    let new = receipt(2, at(2024, 1, 1), OWNED[1], "1");
+    app.add_transaction(old.clone()).unwrap();
+    app.add_transaction(new.clone()).unwrap();
+    app.add_transaction(transaction(3, at(2024, 5, 1), vec![output(&old, 0), output(&new, 0)], &[(EXTERNAL, "1.5"), (OWNED[2], "0.5")], "0"))
+        .unwrap();
+
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let form = app.form_8949(&year).unwrap();
+    let gains = app.calculate_realized_gains_losses(&year).unwrap();
+    assert_eq!(form.long_term_totals().gain_loss, gains.long_term);
+    assert_eq!(form.short_term_totals().gain_loss, gains.short_term);
+    assert_eq!((gains.long_term, gains.short_term), (dec!(40000), dec!(15000)));
+    assert_eq!(form.short_term_totals().proceeds + form.long_term_totals().proceeds, dec!(90000));
+}
+
+#[test]
+fn holding_period_runs_from_the_acquiring_transaction_in_local_days() {
+    let mut app = app(&[(at(2022, 12, 1), dec!(20000))]);
+    app.set_reporting_calendar(ReportingCalendar::new(FixedOffset::west_opt(5 * 3600).unwrap(), FiscalYearStart::JANUARY_1))
+        .unwrap();
+    // Received on 31 December 2022 local time, moved between wallets, then sold on
+    // 1 January 2024 local time: more than a year, though not in UTC dates
+    let received = receipt(1, Utc.with_ymd_and_hms(2023, 1, 1, 3, 0, 0).unwrap(), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+    let moved = transaction(2, at(2023, 6, 1), vec![output(&received, 0)], &[(OWNED[1], "1")], "0");
+    app.add_transaction(moved.clone()).unwrap();
+    let sold_at = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    app.add_transaction(transaction(3, sold_at, vec![output(&moved, 0)], &[(EXTERNAL, "1")], "0")).unwrap();
+
+    let year = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let realized = app.realized_gains_by_lot(&year).unwrap();
+    assert_eq!(realized[0].acquired_at, received.timestamp);
+    assert_eq!(realized[0].term, HoldingTerm::LongTerm);
+    let gains = app.calculate_realized_gains_losses(&year).unwrap();
+    assert_eq!((gains.short_term, gains.long_term), (dec!(0), dec!(0)));
+}
+
+#[test]
+fn section_104_matches_same_day_then_bed_and_breakfast_then_the_pool() {
+    let mut app = app(&[
+        (at(2024, 1, 1), dec!(10000)),
+        (at(2024, 3, 1), dec!(20000)),
+        (at(2024, 3, 10), dec!(15000)),
+        (at(2024, 4, 1), dec!(30000)),
+    ]);
+    let pooled = receipt(1, at(2024, 1, 1), OWNED[0], "2");
+    app.add_transaction(pooled.clone()).unwrap();
+    app.add_transaction(transaction(2, at(2024, 3, 1), vec![output(&pooled, 0)], &[(EXTERNAL, "1"), (OWNED[1], "1")], "0"))
+        .unwrap();
+    app.add_transaction(receipt(3, at(2024, 3, 1), OWNED[2], "0.25")).unwrap();
+    app.add_transaction(receipt(4, at(2024, 3, 10), OWNED[2], "0.5")).unwrap();
+    // 31 days after the disposal, too late to be matched with it
+    app.add_transaction(receipt(5, at(2024, 4, 1), OWNED[2], "0.1")).unwrap();
+
+    let period = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 4, 30));
+    let computation = app.uk_cgt_computation(&period).unwrap();
+    assert_eq!(computation.disposals.len(), 1);
+    let disposal = &computation.disposals[0];
+    assert_eq!(disposal.txids, vec![txid(2)]);
+    let matches: Vec<_> = disposal
+        .matches
+        .iter()
+        .map(|matched| (matched.rule, matched.quantity, matched.proceeds, matched.allowable_cost))
+        .collect();
+    assert_eq!(
+        matches,
+        vec![
+            (MatchRule::SameDay, btc("0.25"), dec!(5000), dec!(5000)),
+            (MatchRule::BedAndBreakfast { acquired_on: at(2024, 3, 10).date_naive() }, btc("0.5"), dec!(10000), dec!(7500)),
+            (MatchRule::Section104, btc("0.25"), dec!(5000), dec!(2500)),
+        ]
+    );
+    assert_eq!((disposal.allowable_cost, disposal.gain_loss), (dec!(15000), dec!(5000)));
+    assert_eq!((computation.gains(), computation.losses()), (dec!(5000), dec!(0)));
+    // The matched acquisitions never enter the pool
+    assert_eq!((computation.pool.quantity, computation.pool.cost), (btc("1.85"), dec!(20500)));
+}
+
+#[test]
+fn section_104_days_follow_the_reporting_utc_offset() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(10000)), (at(2024, 3, 1), dec!(20000))]);
+    app.set_reporting_calendar(ReportingCalendar::new(FixedOffset::east_opt(3600).unwrap(), FiscalYearStart::JANUARY_1))
+        .unwrap();
+    let pooled = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(pooled.clone()).unwrap();
+    // 23:30 UTC on 29 February is 1 March at UTC+01:00, the day of the later receipt
+    let sold_at = Utc.with_ymd_and_hms(2024, 2, 29, 23, 30, 0).unwrap();
+    app.add_transaction(transaction(2, sold_at, vec![output(&pooled, 0)], &[(EXTERNAL, "1")], "0")).unwrap();
+    app.add_transaction(receipt(3, at(2024, 3, 1), OWNED[1], "1")).unwrap();
+
+    let period = FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31));
+    let disposal = &app.uk_cgt_computation(&period).unwrap().disposals[0];
+    assert_eq!(disposal.date, at(2024, 3, 1).date_naive());
+    assert_eq!(disposal.matches[0].rule, MatchRule::SameDay);
+}
+
+#[test]
+fn acb_adds_a_superficial_loss_back_to_the_pool() {
+    let mut app = app(&[
+        (at(2024, 1, 1), dec!(50000)),
+        (at(2024, 3, 1), dec!(20000)),
+        (at(2024, 3, 15), dec!(25000)),
+        (at(2024, 6, 1), dec!(30000)),
+    ]);
+    let bought = receipt(1, at(2024, 1, 1), OWNED[0], "2");
+    app.add_transaction(bought.clone()).unwrap();
+    let sold = transaction(2, at(2024, 3, 1), vec![output(&bought, 0)], &[(EXTERNAL, "1"), (OWNED[1], "1")], "0");
+    app.add_transaction(sold.clone()).unwrap();
+    // Half the units sold at a loss are bought back within 30 days
+    app.add_transaction(receipt(3, at(2024, 3, 15), OWNED[2], "0.5")).unwrap();
+    app.add_transaction(transaction(4, at(2024, 6, 1), vec![output(&sold, 1)], &[(EXTERNAL, "0.5"), (OWNED[0], "0.5")], "0"))
+        .unwrap();
+
+    let computation = app.acb_computation(&FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31))).unwrap();
+    let dispositions: Vec<_> = computation
+        .entries
+        .iter()
+        .filter(|entry| entry.kind == AcbEventKind::Disposition)
+        .collect();
+    assert_eq!(dispositions.len(), 2);
+    let first = dispositions[0];
+    assert_eq!((first.amount, first.acb_disposed), (dec!(20000), dec!(50000)));
+    assert_eq!((first.superficial_loss, first.gain_loss), (dec!(15000), dec!(-15000)));
+    assert_eq!((first.units_held, first.total_acb), (btc("1"), dec!(65000)));
+
+    // Nothing is bought around the second sale, so its whole loss is allowed
+    let second = dispositions[1];
+    assert_eq!(second.acb_disposed, dec!(25833.33333333));
+    assert_eq!(second.superficial_loss, dec!(0));
+    assert_eq!(second.gain_loss, dec!(-10833.33333333));
+
+    assert_eq!(computation.gain_loss(), dec!(-25833.33333333));
+    assert_eq!((computation.closing_units, computation.closing_acb), (btc("1"), dec!(51666.66666667)));
+}
+
+#[test]
+fn acb_opening_pool_carries_earlier_activity() {
+    let mut app = app(&[(at(2023, 1, 1), dec!(20000)), (at(2023, 6, 1), dec!(40000))]);
+    app.add_transaction(receipt(1, at(2023, 1, 1), OWNED[0], "1")).unwrap();
+    app.add_transaction(receipt(2, at(2023, 6, 1), OWNED[1], "1")).unwrap();
+
+    let computation = app.acb_computation(&FiscalPeriod::new("2024", at(2024, 1, 1), at(2024, 12, 31))).unwrap();
+    assert!(computation.entries.is_empty());
+    assert_eq!((computation.opening_units, computation.opening_acb), (btc("2"), dec!(60000)));
+    assert_eq!((computation.closing_units, computation.closing_acb), (btc("2"), dec!(60000)));
+}
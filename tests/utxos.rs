@@ -0,0 +1,25 @@
+mod common;
+
+use common::*;
+use rust_decimal_macros::dec;
+
+#[test]
+fn outputs_are_keyed_by_their_own_transaction() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000))]);
+    let received = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    let mut mislabelled = received.clone();
+    // Whatever txid the caller gave an output, it belongs to the transaction creating it
+    mislabelled.outputs[0].txid = txid(7);
+    app.add_transaction(mislabelled).unwrap();
+    let held = output(&received, 0).outpoint();
+    assert_eq!(app.get_utxo(&held).map(|utxo| utxo.txid.clone()), Some(txid(1)));
+    assert!(app.get_utxo(&utxo(&txid(7), 0, "1", OWNED[0], at(2024, 1, 1)).outpoint()).is_none());
+
+    // Spending it removes the spent outpoint, not one keyed by the spender's txid
+    app.add_transaction(transaction(2, at(2024, 1, 2), vec![output(&received, 0)], &[(OWNED[1], "0.6"), (OWNED[1], "0.4")], "0"))
+        .unwrap();
+    assert!(app.get_utxo(&held).is_none());
+    let by_address: Vec<u32> = app.utxos_by_address(OWNED[1]).iter().map(|utxo| utxo.vout).collect();
+    assert_eq!(by_address, vec![0, 1]);
+    assert!(app.utxos_by_address(OWNED[0]).is_empty());
+}
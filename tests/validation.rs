@@ -0,0 +1,94 @@
+mod common;
+
+use bitcoin_accounting::amount::Amount;
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::outpoint::OutPoint;
+use bitcoin_accounting::{BitcoinAccountingApp, Transaction};
+use common::*;
+use rust_decimal_macros::dec;
+
+/// A book holding 1 BTC received in transaction 1.
+fn funded() -> (BitcoinAccountingApp, Transaction) {
+    let mut app = app(&[(at(2024, 1, 1), dec!(40000))]);
+    let funding = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(funding.clone()).unwrap();
+    (app, funding)
+}
+
+/// Posts `transaction`, expecting it to fail without changing the book.
+fn reject(app: &mut BitcoinAccountingApp, transaction: Transaction) -> AccountingError {
+    let state = (app.transactions().len(), app.ledger().entries().len(), app.utxo_set().len());
+    let err = app.add_transaction(transaction).unwrap_err();
+    assert_eq!((app.transactions().len(), app.ledger().entries().len(), app.utxo_set().len()), state);
+    err
+}
+
+#[test]
+fn malformed_and_duplicate_txids_are_rejected() {
+    let (mut app, funding) = funded();
+    let mut malformed = receipt(2, at(2024, 1, 2), OWNED[1], "1");
+    malformed.txid = "not-a-txid".to_string();
+    assert!(matches!(reject(&mut app, malformed), AccountingError::InvalidTxid(txid) if txid == "not-a-txid"));
+    assert!(matches!(reject(&mut app, funding), AccountingError::DuplicateTransaction(duplicate) if duplicate == txid(1)));
+}
+
+#[test]
+fn inputs_must_match_outputs_in_the_book() {
+    let (mut app, funding) = funded();
+
+    let unrecorded = utxo(&txid(9), 0, "1", OWNED[1], at(2023, 12, 1));
+    let spend = transaction(2, at(2024, 1, 2), vec![unrecorded.clone()], &[(EXTERNAL, "1")], "0");
+    assert!(matches!(reject(&mut app, spend), AccountingError::UnknownInput(outpoint) if outpoint == unrecorded.outpoint()));
+
+    let mut inflated = output(&funding, 0);
+    inflated.amount = btc("2");
+    let spend = transaction(2, at(2024, 1, 2), vec![inflated], &[(EXTERNAL, "2")], "0");
+    assert!(matches!(reject(&mut app, spend), AccountingError::UnknownInput(_)));
+
+    let twice = transaction(2, at(2024, 1, 2), vec![output(&funding, 0), output(&funding, 0)], &[(EXTERNAL, "2")], "0");
+    assert!(matches!(reject(&mut app, twice), AccountingError::InputAlreadySpent(_)));
+
+    // Inputs of other parties are taken as given
+    let foreign = utxo(&txid(9), 0, "1", EXTERNAL, at(2023, 12, 1));
+    app.add_transaction(transaction(3, at(2024, 1, 2), vec![foreign], &[(OWNED[1], "1")], "0")).unwrap();
+}
+
+#[test]
+fn outputs_must_have_distinct_vouts() {
+    let (mut app, funding) = funded();
+    let mut spend = transaction(2, at(2024, 1, 2), vec![output(&funding, 0)], &[(EXTERNAL, "0.5"), (OWNED[1], "0.5")], "0");
+    spend.outputs[1].vout = 0;
+    assert!(matches!(
+        reject(&mut app, spend),
+        AccountingError::DuplicateOutput(outpoint) if outpoint == OutPoint::new(&txid(2), 0).unwrap()
+    ));
+}
+
+#[test]
+fn inputs_must_equal_outputs_plus_fee() {
+    let (mut app, funding) = funded();
+    let spend = transaction(2, at(2024, 1, 2), vec![output(&funding, 0)], &[(EXTERNAL, "0.9")], "0.05");
+    assert!(matches!(
+        reject(&mut app, spend),
+        AccountingError::ValueImbalance { inputs, outputs, fee, .. }
+            if (inputs, outputs, fee) == (btc("1"), btc("0.9"), btc("0.05"))
+    ));
+    app.add_transaction(transaction(2, at(2024, 1, 2), vec![output(&funding, 0)], &[(EXTERNAL, "0.9")], "0.1"))
+        .unwrap();
+}
+
+#[test]
+fn amounts_above_the_supply_cap_are_rejected() {
+    let (mut app, _) = funded();
+    let above = Amount::from_sat(Amount::MAX_MONEY.to_sat() + 1);
+    let mut huge = receipt(2, at(2024, 1, 2), OWNED[1], "1");
+    huge.outputs[0].amount = above;
+    assert!(matches!(reject(&mut app, huge), AccountingError::AmountOutOfRange { amount, .. } if amount == above));
+
+    // Each output is in range, but their total is not
+    let mut split = transaction(2, at(2024, 1, 2), Vec::new(), &[(OWNED[1], "1"), (OWNED[2], "1")], "0");
+    for output in &mut split.outputs {
+        output.amount = Amount::MAX_MONEY;
+    }
+    assert!(matches!(reject(&mut app, split), AccountingError::AmountOutOfRange { .. }));
+}
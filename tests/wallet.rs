@@ -0,0 +1,50 @@
+mod common;
+
+use bitcoin_accounting::error::AccountingError;
+use bitcoin_accounting::ledger::AccountRole;
+use common::*;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[test]
+fn only_value_crossing_the_entity_is_booked() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000)), (at(2024, 2, 1), dec!(40000))]);
+    app.register_address("cold", "cold-1").unwrap();
+    let received = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+
+    // A move to cold storage with change back to the treasury realizes nothing
+    let transfer = transaction(2, at(2024, 2, 1), vec![output(&received, 0)], &[("cold-1", "0.6"), (OWNED[1], "0.4")], "0");
+    app.add_transaction(transfer.clone()).unwrap();
+    let chart = app.ledger().chart();
+    let as_of = at(2024, 12, 31);
+    assert_eq!(balance(&app, chart.code_for(AccountRole::Revenue), as_of), dec!(-30000));
+    assert_eq!(balance(&app, chart.code_for(AccountRole::RealizedGainLoss), as_of), Decimal::ZERO);
+    assert_eq!(balance(&app, chart.code_for(AccountRole::DigitalAssets), as_of), dec!(30000));
+
+    // Paying out from cold storage is a disposal of the coins that left
+    app.add_transaction(transaction(3, at(2024, 2, 1), vec![output(&transfer, 0)], &[(EXTERNAL, "0.6")], "0"))
+        .unwrap();
+    let chart = app.ledger().chart();
+    assert_eq!(balance(&app, chart.code_for(AccountRole::RealizedGainLoss), as_of), dec!(-6000));
+    assert_eq!(balance(&app, chart.code_for(AccountRole::DigitalAssets), as_of), dec!(12000));
+}
+
+#[test]
+fn addresses_seen_as_another_partys_cannot_be_registered() {
+    let mut app = app(&[(at(2024, 1, 1), dec!(30000))]);
+    let received = receipt(1, at(2024, 1, 1), OWNED[0], "1");
+    app.add_transaction(received.clone()).unwrap();
+    app.add_transaction(transaction(2, at(2024, 1, 1), vec![output(&received, 0)], &[(EXTERNAL, "1")], "0"))
+        .unwrap();
+
+    let err = app.register_address("treasury", EXTERNAL).unwrap_err();
+    assert!(matches!(err, AccountingError::AddressInHistory(ref address) if address == EXTERNAL), "{}", err);
+    assert!(!app.wallets().is_owned(EXTERNAL));
+    assert!(app.utxo_set().is_empty());
+    assert_eq!(app.holdings_at(at(2024, 12, 31)).to_sat(), 0);
+
+    // Owned addresses can move between wallets, and unseen ones can be added
+    app.register_address("cold", OWNED[0]).unwrap();
+    app.register_address("cold", "cold-1").unwrap();
+}